@@ -0,0 +1,104 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// Benchmarks comparing the allocating `to_cef` against the
+/// streaming `write_cef` and `write_cef_io` encoders.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_cef::{
+    CefCollector, CefExtensions, CefExtensionsResult, CefHeaderDeviceEventClassID,
+    CefHeaderDeviceProduct, CefHeaderDeviceVendor, CefHeaderDeviceVersion, CefHeaderName,
+    CefHeaderSeverity, CefHeaderVersion, CefResult, ToCef,
+};
+use std::io;
+
+struct LoginEvent {
+    user: String,
+    source_address: String,
+    source_port: u16,
+    attempts: u32,
+    message: String,
+}
+
+impl ToCef for LoginEvent {}
+
+impl CefHeaderVersion for LoginEvent {
+    fn cef_header_version(&self) -> CefResult {
+        Ok("0".to_owned())
+    }
+}
+
+impl CefHeaderDeviceVendor for LoginEvent {
+    fn cef_header_device_vendor(&self) -> CefResult {
+        Ok("polyverse".to_owned())
+    }
+}
+
+impl CefHeaderDeviceProduct for LoginEvent {
+    fn cef_header_device_product(&self) -> CefResult {
+        Ok("zerotect".to_owned())
+    }
+}
+
+impl CefHeaderDeviceVersion for LoginEvent {
+    fn cef_header_device_version(&self) -> CefResult {
+        Ok("V1".to_owned())
+    }
+}
+
+impl CefHeaderDeviceEventClassID for LoginEvent {
+    fn cef_header_device_event_class_id(&self) -> CefResult {
+        Ok("LoginFailed".to_owned())
+    }
+}
+
+impl CefHeaderName for LoginEvent {
+    fn cef_header_name(&self) -> CefResult {
+        Ok("Failed login attempt".to_owned())
+    }
+}
+
+impl CefHeaderSeverity for LoginEvent {
+    fn cef_header_severity(&self) -> CefResult {
+        Ok("7".to_owned())
+    }
+}
+
+impl CefExtensions for LoginEvent {
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        collector.insert("cnt", &self.attempts.to_string())?;
+        collector.insert("msg", &self.message)?;
+        collector.insert("src", &self.source_address)?;
+        collector.insert_fmt("spt", format_args!("{}", self.source_port))?;
+        collector.insert("suser", &self.user)
+    }
+}
+
+fn bench_encoders(c: &mut Criterion) {
+    let event = LoginEvent {
+        user: "admin".to_owned(),
+        source_address: "10.1.2.3".to_owned(),
+        source_port: 52311,
+        attempts: 3,
+        message: "password mismatch for user=admin on C:\\Users\\admin".to_owned(),
+    };
+
+    let mut group = c.benchmark_group("encode");
+
+    group.bench_function("to_cef", |b| b.iter(|| black_box(&event).to_cef().unwrap()));
+
+    let mut output = String::with_capacity(512);
+    group.bench_function("write_cef", |b| {
+        b.iter(|| {
+            output.clear();
+            black_box(&event).write_cef(&mut output).unwrap();
+        })
+    });
+
+    group.bench_function("write_cef_io", |b| {
+        b.iter(|| black_box(&event).write_cef_io(&mut io::sink()).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encoders);
+criterion_main!(benches);
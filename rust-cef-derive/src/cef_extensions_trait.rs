@@ -2,15 +2,17 @@
 ///
 /// This module provides functions to implement the CefExtensions trait
 use crate::helpers::{
-    is_valid_item_type, parse_attrs_to_name_value, ParseAttrResult, CEF_ATTRIBUTE_APPLICATION,
+    is_option_type, is_valid_item_type, nest_error, parse_attrs_to_name_value,
+    parse_cef_ext_custom, parse_cef_ext_field, ParseAttrResult, CEF_ATTRIBUTE_APPLICATION,
+    CEF_EXT_CUSTOM_USAGE,
 };
 use crate::proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
 use std::convert::From;
 use syn::{
-    parse_macro_input, spanned::Spanned, Attribute, Data, DataEnum, DataStruct, DeriveInput,
-    Error as SynError, Fields, Ident, Index, Lit, Meta, NestedMeta, Type, Variant,
+    ext::IdentExt, parse_macro_input, spanned::Spanned, Attribute, Data, DataEnum, DataStruct,
+    DeriveInput, Error as SynError, Fields, Ident, Index, Lit, Meta, Type, Variant,
 };
 
 const CEF_EXT_VALUES_APPLICABLE: &str =
@@ -18,11 +20,16 @@ const CEF_EXT_VALUES_APPLICABLE: &str =
 const CEF_EXT_VALUES_USAGE: &str = "'cef_ext_values' macro expects extension values to be listed in the following syntax: #[cef_ext_values(extensionKey1 = \"value1\", extensionKey2 = \"value2\", ...)] ";
 
 const CEF_EXT_GOBBLE_USAGE: &str = "'cef_ext_gobble' macro must supply no arguments and appear by itself to inform CefExtensions derivation to gobble any keys generated by that field type's CefExtensions implementation. #[cef_ext_gobble]";
-const CEF_EXT_FIELD_USAGE: &str = "'cef_ext_field' macro may optionally supply one argument which is the custom extension key name to use. If no arguments are supplied, the field's name is used. #[cef_ext_field(rename)]";
+const CEF_EXT_CUSTOM_KEYS_USAGE: &str = "'cef_ext_custom_keys' macro must supply no arguments. It may apply on a Struct, Enum, Enum::Variant or field to allow extension keys that look like misspelled standard keys. #[cef_ext_custom_keys]";
+const CEF_EXT_FIELD_USAGE: &str = "'cef_ext_field' macro may optionally supply the custom extension key name to use, followed by 'redact', 'pseudonymize' or 'mask = \"lastN\"' to hide the value. If no key is supplied, the field's name is used. #[cef_ext_field(rename, redact)]";
 
 enum FieldValueType {
     GobbleTrait,
     DisplayTrait,
+    // Display, then redacted or masked
+    RedactedDisplay(rust_cef::redact::Redaction),
+    // label and kind of the custom slot
+    CustomSlot(String, rust_cef::CefCustomKind),
 }
 
 enum PrefixSelf {
@@ -67,7 +74,7 @@ pub fn implement_extensions_trait(item_tokens: TokenStream) -> TokenStream {
 
     let trait_impl = quote! {
         impl #item_impl_generics rust_cef::CefExtensions for #item_name #item_ty_generics #item_where_clause {
-            fn cef_extensions(&self, collector: &mut std::collections::HashMap<String, String>) -> rust_cef::CefExtensionsResult {
+            fn cef_extensions(&self, collector: &mut dyn rust_cef::CefCollector) -> rust_cef::CefExtensionsResult {
                 #collections
 
                 // let collections return errors if they wish
@@ -92,12 +99,17 @@ pub fn implement_extensions_trait(item_tokens: TokenStream) -> TokenStream {
 /// NOTE: Union types are not supported.
 ///
 fn extensions_from_child_item(item: &DeriveInput) -> TokenStream2 {
-    let fixed_values = top_level_cef_ext_values(&item.attrs);
+    let custom_keys = match allows_custom_keys(&item.attrs) {
+        Ok(custom_keys) => custom_keys,
+        Err(ts) => return ts,
+    };
+
+    let fixed_values = top_level_cef_ext_values(&item.attrs, custom_keys);
 
     // Is the Item a struct or enum?
     let field_values = match &item.data {
-        Data::Struct(s) => extensions_from_child_struct(s),
-        Data::Enum(e) => extensions_from_child_enum(e),
+        Data::Struct(s) => extensions_from_child_struct(s, custom_keys),
+        Data::Enum(e) => extensions_from_child_enum(e, custom_keys),
         _ => {
             return SynError::new(Span::call_site(), CEF_ATTRIBUTE_APPLICATION.to_owned())
                 .to_compile_error()
@@ -196,7 +208,11 @@ fn extensions_from_child_item(item: &DeriveInput) -> TokenStream2 {
 /// }
 /// ```
 ///
-fn extensions_from_child_struct(s: &DataStruct) -> TokenStream2 {
+fn extensions_from_child_struct(s: &DataStruct, custom_keys: bool) -> TokenStream2 {
+    if let Err(ts) = check_custom_slot_counts(&s.fields) {
+        return ts;
+    }
+
     // Map all possible fields into expressions for adding to extensions
     let field_extension_exprs_result: OptionalCollectedCompileResult = s
         .fields
@@ -214,6 +230,12 @@ fn extensions_from_child_struct(s: &DataStruct) -> TokenStream2 {
                 ),
             };
 
+            // errors from this field are reported under its name (or index)
+            let error_path = match &field.ident {
+                Some(ident) => vec![ident.to_string()],
+                None => vec![index.to_string()],
+            };
+
             // look for field attributes
             field_extraction(
                 &field.attrs,
@@ -221,6 +243,8 @@ fn extensions_from_child_struct(s: &DataStruct) -> TokenStream2 {
                 field_anme_from_id,
                 &field.ty,
                 &PrefixSelf::Yes,
+                &error_path,
+                custom_keys,
                 field.span(),
             )
         })
@@ -357,11 +381,11 @@ fn extensions_from_child_struct(s: &DataStruct) -> TokenStream2 {
 /// an error is thrown, and if multiple values are found an error is
 /// thrown to indicate conflict and ambiguity.
 ///
-fn extensions_from_child_enum(e: &DataEnum) -> TokenStream2 {
+fn extensions_from_child_enum(e: &DataEnum, custom_keys: bool) -> TokenStream2 {
     let match_branches_result: OptionalCollectedCompileResult = e
         .variants
         .iter()
-        .map(destructure_and_match_variant)
+        .map(|variant| destructure_and_match_variant(variant, custom_keys))
         .collect();
 
     let match_branches: Vec<TokenStream2> = match match_branches_result {
@@ -396,7 +420,7 @@ fn extensions_from_child_enum(e: &DataEnum) -> TokenStream2 {
 ///     // gobble cef extensions from address field
 ///     rust_cef_::CefExtensions::cef_extensions(&_address, &mut collector);
 ///     // add cef extensions from name field
-///     collector.insert("newname", format!("{}", _name));
+///     collector.insert_fmt("newname", format_args!("{}", _name))?;
 /// }
 ///  ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
 ///
@@ -404,7 +428,7 @@ fn extensions_from_child_enum(e: &DataEnum) -> TokenStream2 {
 ///     // gobble cef extensions from _index0 unnamed field
 ///     rust_cef_::CefExtensions::cef_extensions(&_index0, &mut collector);
 ///     // add cef extensions from index1 unnamed field
-///     collector.insert("newname", format!("{}", _index1));
+///     collector.insert_fmt("newname", format_args!("{}", _index1))?;
 /// }
 ///  ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
 ///```
@@ -414,7 +438,7 @@ fn extensions_from_child_enum(e: &DataEnum) -> TokenStream2 {
 ///
 ///
 ///
-fn destructure_and_match_variant(variant: &Variant) -> OptionalCompileResult {
+fn destructure_and_match_variant(variant: &Variant, custom_keys: bool) -> OptionalCompileResult {
     // Get the identity of the Variant
     // This part:
     // ```
@@ -426,7 +450,12 @@ fn destructure_and_match_variant(variant: &Variant) -> OptionalCompileResult {
     //
     let ident = variant.ident.clone();
 
-    let fixed_values = top_level_cef_ext_values(&variant.attrs);
+    // custom keys may be allowed for the whole item, or just this variant
+    let custom_keys = custom_keys || allows_custom_keys(&variant.attrs)?;
+
+    check_custom_slot_counts(&variant.fields)?;
+
+    let fixed_values = top_level_cef_ext_values(&variant.attrs, custom_keys);
 
     // create a field-capture
     // field_captures is a Vector of either:
@@ -453,12 +482,20 @@ fn destructure_and_match_variant(variant: &Variant) -> OptionalCompileResult {
                     ),
                 };
 
+                // errors from this field are reported under the variant and field name (or index)
+                let error_path = match &f.ident {
+                    Some(id) => vec![ident.to_string(), id.to_string()],
+                    None => vec![ident.to_string(), index.to_string()],
+                };
+
                 let (final_fieldid, extraction) = match field_extraction(
                     &f.attrs,
                     FieldIdentity::Ident(fieldid.clone()),
                     field_name_from_id,
                     &f.ty,
                     &PrefixSelf::No,
+                    &error_path,
+                    custom_keys,
                     f.span(),
                 ) {
                     Err(ts) => return Err(ts),
@@ -498,13 +535,13 @@ fn destructure_and_match_variant(variant: &Variant) -> OptionalCompileResult {
     //
     // For named fields:
     // Self::Variant1{name: _name, address: _address}  => {
-    //      collector.insert("name", format!("{}", _name));
+    //      collector.insert_fmt("name", format_args!("{}", _name))?;
     //      rust_cef::CefExtensions::cef_extensions(&_address, &mut collector);
     // },
     //
     // For unnamed fields:
     // Self::Variant1(_index0, _index1)  => {
-    //      collector.insert("newname", format!("{}", _index0));
+    //      collector.insert_fmt("newname", format_args!("{}", _index0))?;
     //      rust_cef::CefExtensions::cef_extensions(&_index1, &mut collector);
     // },
     let match_branch = quote! {
@@ -517,23 +554,32 @@ fn destructure_and_match_variant(variant: &Variant) -> OptionalCompileResult {
     Ok(Some(match_branch))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn field_extraction(
     attrs: &[Attribute],
     field_identity: FieldIdentity,
     field_name_from_id: FieldNameFromId,
     field_type: &Type,
     prefix_self: &PrefixSelf,
+    error_path: &[String],
+    custom_keys: bool,
     span: Span,
 ) -> Result<Option<TokenStream2>, TokenStream2> {
+    let custom_keys = custom_keys || allows_custom_keys(attrs)?;
+
     // look for field attributes
     let values_for_field_result: CollectedCompileResult = attrs.iter()
-        .filter(|attr| attr.path.is_ident("cef_ext_gobble") || attr.path.is_ident("cef_ext_optional_gobble") || attr.path.is_ident("cef_ext_field") || attr.path.is_ident("cef_ext_optional_field") || attr.path.is_ident("cef_ext_gobble_kv_iterator") || attr.path.is_ident("cef_ext_optional_gobble_kv_iterator") || attr.path.is_ident("cef_ext_values"))
+        .filter(|attr| attr.path.is_ident("cef_ext_gobble") || attr.path.is_ident("cef_ext_optional_gobble") || attr.path.is_ident("cef_ext_field") || attr.path.is_ident("cef_ext_optional_field") || attr.path.is_ident("cef_ext_gobble_kv_iterator") || attr.path.is_ident("cef_ext_optional_gobble_kv_iterator") || attr.path.is_ident("cef_ext_values") || attr.path.is_ident("cef_ext_custom"))
         .map(|attr| {
             let (usage_message, value_type) = match attr.path.get_ident() {
                 None => return Err(SynError::new(attr.span(), "attribute should have an 'ident', and the internal filter should have protected you from it. This is a bug in rust-cef-derive crate.".to_owned()).to_compile_error()),
                 Some(ident) => match ident.to_string().as_str() {
                     "cef_ext_gobble" => (CEF_EXT_GOBBLE_USAGE.to_owned(), FieldValueType::GobbleTrait),
                     "cef_ext_field" => (CEF_EXT_FIELD_USAGE.to_owned(), FieldValueType::DisplayTrait),
+                    "cef_ext_custom" => {
+                        let (label, kind) = parse_cef_ext_custom(attr)?;
+                        (CEF_EXT_CUSTOM_USAGE.to_owned(), FieldValueType::CustomSlot(label, kind))
+                    },
                     "cef_ext_values" => return Err(SynError::new(attr.span(), CEF_EXT_VALUES_APPLICABLE).to_compile_error()),
                     _ => return Err(SynError::new(attr.span(), "attribute ident not understood, and the internal filter should have protected you from it. This is a bug in rust-cef-derive crate.".to_owned()).to_compile_error()),
                 }
@@ -544,16 +590,24 @@ fn field_extraction(
 
                 // if named...
                 FieldIdentity::Ident(fieldid) => match value_type {
-                    // Gobble is fine.
-                    FieldValueType::GobbleTrait => Ok(field_value(fieldid.to_string().as_str(), fieldid, field_type, &value_type, prefix_self)),
+                    // Gobble is fine, and so are custom slots (the slot is the key)
+                    FieldValueType::GobbleTrait | FieldValueType::CustomSlot(..) => Ok(field_value(fieldid.to_string().as_str(), fieldid, field_type, &value_type, prefix_self, error_path)),
 
                     // When exposed as named...
-                    FieldValueType::DisplayTrait => match parse_attrs_to_path(attr, usage_message.as_str())? {
+                    FieldValueType::DisplayTrait | FieldValueType::RedactedDisplay(_) => match parse_cef_ext_field(attr, usage_message.as_str())? {
                         // renamed? - use new name!
-                        Some(newfield) => Ok(field_value(newfield.as_str(), fieldid, field_type, &value_type, prefix_self)),
+                        (Some(newfield), redaction) => {
+                            validate_extension_key(newfield.as_str(), custom_keys, attr.tokens.span())?;
+                            Ok(field_value(newfield.as_str(), fieldid, field_type, &display_value_type(redaction), prefix_self, error_path))
+                        },
 
                         // Not renamed? But allowed to use field-id? Use field-id.
-                        None if FieldNameFromId::Allowed == field_name_from_id => Ok(field_value(fieldid.to_string().as_str(), fieldid, field_type, &value_type, prefix_self)),
+                        (None, redaction) if FieldNameFromId::Allowed == field_name_from_id => {
+                            // raw identifiers (i.e. r#type) name the key without their prefix
+                            let key = fieldid.unraw().to_string();
+                            validate_extension_key(key.as_str(), custom_keys, fieldid.span())?;
+                            Ok(field_value(key.as_str(), fieldid, field_type, &display_value_type(redaction), prefix_self, error_path))
+                        },
 
                         // Not renamed, and not allowed field-id as name? Error - how are we supposed to name it?
                         _ => Err(SynError::new(attr.span(), "'cef_ext_field' should have a single parameter with the field name when used on unnamed fields. Cannot use tuple index as a cef key.".to_owned()).to_compile_error()),
@@ -562,12 +616,15 @@ fn field_extraction(
 
                 // if index...
                 FieldIdentity::Index(index) => match value_type {
-                    // Gobble is fine.
-                    FieldValueType::GobbleTrait => Ok(field_value("ignored", index, field_type, &value_type, prefix_self)),
+                    // Gobble is fine, and so are custom slots (the slot is the key)
+                    FieldValueType::GobbleTrait | FieldValueType::CustomSlot(..) => Ok(field_value("ignored", index, field_type, &value_type, prefix_self, error_path)),
 
                     // When exposed as named - be sure to have specified a field name (none exists when indexed)
-                    FieldValueType::DisplayTrait => match parse_attrs_to_path(attr, usage_message.as_str()) {
-                        Ok(Some(newfield)) => Ok(field_value(newfield.as_str(), index, field_type, &value_type, prefix_self)),
+                    FieldValueType::DisplayTrait | FieldValueType::RedactedDisplay(_) => match parse_cef_ext_field(attr, usage_message.as_str()) {
+                        Ok((Some(newfield), redaction)) => {
+                            validate_extension_key(newfield.as_str(), custom_keys, attr.tokens.span())?;
+                            Ok(field_value(newfield.as_str(), index, field_type, &display_value_type(redaction), prefix_self, error_path))
+                        },
                         _ => Err(SynError::new(attr.span(), "'cef_ext_field' should have a single parameter with the field name when used on unnamed fields. Cannot use tuple index as a cef key.".to_owned()).to_compile_error()),
                     },
                 },
@@ -587,27 +644,11 @@ fn field_extraction(
     }
 }
 
-// Helps cut through a lot of parse tree and doesn't confuse reading-context
-fn parse_attrs_to_path(attr: &Attribute, messsage: &str) -> ParseAttrResult<Option<String>> {
-    match attr.parse_meta() {
-        Ok(parsed_meta) => match parsed_meta {
-            Meta::Path(_) => Ok(None),
-            Meta::List(ml) => match ml.nested.len() {
-                0 | 1 => match ml.nested.first() {
-                    None => Ok(None),
-                    Some(nm) => match nm {
-                        NestedMeta::Meta(Meta::Path(p)) => match p.get_ident() {
-                            Some(ident) => Ok(Some(ident.to_string())),
-                            _ => Err(SynError::new(attr.span(), messsage).to_compile_error()),
-                        },
-                        _ => Err(SynError::new(attr.span(), messsage).to_compile_error()),
-                    },
-                },
-                _ => Err(SynError::new(attr.span(), messsage).to_compile_error()),
-            },
-            _ => Err(SynError::new(attr.span(), messsage).to_compile_error()),
-        },
-        Err(e) => Err(e.to_compile_error()),
+// Fields exposed through Display are redacted when asked to
+fn display_value_type(redaction: Option<rust_cef::redact::Redaction>) -> FieldValueType {
+    match redaction {
+        Some(redaction) => FieldValueType::RedactedDisplay(redaction),
+        None => FieldValueType::DisplayTrait,
     }
 }
 
@@ -618,34 +659,72 @@ fn field_value<T: quote::ToTokens>(
     field_type: &Type,
     value_type: &FieldValueType,
     prefix_self: &PrefixSelf,
+    error_path: &[String],
 ) -> TokenStream2 {
     let maybe_self = match prefix_self {
         PrefixSelf::Yes => quote! {&self.},
         PrefixSelf::No => quote! {},
     };
 
+    let nested_err = nest_error(error_path, quote! {err});
+
+    // tell the collector which field the keys come from (i.e. to report duplicates)
+    let field_label = error_path.join(".");
+
     let field_value_ts = match value_type {
         FieldValueType::GobbleTrait => quote! {
-            match rust_cef::CefExtensions::cef_extensions(val, collector) {
-                Err(err) => return Err(err),
-                Ok(()) => {},
+            {
+                collector.enter_field(#field_label);
+                match rust_cef::CefExtensions::cef_extensions(val, collector) {
+                    Err(err) => return Err(#nested_err),
+                    Ok(()) => {},
+                }
+                collector.exit_field();
             }
         },
         FieldValueType::DisplayTrait => quote! {
-            {collector.insert(#field_name.to_owned(), format!("{}", val));}
+            {
+                collector.enter_field(#field_label);
+                collector.insert_fmt(#field_name, format_args!("{}", val))?;
+                collector.exit_field();
+            }
         },
-    };
-
-    let optional: bool = match field_type {
-        Type::Path(tp) => {
-            tp.path.leading_colon.is_none()
-                && tp.path.segments.len() == 1
-                && tp.path.segments.iter().next().unwrap().ident == "Option"
+        // pseudonyms need the collector's secret
+        FieldValueType::RedactedDisplay(rust_cef::redact::Redaction::Pseudonymize) => quote! {
+            {
+                collector.enter_field(#field_label);
+                collector.insert_pseudonymized(#field_name, format_args!("{}", val))?;
+                collector.exit_field();
+            }
+        },
+        FieldValueType::RedactedDisplay(redaction) => {
+            let redaction = match redaction {
+                rust_cef::redact::Redaction::MaskLast(keep) => {
+                    quote! { rust_cef::redact::Redaction::MaskLast(#keep) }
+                }
+                _ => quote! { rust_cef::redact::Redaction::Redact },
+            };
+            quote! {
+                {
+                    collector.enter_field(#field_label);
+                    collector.insert(#field_name, &#redaction.apply(&val.to_string()))?;
+                    collector.exit_field();
+                }
+            }
+        }
+        FieldValueType::CustomSlot(label, kind) => {
+            let kind = format_ident!("{}", format!("{:?}", kind));
+            quote! {
+                {
+                    collector.enter_field(#field_label);
+                    collector.insert_custom(rust_cef::CefCustomKind::#kind, #label, format_args!("{}", val))?;
+                    collector.exit_field();
+                }
+            }
         }
-        _ => false,
     };
 
-    match optional {
+    match is_option_type(field_type) {
         true => quote! {
             match #maybe_self#field_ident {
                 Some(val) => #field_value_ts,
@@ -692,7 +771,7 @@ fn field_value<T: quote::ToTokens>(
 /// }
 /// ```
 ///
-fn top_level_cef_ext_values(attrs: &[Attribute]) -> Vec<TokenStream2> {
+fn top_level_cef_ext_values(attrs: &[Attribute], custom_keys: bool) -> Vec<TokenStream2> {
     let mut retval = vec![];
     for attr in attrs {
         match attr.path.get_ident().map(|x| x.to_string()).as_deref() {
@@ -708,11 +787,15 @@ fn top_level_cef_ext_values(attrs: &[Attribute]) -> Vec<TokenStream2> {
                             ),
                             Some(keyident) => match &mnv.lit {
                                 Lit::Str(strval) => {
-                                    let key = keyident.to_string();
+                                    let key = keyident.unraw().to_string();
                                     let val = strval.value();
-                                    retval.push(quote! {
-                                        collector.insert(#key.to_owned(), #val.to_owned());
-                                    })
+                                    match validate_extension_key(&key, custom_keys, keyident.span())
+                                    {
+                                        Err(ts) => retval.push(ts),
+                                        Ok(()) => retval.push(quote! {
+                                            collector.insert(#key, #val)?;
+                                        }),
+                                    }
                                 }
                                 _ => retval.push(
                                     SynError::new(mnv.lit.span(), CEF_EXT_VALUES_USAGE.to_owned())
@@ -723,6 +806,8 @@ fn top_level_cef_ext_values(attrs: &[Attribute]) -> Vec<TokenStream2> {
                     }
                 }
             },
+            // handled by allows_custom_keys
+            Some("cef_ext_custom_keys") => continue,
             Some(attr_name) => {
                 if attr_name.starts_with("cef_ext_") {
                     retval.push(
@@ -739,3 +824,97 @@ fn top_level_cef_ext_values(attrs: &[Attribute]) -> Vec<TokenStream2> {
 
     retval
 }
+
+/// Looks for the #[cef_ext_custom_keys] attribute, which allows extension
+/// keys that look like misspelled standard keys on the Struct, Enum,
+/// Enum::Variant or field it's applied to.
+fn allows_custom_keys(attrs: &[Attribute]) -> ParseAttrResult<bool> {
+    let mut custom_keys = false;
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cef_ext_custom_keys"))
+    {
+        match attr.parse_meta() {
+            Ok(Meta::Path(_)) => custom_keys = true,
+            Ok(_) => {
+                return Err(SynError::new(attr.span(), CEF_EXT_CUSTOM_KEYS_USAGE).to_compile_error())
+            }
+            Err(e) => return Err(e.to_compile_error()),
+        }
+    }
+
+    Ok(custom_keys)
+}
+
+/// Checks an extension key against the CEF key grammar and, unless custom
+/// keys are allowed, against the standard keys it may be a misspelling of.
+fn validate_extension_key(key: &str, custom_keys: bool, span: Span) -> ParseAttrResult<()> {
+    if !rust_cef::is_valid_extension_key(key) {
+        return Err(SynError::new(
+            span,
+            format!(
+                "'{}' is not a valid CEF extension key. Keys may only contain letters, digits, '_' and '.'",
+                key
+            ),
+        )
+        .to_compile_error());
+    }
+
+    if custom_keys {
+        return Ok(());
+    }
+
+    match rust_cef::dictionary::suggest_standard_key(key) {
+        None => Ok(()),
+        Some(suggestion) => Err(SynError::new(
+            span,
+            format!(
+                "'{}' is not a standard CEF extension key, did you mean `{}`? Add #[cef_ext_custom_keys] to use it anyway.",
+                key, suggestion
+            ),
+        )
+        .to_compile_error()),
+    }
+}
+
+/// Ensures the #[cef_ext_custom] fields of a Struct or Enum::Variant don't ask
+/// for more custom slots of a kind than exist. Slots may still run out when
+/// encoding, if gobbled items take some of them.
+fn check_custom_slot_counts(fields: &Fields) -> ParseAttrResult<()> {
+    let mut counts: Vec<(rust_cef::CefCustomKind, usize)> = vec![];
+
+    for attr in fields
+        .iter()
+        .flat_map(|field| field.attrs.iter())
+        .filter(|attr| attr.path.is_ident("cef_ext_custom"))
+    {
+        let (_, kind) = parse_cef_ext_custom(attr)?;
+        let count = match counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                counts.push((kind, 1));
+                1
+            }
+        };
+
+        let slots = kind.slots();
+        if count > slots.len() {
+            return Err(SynError::new(
+                attr.tokens.span(),
+                format!(
+                    "Too many 'cef_ext_custom' fields of kind '{}': only {} slots ({} to {}) exist.",
+                    kind,
+                    slots.len(),
+                    slots[0].0,
+                    slots[slots.len() - 1].0
+                ),
+            )
+            .to_compile_error());
+        }
+    }
+
+    Ok(())
+}
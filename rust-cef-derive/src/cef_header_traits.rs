@@ -2,7 +2,8 @@
 ///
 /// This module provides functions to implement the CefHeader* traits
 use crate::helpers::{
-    is_valid_item_type, parse_attrs_to_name_value, ParseAttrResult, CEF_ATTRIBUTE_APPLICATION,
+    is_valid_item_type, nest_error, parse_attrs_to_name_value, ParseAttrResult,
+    CEF_ATTRIBUTE_APPLICATION,
 };
 use crate::proc_macro::TokenStream;
 use inflections::case::to_snake_case;
@@ -16,7 +17,7 @@ use syn::{
     Field, Fields, Ident, Index, Lit, Meta, NestedMeta, Path, Variant,
 };
 
-const CEF_ALLOWED_HEADERS: &[&str] = &[
+pub const CEF_ALLOWED_HEADERS: &[&str] = &[
     "Version",
     "DeviceVendor",
     "DeviceProduct",
@@ -40,7 +41,7 @@ const CEF_FIELD_USAGE: &str = "'cef_field' macro adapts the attributed field usi
 const CEF_VALUES_STRINGS: &str = "'cef_values' macro expects all values to be string literals";
 
 lazy_static! {
-    static ref CEF_INVALID_HEADER: String = [
+    pub static ref CEF_INVALID_HEADER: String = [
         "header name should be one of the following: ",
         CEF_ALLOWED_HEADERS.join(",").as_str()
     ]
@@ -233,6 +234,7 @@ fn header_value_from_child_struct(
                                         &value_type,
                                         format_ident!("{}", i),
                                         PrefixSelf::Yes,
+                                        &[i.to_string()],
                                     ),
                                     None => field_value(
                                         header_name,
@@ -240,6 +242,7 @@ fn header_value_from_child_struct(
                                         &value_type,
                                         Index::from(index),
                                         PrefixSelf::Yes,
+                                        &[index.to_string()],
                                     ),
                                 };
 
@@ -460,6 +463,7 @@ fn header_value_from_child_struct(
 /// an error is thrown, and if multiple values are found an error is
 /// thrown to indicate conflict and ambiguity.
 ///
+#[allow(clippy::doc_lazy_continuation)]
 fn header_value_from_child_enum(
     header_name: &Ident,
     method_name: &Ident,
@@ -569,6 +573,7 @@ fn all_variants_cef_value(
 ///
 ///
 ///
+#[allow(clippy::question_mark)]
 fn destructure_and_match_variant(
     header_name: &Ident,
     method_name: &Ident,
@@ -611,12 +616,23 @@ fn destructure_and_match_variant(
                 None => format_ident!("index{}", index),
             };
 
-            let final_fieldid =
-                match variant_field_value(header_name, method_name, &fieldid, f, &mut trait_values)
-                {
-                    Err(ts) => return Err(ts),
-                    Ok(ident) => ident,
-                };
+            // errors from this field are reported under the variant and field name (or index)
+            let error_path = match &f.ident {
+                Some(id) => vec![ident.to_string(), id.to_string()],
+                None => vec![ident.to_string(), index.to_string()],
+            };
+
+            let final_fieldid = match variant_field_value(
+                header_name,
+                method_name,
+                &fieldid,
+                f,
+                &error_path,
+                &mut trait_values,
+            ) {
+                Err(ts) => return Err(ts),
+                Ok(ident) => ident,
+            };
 
             Ok(quote! {#final_fieldid})
         })
@@ -683,6 +699,7 @@ fn variant_field_value(
     method_name: &Ident,
     fieldid: &Ident,
     field: &Field,
+    error_path: &[String],
     trait_values: &mut Vec<TraitValue>,
 ) -> CompileResult {
     let mut ignore_ident: bool = true;
@@ -709,6 +726,7 @@ fn variant_field_value(
                                 &value_type,
                                 fieldid,
                                 PrefixSelf::No,
+                                error_path,
                             );
 
                             // no longer ignore the ident
@@ -760,15 +778,18 @@ fn field_value<T: quote::ToTokens>(
     value_type: &FieldValueType,
     field_name: T,
     prefix_self: PrefixSelf,
+    error_path: &[String],
 ) -> TokenStream2 {
     let maybe_self = match prefix_self {
         PrefixSelf::Yes => quote! {&self.},
         PrefixSelf::No => quote! {},
     };
 
+    let nested_err = nest_error(error_path, quote! {err});
+
     match value_type {
         FieldValueType::InheritTrait => quote! {
-            rust_cef::#header_name::#method_name(#maybe_self#field_name)
+            rust_cef::#header_name::#method_name(#maybe_self#field_name).map_err(|err| #nested_err)
         },
         FieldValueType::DisplayTrait => quote! {
             Ok(format!("{}", #maybe_self#field_name))
@@ -0,0 +1,390 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides functions to implement the FromCef trait
+use crate::cef_header_traits::{CEF_ALLOWED_HEADERS, CEF_INVALID_HEADER};
+use crate::helpers::{
+    is_option_type, is_valid_item_type, nest_error, parse_attrs_to_name_value,
+    parse_cef_ext_custom, parse_cef_ext_field, CEF_ATTRIBUTE_APPLICATION,
+};
+use crate::proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use std::convert::From;
+use syn::{
+    ext::IdentExt, parse_macro_input, spanned::Spanned, Attribute, Data, DataEnum, DataStruct,
+    DeriveInput, Error as SynError, Field, Fields, Ident, Lit, Meta, NestedMeta, Variant,
+};
+
+const CEF_FIELD_USAGE: &str = "'cef_field' macro expects one or more header traits to read the field from using the FromStr trait: #[cef_field(headerTrait)]";
+const CEF_EXT_FIELD_USAGE: &str = "'cef_ext_field' macro may optionally supply the custom extension key name to read, followed by 'redact', 'pseudonymize' or 'mask = \"lastN\"'. If no key is supplied, the field's name is used. #[cef_ext_field(rename)]";
+const CEF_EXT_FIELD_UNNAMED: &str = "'cef_ext_field' should have a single parameter with the field name when used on unnamed fields. Cannot use tuple index as a cef key.";
+const CEF_VALUES_USAGE: &str = "'cef_values' and 'cef_ext_values' macros expect values to be listed in the following syntax: #[cef_values(key1 = \"value1\", key2 = \"value2\", ...)] ";
+
+/// Where a field's value is read from in a CefRecord
+enum FieldSource {
+    // FromCef on the field's type (from cef_inherit or cef_ext_gobble)
+    Record,
+    // FromStr on a header (from cef_field)
+    Header(String),
+    // FromStr on an extension (from cef_ext_field)
+    Extension(String),
+    // FromStr on the custom slot with a label (from cef_ext_custom)
+    CustomSlot(String, rust_cef::CefCustomKind),
+    // No attributes - Default::default()
+    Default,
+}
+
+type CompileResult = Result<TokenStream2, TokenStream2>;
+
+/// Implements the FromCef trait asked by `#[derive(FromCef)]`.
+/// It creates the trait skeleton and outsources constructing
+/// the item to a child-item function.
+pub fn implement_from_cef_trait(item_tokens: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item_tokens as DeriveInput);
+
+    // Only applies to structs and enums
+    if let Some(compile_error) = is_valid_item_type(&item) {
+        return compile_error;
+    }
+
+    // type name
+    let item_name = &item.ident;
+
+    // generics
+    let item_generics = &item.generics;
+    let (item_impl_generics, item_ty_generics, item_where_clause) = item_generics.split_for_impl();
+
+    let construction = match &item.data {
+        Data::Struct(s) => from_cef_struct(s),
+        Data::Enum(e) => from_cef_enum(item_name, e),
+        _ => Err(
+            SynError::new(Span::call_site(), CEF_ATTRIBUTE_APPLICATION.to_owned())
+                .to_compile_error(),
+        ),
+    };
+
+    let construction = match construction {
+        Ok(ts) => ts,
+        Err(ts) => return TokenStream::from(ts),
+    };
+
+    let trait_impl = quote! {
+        impl #item_impl_generics rust_cef::FromCef for #item_name #item_ty_generics #item_where_clause {
+            fn from_cef_record(record: &rust_cef::CefRecord) -> Result<Self, rust_cef::CefConversionError> {
+                #construction
+            }
+        }
+    };
+
+    //println!("\n\n{:#?}\n\n", trait_impl.to_string());
+
+    TokenStream::from(trait_impl)
+}
+
+/// A struct is constructed by reading every field in turn:
+///
+/// ```ignore
+/// #[derive(FromCef)]
+/// struct Outer {
+///     #[cef_field(CefHeaderName)]
+///     pub name: String,
+///
+///     #[cef_ext_field(outer)]
+///     pub inner: Inner,
+///
+///     #[cef_ext_gobble]
+///     pub gobbled: Gobbled,
+/// }
+/// ```
+///
+/// expands to:
+/// ```ignore
+/// impl FromCef for Outer {
+///     fn from_cef_record(record: &CefRecord) -> Result<Self, CefConversionError> {
+///         Ok(Self {
+///             name: record.parse_header("CefHeaderName")?,
+///             inner: record.parse_extension("outer")?,
+///             gobbled: rust_cef::FromCef::from_cef_record(record)
+///                 .map_err(|err| CefConversionError::nested("gobbled", err))?,
+///         })
+///     }
+/// }
+/// ```
+fn from_cef_struct(s: &DataStruct) -> CompileResult {
+    let construction = construct_fields(quote! {Self}, &s.fields, &[])?;
+    Ok(quote! {
+        Ok(#construction)
+    })
+}
+
+/// An enum is constructed from the first variant whose fixed values
+/// (`cef_values` and `cef_ext_values` on the variant) all match the record,
+/// and whose fields can all be read from the record:
+///
+/// ```ignore
+/// #[derive(FromCef)]
+/// enum Items {
+///     #[cef_values(CefHeaderDeviceVersion = "V1")]
+///     Variant1(#[cef_field(CefHeaderName)] String),
+///
+///     #[cef_ext_values(kind = "second")]
+///     Variant2 {
+///         #[cef_ext_field]
+///         address: String,
+///     },
+/// }
+/// ```
+///
+/// expands to:
+/// ```ignore
+/// impl FromCef for Items {
+///     fn from_cef_record(record: &CefRecord) -> Result<Self, CefConversionError> {
+///         let mut last_error = None;
+///         if record.header("CefHeaderDeviceVersion") == Some("V1") {
+///             match (|| Ok(Self::Variant1(record.parse_header("CefHeaderName")?)))() {
+///                 Ok(item) => return Ok(item),
+///                 Err(err) => last_error = Some(err),
+///             }
+///         }
+///         if record.extension("kind") == Some("second") {
+///             match (|| Ok(Self::Variant2{address: record.parse_extension("address")?}))() {
+///                 Ok(item) => return Ok(item),
+///                 Err(err) => last_error = Some(err),
+///             }
+///         }
+///         Err(last_error.unwrap_or(...))
+///     }
+/// }
+/// ```
+fn from_cef_enum(item_name: &Ident, e: &DataEnum) -> CompileResult {
+    let attempts: Vec<TokenStream2> = e
+        .variants
+        .iter()
+        .map(variant_attempt)
+        .collect::<Result<_, _>>()?;
+
+    let no_match = format!("No variant of {} matched the CEF record", item_name);
+
+    Ok(quote! {
+        let mut last_error: Option<rust_cef::CefConversionError> = None;
+
+        #(#attempts)*
+
+        Err(last_error.unwrap_or_else(|| rust_cef::CefConversionError::Unexpected(#no_match.to_owned())))
+    })
+}
+
+/// Tries to construct a single variant when its fixed values match the record
+fn variant_attempt(variant: &Variant) -> CompileResult {
+    let ident = &variant.ident;
+    let conditions = fixed_value_conditions(&variant.attrs)?;
+    let construction =
+        construct_fields(quote! {Self::#ident}, &variant.fields, &[ident.to_string()])?;
+
+    let attempt = quote! {
+        let attempt = (|| -> Result<Self, rust_cef::CefConversionError> { Ok(#construction) })();
+        match attempt {
+            Ok(item) => return Ok(item),
+            Err(err) => last_error = Some(err),
+        }
+    };
+
+    match conditions.is_empty() {
+        true => Ok(quote! {
+            {
+                #attempt
+            }
+        }),
+        false => Ok(quote! {
+            if #(#conditions)&&* {
+                #attempt
+            }
+        }),
+    }
+}
+
+/// Turns `#[cef_values(Header = "value")]` and `#[cef_ext_values(key = "value")]`
+/// on a variant into comparisons against the record.
+fn fixed_value_conditions(attrs: &[Attribute]) -> Result<Vec<TokenStream2>, TokenStream2> {
+    let mut conditions = vec![];
+
+    for attr in attrs {
+        let is_header = attr.path.is_ident("cef_values");
+        if !is_header && !attr.path.is_ident("cef_ext_values") {
+            continue;
+        }
+
+        for mnv in parse_attrs_to_name_value(attr, CEF_VALUES_USAGE)? {
+            let key = match mnv.path.get_ident() {
+                Some(ident) => ident.to_string(),
+                None => {
+                    return Err(SynError::new(mnv.path.span(), CEF_VALUES_USAGE).to_compile_error())
+                }
+            };
+            let value = match &mnv.lit {
+                Lit::Str(strval) => strval.value(),
+                _ => return Err(SynError::new(mnv.lit.span(), CEF_VALUES_USAGE).to_compile_error()),
+            };
+
+            conditions.push(match is_header {
+                true => quote! { record.header(#key) == Some(#value) },
+                false => quote! { record.extension(#key) == Some(#value) },
+            });
+        }
+    }
+
+    Ok(conditions)
+}
+
+/// Builds `Path { field: expr, ... }`, `Path(expr, ...)` or `Path`
+///
+/// Errors from nested items are reported under `error_prefix`
+/// followed by the field name (or index).
+fn construct_fields(path: TokenStream2, fields: &Fields, error_prefix: &[String]) -> CompileResult {
+    let values: Vec<TokenStream2> = fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let mut error_path = error_prefix.to_vec();
+            error_path.push(match &field.ident {
+                Some(ident) => ident.to_string(),
+                None => index.to_string(),
+            });
+            field_value(field, &error_path)
+        })
+        .collect::<Result<_, _>>()?;
+
+    match fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|f| &f.ident);
+            Ok(quote! { #path { #(#names: #values),* } })
+        }
+        Fields::Unnamed(_) => Ok(quote! { #path ( #(#values),* ) }),
+        Fields::Unit => Ok(quote! { #path }),
+    }
+}
+
+/// Generates the expression reading a single field from the record
+fn field_value(field: &Field, error_path: &[String]) -> CompileResult {
+    let optional = is_option_type(&field.ty);
+    let nested_err = nest_error(error_path, quote! {err});
+
+    let ts = match field_source(field)? {
+        // optional items are None when they're missing, not when they're invalid
+        FieldSource::Record => match optional {
+            true => quote! {
+                match rust_cef::FromCef::from_cef_record(record) {
+                    Ok(value) => Some(value),
+                    Err(err) => match err.innermost() {
+                        rust_cef::CefConversionError::MissingExtension { .. }
+                        | rust_cef::CefConversionError::MissingHeader { .. } => None,
+                        _ => return Err(#nested_err),
+                    },
+                }
+            },
+            false => {
+                quote! { rust_cef::FromCef::from_cef_record(record).map_err(|err| #nested_err)? }
+            }
+        },
+        FieldSource::Header(header) => match optional {
+            true => quote! { record.parse_optional_header(#header)? },
+            false => quote! { record.parse_header(#header)? },
+        },
+        FieldSource::Extension(key) => match optional {
+            true => quote! { record.parse_optional_extension(#key)? },
+            false => quote! { record.parse_extension(#key)? },
+        },
+        FieldSource::CustomSlot(label, kind) => {
+            let kind = format_ident!("{}", format!("{:?}", kind));
+            let slot =
+                quote! { record.custom_extension_key(rust_cef::CefCustomKind::#kind, #label) };
+            match optional {
+                true => quote! {
+                    match #slot {
+                        Some(key) => record.parse_optional_extension(key)?,
+                        None => None,
+                    }
+                },
+                false => quote! {
+                    record.parse_extension(#slot.ok_or_else(|| {
+                        rust_cef::CefConversionError::MissingExtension { key: #label.to_owned() }
+                    })?)?
+                },
+            }
+        }
+        FieldSource::Default => quote! { Default::default() },
+    };
+
+    Ok(ts)
+}
+
+/// Decides where a field is read from. When a field carries several attributes,
+/// `cef_inherit`/`cef_ext_gobble` win over `cef_field`, which wins over
+/// `cef_ext_field` and `cef_ext_custom`.
+fn field_source(field: &Field) -> Result<FieldSource, TokenStream2> {
+    let mut header: Option<String> = None;
+    let mut extension: Option<FieldSource> = None;
+
+    for attr in &field.attrs {
+        if attr.path.is_ident("cef_inherit") || attr.path.is_ident("cef_ext_gobble") {
+            return Ok(FieldSource::Record);
+        } else if attr.path.is_ident("cef_field") {
+            if header.is_none() {
+                header = Some(parse_header_attr(attr)?);
+            }
+        } else if attr.path.is_ident("cef_ext_field") {
+            extension = Some(FieldSource::Extension(parse_ext_field_attr(attr, field)?));
+        } else if attr.path.is_ident("cef_ext_custom") {
+            let (label, kind) = parse_cef_ext_custom(attr)?;
+            extension = Some(FieldSource::CustomSlot(label, kind));
+        }
+    }
+
+    match (header, extension) {
+        (Some(header), _) => Ok(FieldSource::Header(header)),
+        (None, Some(extension)) => Ok(extension),
+        (None, None) => Ok(FieldSource::Default),
+    }
+}
+
+// Reads the first header trait named in #[cef_field(...)]
+fn parse_header_attr(attr: &Attribute) -> Result<String, TokenStream2> {
+    let header = match attr.parse_meta() {
+        Ok(Meta::List(ml)) => match ml.nested.first() {
+            Some(NestedMeta::Meta(Meta::Path(p))) => p.get_ident().map(|i| i.to_string()),
+            _ => None,
+        },
+        Ok(_) => None,
+        Err(e) => return Err(e.to_compile_error()),
+    };
+
+    match header {
+        None => Err(SynError::new(attr.span(), CEF_FIELD_USAGE).to_compile_error()),
+        Some(header) => match header.strip_prefix("CefHeader") {
+            Some(h) if CEF_ALLOWED_HEADERS.contains(&h) => Ok(header),
+            _ => Err(SynError::new(attr.span(), CEF_INVALID_HEADER.as_str()).to_compile_error()),
+        },
+    }
+}
+
+// Reads the key in #[cef_ext_field(key)], defaulting to the field name.
+// Redacted, masked and pseudonymized values can't be read back.
+fn parse_ext_field_attr(attr: &Attribute, field: &Field) -> Result<String, TokenStream2> {
+    let (renamed, redaction) = parse_cef_ext_field(attr, CEF_EXT_FIELD_USAGE)?;
+    if let Some(redaction) = redaction {
+        return Err(SynError::new(
+            attr.span(),
+            format!(
+                "'{}' values can't be read back, so FromCef can't be derived for this field",
+                redaction
+            ),
+        )
+        .to_compile_error());
+    }
+
+    match (renamed, &field.ident) {
+        (Some(key), _) => Ok(key),
+        (None, Some(ident)) => Ok(ident.unraw().to_string()),
+        (None, None) => Err(SynError::new(attr.span(), CEF_EXT_FIELD_UNNAMED).to_compile_error()),
+    }
+}
@@ -1,10 +1,17 @@
 use crate::proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
 use std::convert::From;
+use syn::ext::IdentExt;
 use syn::spanned::Spanned;
-use syn::{Attribute, Data, DeriveInput, Error as SynError, Meta, MetaNameValue, NestedMeta};
+use syn::{
+    Attribute, Data, DeriveInput, Error as SynError, Lit, Meta, MetaNameValue, NestedMeta, Type,
+};
 
 pub const CEF_ATTRIBUTE_APPLICATION: &str = "This attribute only applies to Structs or Enums.";
+pub const CEF_EXT_CUSTOM_USAGE: &str = "'cef_ext_custom' macro expects a label and the kind of custom slot (string, number, float, date or ipv6) to store the field in: #[cef_ext_custom(label = \"Container ID\", kind = \"string\")]";
+
+pub const CEF_EXT_FIELD_MASK_USAGE: &str = "'mask' expects the number of trailing characters to keep in the clear: #[cef_ext_field(mask = \"last4\")]";
 
 pub type ParseAttrResult<T> = Result<T, TokenStream2>;
 
@@ -50,3 +57,132 @@ pub fn parse_attrs_to_name_value(
 
     Ok(mnvs)
 }
+
+// Is the type an `Option<...>`? Fields of such types are skipped when `None`.
+pub fn is_option_type(ty: &Type) -> bool {
+    match ty {
+        Type::Path(tp) => {
+            tp.path.leading_colon.is_none()
+                && tp.path.segments.len() == 1
+                && tp.path.segments.iter().next().unwrap().ident == "Option"
+        }
+        _ => false,
+    }
+}
+
+// Wraps an error expression with CefConversionError::Nested once for every
+// field on the path (outermost first), so callers can tell where it came from.
+pub fn nest_error(path: &[String], err: TokenStream2) -> TokenStream2 {
+    path.iter().rev().fold(err, |inner, field| {
+        quote! {
+            rust_cef::CefConversionError::nested(#field, #inner)
+        }
+    })
+}
+
+// Reads #[cef_ext_custom(label = "...", kind = "...")] into the label and kind
+pub fn parse_cef_ext_custom(
+    attr: &Attribute,
+) -> ParseAttrResult<(String, rust_cef::CefCustomKind)> {
+    let mut label: Option<String> = None;
+    let mut kind: Option<rust_cef::CefCustomKind> = None;
+
+    for mnv in parse_attrs_to_name_value(attr, CEF_EXT_CUSTOM_USAGE)? {
+        let value = match &mnv.lit {
+            Lit::Str(strval) => strval.value(),
+            _ => return Err(SynError::new(mnv.lit.span(), CEF_EXT_CUSTOM_USAGE).to_compile_error()),
+        };
+
+        match mnv
+            .path
+            .get_ident()
+            .map(|ident| ident.to_string())
+            .as_deref()
+        {
+            Some("label") if !value.is_empty() => label = Some(value),
+            Some("kind") => match value.parse() {
+                Ok(k) => kind = Some(k),
+                Err(_) => {
+                    let kinds: Vec<String> = rust_cef::CefCustomKind::all()
+                        .iter()
+                        .map(|k| k.to_string())
+                        .collect();
+                    return Err(SynError::new(
+                        mnv.lit.span(),
+                        format!(
+                            "'{}' is not a kind of custom slot. Expected one of: {}",
+                            value,
+                            kinds.join(",")
+                        ),
+                    )
+                    .to_compile_error());
+                }
+            },
+            _ => return Err(SynError::new(mnv.span(), CEF_EXT_CUSTOM_USAGE).to_compile_error()),
+        }
+    }
+
+    match (label, kind) {
+        (Some(label), Some(kind)) => Ok((label, kind)),
+        _ => Err(SynError::new(attr.span(), CEF_EXT_CUSTOM_USAGE).to_compile_error()),
+    }
+}
+
+// Reads #[cef_ext_field], #[cef_ext_field(key)], #[cef_ext_field(key, redact)],
+// #[cef_ext_field(key, pseudonymize)] or #[cef_ext_field(key, mask = "last4")]
+// into the key (if renamed) and the redaction
+pub fn parse_cef_ext_field(
+    attr: &Attribute,
+    message: &str,
+) -> ParseAttrResult<(Option<String>, Option<rust_cef::redact::Redaction>)> {
+    let list = match attr.parse_meta() {
+        Err(e) => return Err(e.to_compile_error()),
+        Ok(Meta::Path(_)) => return Ok((None, None)),
+        Ok(Meta::List(list)) => list,
+        Ok(_) => return Err(SynError::new(attr.span(), message.to_owned()).to_compile_error()),
+    };
+
+    let mut key: Option<String> = None;
+    let mut redaction: Option<rust_cef::redact::Redaction> = None;
+
+    for nestedmeta in list.nested {
+        match nestedmeta {
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("redact") && redaction.is_none() => {
+                redaction = Some(rust_cef::redact::Redaction::Redact);
+            }
+            NestedMeta::Meta(Meta::Path(p))
+                if p.is_ident("pseudonymize") && redaction.is_none() =>
+            {
+                redaction = Some(rust_cef::redact::Redaction::Pseudonymize);
+            }
+            NestedMeta::Meta(Meta::Path(p)) if key.is_none() && redaction.is_none() => {
+                match p.get_ident() {
+                    Some(ident) => key = Some(ident.unraw().to_string()),
+                    None => {
+                        return Err(SynError::new(p.span(), message.to_owned()).to_compile_error())
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(mnv))
+                if mnv.path.is_ident("mask") && redaction.is_none() =>
+            {
+                let mask = match &mnv.lit {
+                    Lit::Str(strval) => strval.value().parse().ok(),
+                    _ => None,
+                };
+                match mask {
+                    Some(mask @ rust_cef::redact::Redaction::MaskLast(_)) => redaction = Some(mask),
+                    _ => {
+                        return Err(SynError::new(mnv.lit.span(), CEF_EXT_FIELD_MASK_USAGE)
+                            .to_compile_error())
+                    }
+                }
+            }
+            nestedmeta => {
+                return Err(SynError::new(nestedmeta.span(), message.to_owned()).to_compile_error())
+            }
+        }
+    }
+
+    Ok((key, redaction))
+}
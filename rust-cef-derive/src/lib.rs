@@ -7,39 +7,42 @@
 extern crate proc_macro;
 extern crate proc_macro2;
 
-use quote::quote;
-
 mod cef_extensions_trait;
 mod cef_header_traits;
+mod from_cef_trait;
 mod helpers;
+mod to_cef_trait;
+mod to_ocsf_trait;
 
 use crate::proc_macro::TokenStream;
 use cef_extensions_trait::implement_extensions_trait;
 use cef_header_traits::implement_header_trait;
-use std::convert::From;
-use syn::{parse_macro_input, DeriveInput};
+use from_cef_trait::implement_from_cef_trait;
+use to_cef_trait::implement_to_cef_trait;
+use to_ocsf_trait::implement_to_ocsf_trait;
 
 /// This macro derives the ToCef trait on the annotated item.
-/// For now, the ToCef trait itself provides a useful implementation
-/// of the trait, so this macro simply implements a blank trait.
+/// The ToCef trait itself provides a useful implementation, so this macro
+/// only generates the item's encoding options when asked for them:
 ///
-#[proc_macro_derive(ToCef)]
-pub fn derive_to_cef(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
-
-    // type name
-    let name = &input.ident;
-
-    // generics
-    let generics = input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
-
-    // default implementation is great
-    let to_cef_impl = quote! {
-        impl #impl_generics rust_cef::ToCef for #name #ty_generics #where_clause {}
-    };
+/// `#[cef_options(duplicate_keys = "Error")]` picks the `DuplicateKeyPolicy`
+/// applied when more than one field produces the same extension key, and
+/// `#[cef_options(severity = "Reject")]` picks the `SeverityPolicy` applied
+/// to severities the spec doesn't allow.
+///
+#[proc_macro_derive(ToCef, attributes(cef_options))]
+pub fn derive_to_cef(item_tokens: TokenStream) -> TokenStream {
+    implement_to_cef_trait(item_tokens)
+}
 
-    TokenStream::from(to_cef_impl)
+/// This macro derives the ToOcsf trait on the annotated item.
+/// Like ToCef, the trait provides a useful implementation, so this macro
+/// only generates the item's OCSF class when asked for it with
+/// `#[ocsf(class_uid = 3002)]`. Items without one are Base Events.
+///
+#[proc_macro_derive(ToOcsf, attributes(ocsf))]
+pub fn derive_to_ocsf(item_tokens: TokenStream) -> TokenStream {
+    implement_to_ocsf_trait(item_tokens)
 }
 
 #[proc_macro_derive(CefHeaderVersion, attributes(cef_values, cef_inherit, cef_field))]
@@ -77,10 +80,56 @@ pub fn derive_cef_header_severity(item_tokens: TokenStream) -> TokenStream {
     implement_header_trait("CefHeaderSeverity", item_tokens)
 }
 
+/// This macro derives the CefExtensions trait on the annotated item.
+///
+/// Extension keys are checked at compile time: they must follow the CEF key
+/// grammar, and a key one typo away from a standard key (i.e. `scr`) is rejected
+/// with a suggestion unless `#[cef_ext_custom_keys]` is applied to the field,
+/// variant or item.
+///
+/// `#[cef_ext_custom(label = "Container ID", kind = "string")]` stores a field
+/// in the first free custom slot of that kind (i.e. `cs1`, `cs2`...), along with
+/// the slot's label key.
+///
+/// `#[cef_ext_field(suser, redact)]` writes the field's value as `***`, and
+/// `#[cef_ext_field(cs1, mask = "last4")]` keeps only its last four characters.
+/// `#[cef_ext_field(suser, pseudonymize)]` writes its keyed digest, computed with
+/// the secret in the encoding options' `RedactionPolicy` (and redacts it without one).
+///
 #[proc_macro_derive(
     CefExtensions,
-    attributes(cef_ext_field, cef_ext_gobble, cef_ext_values)
+    attributes(
+        cef_ext_field,
+        cef_ext_gobble,
+        cef_ext_values,
+        cef_ext_custom,
+        cef_ext_custom_keys
+    )
 )]
 pub fn derive_cef_extensions(input: TokenStream) -> TokenStream {
     implement_extensions_trait(input)
 }
+
+/// This macro derives the FromCef trait on the annotated item, reading
+/// headers and extensions back according to the same attributes used
+/// to derive the CefHeader* and CefExtensions traits.
+///
+/// `cef_field` and `cef_ext_field` read values using the `FromStr` trait,
+/// while `cef_inherit` and `cef_ext_gobble` read the field's own FromCef.
+///
+#[proc_macro_derive(
+    FromCef,
+    attributes(
+        cef_values,
+        cef_inherit,
+        cef_field,
+        cef_ext_field,
+        cef_ext_gobble,
+        cef_ext_values,
+        cef_ext_custom,
+        cef_ext_custom_keys
+    )
+)]
+pub fn derive_from_cef(input: TokenStream) -> TokenStream {
+    implement_from_cef_trait(input)
+}
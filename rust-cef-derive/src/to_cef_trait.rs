@@ -0,0 +1,117 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides functions to implement the ToCef trait
+use crate::helpers::{parse_attrs_to_name_value, ParseAttrResult};
+use crate::proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use std::convert::From;
+use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Error as SynError, Lit};
+
+const CEF_OPTIONS_USAGE: &str = "'cef_options' macro expects encoding options to be listed in the following syntax: #[cef_options(duplicate_keys = \"KeepFirst\", severity = \"Reject\")] ";
+
+/// The variants of rust_cef::DuplicateKeyPolicy
+const CEF_DUPLICATE_KEY_POLICIES: &[&str] = &["Error", "KeepFirst", "KeepLast", "AutoSuffix"];
+
+/// The variants of rust_cef::SeverityPolicy
+const CEF_SEVERITY_POLICIES: &[&str] = &["PassThrough", "Reject", "Clamp"];
+
+/// Implements the ToCef trait. The trait itself provides a useful
+/// implementation, so only the encoding options are generated, and
+/// only when the item asks for them with `#[cef_options(...)]`.
+pub fn implement_to_cef_trait(item_tokens: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item_tokens as DeriveInput);
+
+    // type name
+    let name = &item.ident;
+
+    // generics
+    let generics = &item.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let options = match encode_options(&item) {
+        Ok(options) => options,
+        Err(ts) => return TokenStream::from(ts),
+    };
+
+    let options_impl = match options.is_empty() {
+        // default implementation is great
+        true => quote! {},
+        false => quote! {
+            fn cef_encode_options(&self) -> rust_cef::CefEncodeOptions {
+                rust_cef::CefEncodeOptions::default()
+                    #(#options)*
+            }
+        },
+    };
+
+    let to_cef_impl = quote! {
+        impl #impl_generics rust_cef::ToCef for #name #ty_generics #where_clause {
+            #options_impl
+        }
+    };
+
+    TokenStream::from(to_cef_impl)
+}
+
+/// Looks for the #[cef_options] attribute on the item and returns
+/// the builder calls that apply each option.
+///
+/// For example:
+///
+/// ```ignore
+/// #[derive(ToCef)]
+/// #[cef_options(duplicate_keys = "Error", severity = "Reject")]
+/// struct Foo {
+/// }
+/// ```
+///
+fn encode_options(item: &DeriveInput) -> ParseAttrResult<Vec<TokenStream2>> {
+    let mut options = vec![];
+
+    for attr in item.attrs.iter().filter(|a| a.path.is_ident("cef_options")) {
+        for mnv in parse_attrs_to_name_value(attr, CEF_OPTIONS_USAGE)? {
+            let value = match &mnv.lit {
+                Lit::Str(strval) => strval.value(),
+                _ => {
+                    return Err(SynError::new(mnv.lit.span(), CEF_OPTIONS_USAGE).to_compile_error())
+                }
+            };
+
+            let option = mnv.path.get_ident().map(|ident| ident.to_string());
+            let (policy_type, policies, builder) = match option.as_deref() {
+                Some("duplicate_keys") => (
+                    "DuplicateKeyPolicy",
+                    CEF_DUPLICATE_KEY_POLICIES,
+                    "with_duplicate_keys",
+                ),
+                Some("severity") => ("SeverityPolicy", CEF_SEVERITY_POLICIES, "with_severity"),
+                _ => {
+                    return Err(SynError::new(mnv.path.span(), CEF_OPTIONS_USAGE).to_compile_error())
+                }
+            };
+
+            if !policies.contains(&value.as_str()) {
+                return Err(SynError::new(
+                    mnv.lit.span(),
+                    format!(
+                        "'{}' is not a {}. Expected one of: {}",
+                        value,
+                        policy_type,
+                        policies.join(",")
+                    ),
+                )
+                .to_compile_error());
+            }
+
+            let policy_type = format_ident!("{}", policy_type);
+            let policy = format_ident!("{}", value);
+            let builder = format_ident!("{}", builder);
+            options.push(quote! {
+                .#builder(rust_cef::#policy_type::#policy)
+            });
+        }
+    }
+
+    Ok(options)
+}
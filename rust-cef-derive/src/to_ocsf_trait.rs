@@ -0,0 +1,80 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides functions to implement the ToOcsf trait
+use crate::helpers::{parse_attrs_to_name_value, ParseAttrResult};
+use crate::proc_macro::TokenStream;
+use quote::quote;
+use std::convert::From;
+use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Error as SynError, Lit};
+
+const OCSF_USAGE: &str = "'ocsf' macro expects the item's OCSF class to be listed in the following syntax: #[ocsf(class_uid = 3002)] ";
+
+/// Implements the ToOcsf trait. The trait itself provides a useful
+/// implementation, so only the class is generated, and only when
+/// the item asks for one with `#[ocsf(class_uid = ...)]`.
+pub fn implement_to_ocsf_trait(item_tokens: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item_tokens as DeriveInput);
+
+    // type name
+    let name = &item.ident;
+
+    // generics
+    let generics = &item.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let class_uid = match class_uid(&item) {
+        Ok(class_uid) => class_uid,
+        Err(ts) => return TokenStream::from(ts),
+    };
+
+    let class_uid_impl = match class_uid {
+        // default implementation is great
+        None => quote! {},
+        Some(class_uid) => quote! {
+            fn ocsf_class_uid(&self) -> u32 {
+                #class_uid
+            }
+        },
+    };
+
+    let to_ocsf_impl = quote! {
+        impl #impl_generics rust_cef::ocsf::ToOcsf for #name #ty_generics #where_clause {
+            #class_uid_impl
+        }
+    };
+
+    TokenStream::from(to_ocsf_impl)
+}
+
+/// Looks for the #[ocsf] attribute on the item and returns the class it names.
+///
+/// For example:
+///
+/// ```ignore
+/// #[derive(ToOcsf)]
+/// #[ocsf(class_uid = 3002)]
+/// struct Foo {
+/// }
+/// ```
+///
+fn class_uid(item: &DeriveInput) -> ParseAttrResult<Option<u32>> {
+    let mut class_uid = None;
+
+    for attr in item.attrs.iter().filter(|a| a.path.is_ident("ocsf")) {
+        for mnv in parse_attrs_to_name_value(attr, OCSF_USAGE)? {
+            if !mnv.path.is_ident("class_uid") {
+                return Err(SynError::new(mnv.path.span(), OCSF_USAGE).to_compile_error());
+            }
+
+            class_uid = match &mnv.lit {
+                Lit::Int(intval) => match intval.base10_parse::<u32>() {
+                    Ok(value) => Some(value),
+                    Err(e) => return Err(e.to_compile_error()),
+                },
+                _ => return Err(SynError::new(mnv.lit.span(), OCSF_USAGE).to_compile_error()),
+            };
+        }
+    }
+
+    Ok(class_uid)
+}
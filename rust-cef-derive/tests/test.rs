@@ -1,11 +1,17 @@
 use rust_cef_derive::{
     CefExtensions, CefHeaderDeviceEventClassID, CefHeaderDeviceProduct, CefHeaderDeviceVendor,
-    CefHeaderDeviceVersion, CefHeaderName, CefHeaderSeverity, CefHeaderVersion, ToCef,
+    CefHeaderDeviceVersion, CefHeaderName, CefHeaderSeverity, CefHeaderVersion, FromCef, ToCef,
+    ToOcsf,
 };
 
-use rust_cef::{CefExtensions, CefHeaderName, CefHeaderVersion, ToCef};
+use rust_cef::redact::{Pseudonymizer, Redaction, RedactionPolicy};
+use rust_cef::{
+    CefConversionError, CefEncodeOptions, CefExtensions, CefHeaderName, CefHeaderVersion,
+    CefSeverity, DuplicateKeyPolicy, FromCef, LeefVersion, SeverityPolicy, ToCef, ToLeef, ToOcsf,
+};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::IpAddr;
 use time::OffsetDateTime;
 
 #[test]
@@ -13,6 +19,32 @@ fn test_cef_fixed_headers_fails() {
     let _t = trybuild::TestCases::new();
 }
 
+#[test]
+fn test_extension_keys_are_validated() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/ext_key_*.rs");
+    t.compile_fail("tests/ui/ext_values_*.rs");
+    t.pass("tests/ui/ext_custom_keys.rs");
+}
+
+#[test]
+fn test_custom_slots_are_counted() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/custom_slots_*.rs");
+}
+
+#[test]
+fn test_ocsf_class_is_validated() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/ocsf_*.rs");
+}
+
+#[test]
+fn test_redaction_is_validated() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/redact_*.rs");
+}
+
 #[test]
 fn test_cef_fixed_headers() {
     let sh = SingleHeader {};
@@ -46,6 +78,7 @@ fn test_to_cef_with_fixed_and_manual_headers() {
 }
 
 #[test]
+#[allow(clippy::unnecessary_to_owned)]
 fn test_cef_extensions() {
     let n1 = NameStruct {
         name: "WillBeRenamed".to_owned(),
@@ -90,6 +123,80 @@ fn test_cef_extensions() {
     );
 }
 
+// `name` and `host` are a typo away from `fname` and `shost`
+#[derive(CefExtensions)]
+#[cef_ext_custom_keys]
+struct CommonNames {
+    #[cef_ext_field]
+    name: String,
+
+    #[cef_ext_field]
+    host: String,
+
+    #[cef_ext_field]
+    r#type: u8,
+}
+
+#[test]
+fn test_extension_keys_from_common_and_raw_names() {
+    let names = CommonNames {
+        name: "login".to_owned(),
+        host: "web-1".to_owned(),
+        r#type: 1,
+    };
+    let mut collector = HashMap::<String, String>::new();
+    assert!(names.cef_extensions(&mut collector).is_ok());
+    assert_eq!(collector.get("name"), Some(&"login".to_owned()));
+    assert_eq!(collector.get("host"), Some(&"web-1".to_owned()));
+    assert_eq!(collector.get("type"), Some(&"1".to_owned()));
+}
+
+#[test]
+fn test_tuple_struct_inherits_header() {
+    let t = TupleStule(NameStruct {
+        name: "Inherited".to_owned(),
+    });
+    assert_eq!(t.cef_header_name().unwrap(), "Inherited");
+}
+
+#[test]
+fn test_cef_extensions_collectors() {
+    let n = NameInheritorStruct {
+        name_struct: NameStruct {
+            name: "NS1".to_owned(),
+        },
+        name_struct2: Some(NameStruct {
+            name: "NS 2=two".to_owned(),
+        }),
+        address: None,
+        age: 42,
+    };
+
+    let mut collector = Vec::<(String, String)>::new();
+    assert!(n.cef_extensions(&mut collector).is_ok());
+    assert_eq!(
+        collector,
+        vec![
+            (
+                "TopStructField".to_owned(),
+                "fixedExtensionsValue".to_owned()
+            ),
+            ("name2".to_owned(), "NameStruct::NS1".to_owned()),
+            ("newname".to_owned(), "NS 2=two".to_owned()),
+            ("person_age".to_owned(), "42".to_owned()),
+        ]
+    );
+
+    let mut output = String::new();
+    assert!(n
+        .cef_extensions(&mut rust_cef::CefWriterCollector::new(&mut output))
+        .is_ok());
+    assert_eq!(
+        output,
+        "TopStructField=fixedExtensionsValue name2=NameStruct::NS1 newname=NS 2\\=two person_age=42"
+    );
+}
+
 #[test]
 fn test_complete_to_cef() {
     let v1 = Top::V1(
@@ -157,6 +264,480 @@ fn test_complete_to_cef() {
     );
 }
 
+#[test]
+fn test_from_cef_struct_round_trip() {
+    let event = RoundTripEvent {
+        event_class: "ProcessStart".to_owned(),
+        name: "Process | Started".to_owned(),
+        severity: 7,
+        source: "10.0.0.1".parse().unwrap(),
+        message: Some("a message = with spaces".to_owned()),
+        timestamp: OffsetDateTime::from_unix_timestamp_nanos(735027350723000000).unwrap(),
+        process: ProcessInfo {
+            pid: 4242,
+            name: "C:\\Windows\\cmd.exe".to_owned(),
+        },
+        unannotated: 0,
+    };
+
+    let line = event.to_cef().unwrap();
+    assert_eq!(
+        line,
+        "CEF:0|polyverse|zerotect|V1|ProcessStart|Process \\| Started|7|message=a message \\= with spaces rt=735027350723 spid=4242 sproc=C:\\\\Windows\\\\cmd.exe src=10.0.0.1"
+    );
+    assert_eq!(RoundTripEvent::from_cef(&line).unwrap(), event);
+
+    let event = RoundTripEvent {
+        message: None,
+        ..event
+    };
+    assert_eq!(
+        RoundTripEvent::from_cef(&event.to_cef().unwrap()).unwrap(),
+        event
+    );
+}
+
+#[test]
+fn test_from_cef_struct_errors() {
+    // missing mandatory extension
+    assert_eq!(
+        RoundTripEvent::from_cef(
+            "CEF:0|polyverse|zerotect|V1|ProcessStart|Started|7|rt=735027350723 spid=4242 sproc=cmd"
+        ),
+        Err(CefConversionError::MissingExtension {
+            key: "src".to_owned()
+        })
+    );
+
+    // header that doesn't parse
+    assert!(matches!(
+        RoundTripEvent::from_cef(
+            "CEF:0|polyverse|zerotect|V1|ProcessStart|Started|High|rt=735027350723 spid=4242 sproc=cmd src=10.0.0.1"
+        ),
+        Err(CefConversionError::InvalidHeaderValue { header, value, .. }) if header == "CefHeaderSeverity" && value == "High"
+    ));
+
+    // gobbled extension that doesn't parse
+    let err = RoundTripEvent::from_cef(
+        "CEF:0|polyverse|zerotect|V1|ProcessStart|Started|7|rt=735027350723 spid=pid sproc=cmd src=10.0.0.1"
+    )
+    .unwrap_err();
+    assert_eq!(err.path(), vec!["process"]);
+    assert!(matches!(
+        err.innermost(),
+        CefConversionError::InvalidExtensionValue { key, value, .. } if key == "spid" && value == "pid"
+    ));
+}
+
+#[test]
+fn test_from_cef_optional_parts() {
+    assert_eq!(
+        OptionalParts::from_cef("CEF:0|polyverse|zerotect|V1|Started|Started||spid=1").unwrap(),
+        OptionalParts {
+            severity: None,
+            process: None,
+        }
+    );
+    assert_eq!(
+        OptionalParts::from_cef("CEF:0|polyverse|zerotect|V1|Started|Started|3|spid=1 sproc=sh")
+            .unwrap(),
+        OptionalParts {
+            severity: Some(3),
+            process: Some(ProcessInfo {
+                pid: 1,
+                name: "sh".to_owned(),
+            }),
+        }
+    );
+
+    // present but invalid values are errors, not None
+    assert!(matches!(
+        OptionalParts::from_cef("CEF:0|polyverse|zerotect|V1|Started|Started|High|"),
+        Err(CefConversionError::InvalidHeaderValue { header, .. }) if header == "CefHeaderSeverity"
+    ));
+    let err =
+        OptionalParts::from_cef("CEF:0|polyverse|zerotect|V1|Started|Started|3|spid=pid sproc=sh")
+            .unwrap_err();
+    assert_eq!(err.path(), vec!["process"]);
+    assert!(matches!(
+        err.innermost(),
+        CefConversionError::InvalidExtensionValue { key, .. } if key == "spid"
+    ));
+}
+
+#[test]
+fn test_nested_errors_carry_field_path() {
+    let outer = FailingOuter::Variant(FailingInner {
+        failing: FailingExtensions {},
+    });
+
+    let mut collector = HashMap::<String, String>::new();
+    let err = outer.cef_extensions(&mut collector).unwrap_err();
+    assert_eq!(err.path(), vec!["Variant", "0", "failing"]);
+    assert_eq!(
+        err.innermost(),
+        &CefConversionError::InvalidExtensionKey {
+            key: "bad key".to_owned()
+        }
+    );
+    assert_eq!(
+        format!("{}", err),
+        "Variant.0.failing: Invalid extension key 'bad key'"
+    );
+
+    let err = outer.cef_header_name().unwrap_err();
+    assert_eq!(err.path(), vec!["Variant", "0", "failing"]);
+    assert_eq!(
+        err.innermost(),
+        &CefConversionError::MissingHeader {
+            header: "CefHeaderName".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_from_cef_enum_round_trip() {
+    let started = ProcessEvent::Started(
+        "/bin/sh".to_owned(),
+        ProcessInfo {
+            pid: 1,
+            name: "sh".to_owned(),
+        },
+    );
+    let line = started.to_cef().unwrap();
+    assert_eq!(
+        line,
+        "CEF:0|polyverse|zerotect|V1|Started|/bin/sh|3|path=/bin/sh spid=1 sproc=sh"
+    );
+    assert_eq!(ProcessEvent::from_cef(&line).unwrap(), started);
+
+    let exited = ProcessEvent::Exited {
+        code: -1,
+        process: ProcessInfo {
+            pid: 2,
+            name: "sh".to_owned(),
+        },
+    };
+    let line = exited.to_cef().unwrap();
+    assert_eq!(
+        line,
+        "CEF:0|polyverse|zerotect|V1|Exited|Process Exited|5|code=-1 kind=exit spid=2 sproc=sh"
+    );
+    assert_eq!(ProcessEvent::from_cef(&line).unwrap(), exited);
+
+    let unknown = ProcessEvent::Unknown;
+    assert_eq!(
+        ProcessEvent::from_cef(&unknown.to_cef().unwrap()).unwrap(),
+        unknown
+    );
+
+    // Exited is only matched when all of its fixed values are present
+    assert!(ProcessEvent::from_cef(
+        "CEF:0|polyverse|zerotect|V1|Exited|Process Exited|5|code=-1 spid=2 sproc=sh"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_from_cef_enum_no_match() {
+    assert!(StrictProcessEvent::from_cef(
+        "CEF:0|polyverse|zerotect|V1|Exited|Process Exited|5|code=-1 spid=2 sproc=sh"
+    )
+    .is_err());
+    assert!(
+        StrictProcessEvent::from_cef("CEF:0|polyverse|zerotect|V1|Started|Process Started|5|")
+            .is_err()
+    );
+    assert_eq!(
+        StrictProcessEvent::from_cef(
+            "CEF:0|polyverse|zerotect|V1|Started|Process Started|5|path=/bin/sh"
+        )
+        .unwrap(),
+        StrictProcessEvent::Started("/bin/sh".to_owned())
+    );
+}
+
+#[test]
+fn test_duplicate_keys_report_fields() {
+    let event = DuplicateNames {
+        primary: NameStruct {
+            name: "First".to_owned(),
+        },
+        secondary: NameStruct {
+            name: "Second".to_owned(),
+        },
+    };
+
+    let err = event.to_cef().unwrap_err();
+    assert_eq!(err.path(), vec!["secondary"]);
+    assert_eq!(
+        err.innermost(),
+        &CefConversionError::DuplicateKey {
+            key: "newname".to_owned(),
+            first_field: Some("primary.name".to_owned()),
+            second_field: Some("secondary.name".to_owned()),
+        }
+    );
+
+    // streaming applies the type's policy too
+    let mut streamed = String::new();
+    assert_eq!(
+        event.write_cef(&mut streamed).unwrap_err().innermost(),
+        err.innermost()
+    );
+
+    // per-call options override the type's own
+    let options = CefEncodeOptions::default().with_duplicate_keys(DuplicateKeyPolicy::AutoSuffix);
+    assert_eq!(
+        event.to_cef_with_options(&options).unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Dup|Duplicate names|1|newname=First newname_2=Second"
+    );
+
+    // the default policy keeps the last value
+    let n = NameInheritorStruct {
+        name_struct: NameStruct {
+            name: "NS1".to_owned(),
+        },
+        name_struct2: Some(NameStruct {
+            name: "NS2".to_owned(),
+        }),
+        address: None,
+        age: 42,
+    };
+    let pair = DuplicateInheritors(n.name_struct.clone(), n);
+    let options = CefEncodeOptions::default();
+    assert!(pair
+        .to_cef_with_options(&options)
+        .unwrap()
+        .contains("newname=NS2"));
+
+    let options = options.with_duplicate_keys(DuplicateKeyPolicy::KeepFirst);
+    assert!(pair
+        .to_cef_with_options(&options)
+        .unwrap()
+        .contains("newname=NS1"));
+}
+
+#[test]
+fn test_typed_severity() {
+    let alert = Alert {
+        severity: CefSeverity::VeryHigh,
+        level: 85,
+    };
+    assert_eq!(
+        alert.to_cef().unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Alert|Alert raised|Very-High|level=85"
+    );
+    assert_eq!(Alert::from_cef(&alert.to_cef().unwrap()).unwrap(), alert);
+    assert!(Alert::from_cef("CEF:0|polyverse|zerotect|V1|Alert|Alert raised|11|level=85").is_err());
+
+    // out of range severities are rejected by the type's own policy...
+    let strict = StrictAlert { level: 85 };
+    assert_eq!(
+        strict.to_cef(),
+        Err(CefConversionError::InvalidHeaderValue {
+            header: "CefHeaderSeverity".to_owned(),
+            value: "85".to_owned(),
+            reason: "severity must be between 0 and 10".to_owned(),
+        })
+    );
+    let mut output = String::new();
+    assert!(strict.write_cef(&mut output).is_err());
+
+    // ...or clamped per call
+    let options = CefEncodeOptions::default().with_severity(SeverityPolicy::Clamp);
+    assert_eq!(
+        strict.to_cef_with_options(&options).unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Alert|Strict alert|10|"
+    );
+}
+
+#[test]
+fn test_custom_slots() {
+    let event = ContainerEvent {
+        container_id: "abc123".to_owned(),
+        image: Some("nginx:1.19".to_owned()),
+        restarts: 4,
+        pod: PodInfo {
+            pod: "web-1".to_owned(),
+        },
+    };
+
+    // the gobbled PodInfo takes the first free slot after its siblings
+    let cef = event.to_cef().unwrap();
+    assert_eq!(
+        cef,
+        "CEF:0|polyverse|zerotect|V1|Container|Container restarted|3|cn1=4 cn1Label=Restarts cs1=abc123 cs1Label=Container ID cs2=nginx:1.19 cs2Label=Image cs3=web-1 cs3Label=Pod"
+    );
+    assert_eq!(ContainerEvent::from_cef(&cef).unwrap(), event);
+
+    // a missing optional field doesn't take a slot
+    let event = ContainerEvent {
+        image: None,
+        ..event
+    };
+    let cef = event.to_cef().unwrap();
+    assert!(cef.ends_with("cs1=abc123 cs1Label=Container ID cs2=web-1 cs2Label=Pod"));
+    assert_eq!(ContainerEvent::from_cef(&cef).unwrap(), event);
+
+    assert_eq!(
+        ContainerEvent::from_cef("CEF:0|polyverse|zerotect|V1|Container|Container restarted|3|cn1=4 cn1Label=Restarts cs1=web-1 cs1Label=Pod"),
+        Err(CefConversionError::MissingExtension {
+            key: "Container ID".to_owned()
+        })
+    );
+
+    // slots may still run out when gobbled items take them
+    let crowded = CrowdedEvent {
+        c1: "1".to_owned(),
+        c2: "2".to_owned(),
+        c3: "3".to_owned(),
+        c4: "4".to_owned(),
+        c5: "5".to_owned(),
+        pod: PodInfo {
+            pod: "web-1".to_owned(),
+        },
+        pod2: PodInfo {
+            pod: "web-2".to_owned(),
+        },
+    };
+    let err = crowded.to_cef().unwrap_err();
+    assert_eq!(err.path(), vec!["pod2"]);
+    assert_eq!(
+        err.innermost(),
+        &CefConversionError::CustomSlotsExhausted {
+            kind: "string".to_owned(),
+            label: "Pod".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn test_to_leef() {
+    let alert = Alert {
+        severity: CefSeverity::VeryHigh,
+        level: 85,
+    };
+    assert_eq!(
+        alert.to_leef().unwrap(),
+        "LEEF:1.0|polyverse|zerotect|V1|Alert|sev=10\tlevel=85"
+    );
+
+    let event = ContainerEvent {
+        container_id: "abc123".to_owned(),
+        image: None,
+        restarts: 4,
+        pod: PodInfo {
+            pod: "web-1".to_owned(),
+        },
+    };
+    assert_eq!(
+        event
+            .to_leef_with_version(LeefVersion::V2 { delimiter: '^' })
+            .unwrap(),
+        "LEEF:2.0|polyverse|zerotect|V1|Container|^|sev=3^cn1=4^cn1Label=Restarts^cs1=abc123^cs1Label=Container ID^cs2=web-1^cs2Label=Pod"
+    );
+}
+
+#[test]
+fn test_to_ocsf() {
+    let alert = Alert {
+        severity: CefSeverity::VeryHigh,
+        level: 85,
+    };
+    assert_eq!(alert.ocsf_class_uid(), 2004);
+    assert!(alert.to_ocsf().unwrap().starts_with(
+        r#"{"class_uid":2004,"category_uid":2,"activity_id":0,"type_uid":200400,"severity_id":5,"#
+    ));
+
+    let event = ContainerEvent {
+        container_id: "abc123".to_owned(),
+        image: None,
+        restarts: 4,
+        pod: PodInfo {
+            pod: "web-1".to_owned(),
+        },
+    };
+    assert_eq!(event.ocsf_class_uid(), 0);
+    assert!(event
+        .to_ocsf()
+        .unwrap()
+        .contains(r#""unmapped":{"cn1":4,"cn1Label":"Restarts","cs1":"abc123","cs1Label":"Container ID","cs2":"web-1","cs2Label":"Pod"},"time":"#));
+}
+
+#[test]
+fn test_redacted_fields() {
+    let attempt = LoginAttempt {
+        username: "alice".to_owned(),
+        token: "tok-abcdwxyz".to_owned(),
+        path: Some("/home/alice/.ssh/id_rsa".to_owned()),
+        source: "10.0.0.1".parse().unwrap(),
+    };
+    assert_eq!(
+        attempt.to_cef().unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Login|Login attempted|3|cs1=***wxyz filePath=/home/alice/.ssh/id_rsa src=10.0.0.1 suser=***"
+    );
+
+    // written the same way when streamed
+    let mut streamed = String::new();
+    assert!(attempt.write_cef(&mut streamed).is_ok());
+    assert_eq!(
+        streamed,
+        "CEF:0|polyverse|zerotect|V1|Login|Login attempted|3|suser=*** cs1=***wxyz filePath=/home/alice/.ssh/id_rsa src=10.0.0.1"
+    );
+
+    // a runtime policy hides more keys, whichever type produces them
+    let options = CefEncodeOptions::default().with_redaction(
+        RedactionPolicy::new()
+            .with_key("filePath", Redaction::MaskLast(6))
+            .with_key("src", Redaction::Redact),
+    );
+    assert_eq!(
+        attempt.to_cef_with_options(&options).unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Login|Login attempted|3|cs1=***wxyz filePath=***id_rsa src=*** suser=***"
+    );
+}
+
+#[test]
+fn test_pseudonymized_fields() {
+    let pseudonymizer = Pseudonymizer::new(b"correlation secret").with_length(12);
+    let alice = pseudonymizer.pseudonym("alice");
+    let bob = pseudonymizer.pseudonym("bob");
+    let options = CefEncodeOptions::default()
+        .with_redaction(RedactionPolicy::new().with_pseudonymizer(pseudonymizer));
+
+    let event = Impersonation {
+        username: "alice".to_owned(),
+        target: "bob".to_owned(),
+    };
+    assert_eq!(
+        event.to_cef_with_options(&options).unwrap(),
+        format!(
+            "CEF:0|polyverse|zerotect|V1|Impersonation|User impersonated|7|duser={} suser={}",
+            bob, alice
+        )
+    );
+
+    // the same user always gets the same pseudonym
+    let event = Impersonation {
+        username: "bob".to_owned(),
+        target: "alice".to_owned(),
+    };
+    assert_eq!(
+        event.to_cef_with_options(&options).unwrap(),
+        format!(
+            "CEF:0|polyverse|zerotect|V1|Impersonation|User impersonated|7|duser={} suser={}",
+            alice, bob
+        )
+    );
+
+    // without a secret, nothing is disclosed
+    assert_eq!(
+        event.to_cef().unwrap(),
+        "CEF:0|polyverse|zerotect|V1|Impersonation|User impersonated|7|duser=*** suser=***"
+    );
+}
+
 /**************************** Test Structs ******************************************/
 
 #[derive(CefHeaderVersion, CefHeaderName)]
@@ -196,10 +777,9 @@ struct AllFixedHeadersCustomExtensions {}
 impl CefExtensions for AllFixedHeadersCustomExtensions {
     fn cef_extensions(
         &self,
-        collector: &mut HashMap<String, String>,
+        collector: &mut dyn rust_cef::CefCollector,
     ) -> rust_cef::CefExtensionsResult {
-        collector.insert("extension1".to_owned(), "value1".to_owned());
-        Ok(())
+        collector.insert("extension1", "value1")
     }
 }
 
@@ -315,7 +895,7 @@ impl Display for NameInheritorStruct {
     }
 }
 
-#[derive(CefHeaderName, CefExtensions)]
+#[derive(CefHeaderName, CefExtensions, Clone)]
 struct NameStruct {
     // use the field's name
     #[cef_ext_field(newname)]
@@ -328,3 +908,416 @@ impl Display for NameStruct {
         write!(f, "NameStruct::{}", self.name)
     }
 }
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+    FromCef,
+    Debug,
+    PartialEq,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1"
+)]
+struct RoundTripEvent {
+    #[cef_field(CefHeaderDeviceEventClassID)]
+    event_class: String,
+
+    #[cef_field(CefHeaderName)]
+    name: String,
+
+    #[cef_field(CefHeaderSeverity)]
+    severity: u8,
+
+    #[cef_ext_field(src)]
+    source: IpAddr,
+
+    #[cef_ext_field]
+    message: Option<String>,
+
+    #[cef_ext_gobble]
+    timestamp: OffsetDateTime,
+
+    #[cef_ext_gobble]
+    process: ProcessInfo,
+
+    // not in CEF at all, so it comes back as Default
+    unannotated: usize,
+}
+
+#[derive(CefExtensions, FromCef, Debug, PartialEq)]
+struct ProcessInfo {
+    #[cef_ext_field(spid)]
+    pid: u32,
+
+    #[cef_ext_field(sproc)]
+    name: String,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+    FromCef,
+    Debug,
+    PartialEq,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1"
+)]
+enum ProcessEvent {
+    #[cef_values(CefHeaderDeviceEventClassID = "Started", CefHeaderSeverity = "3")]
+    Started(
+        #[cef_field(CefHeaderName)]
+        #[cef_ext_field(path)]
+        String,
+        #[cef_ext_gobble] ProcessInfo,
+    ),
+
+    #[cef_values(
+        CefHeaderDeviceEventClassID = "Exited",
+        CefHeaderName = "Process Exited",
+        CefHeaderSeverity = "5"
+    )]
+    #[cef_ext_values(kind = "exit")]
+    Exited {
+        #[cef_ext_field]
+        code: i32,
+
+        #[cef_ext_gobble]
+        process: ProcessInfo,
+    },
+
+    #[cef_values(
+        CefHeaderDeviceEventClassID = "Unknown",
+        CefHeaderName = "Unknown",
+        CefHeaderSeverity = "0"
+    )]
+    Unknown,
+}
+
+#[derive(FromCef, Debug, PartialEq)]
+struct OptionalParts {
+    #[cef_field(CefHeaderSeverity)]
+    severity: Option<u8>,
+
+    #[cef_ext_gobble]
+    process: Option<ProcessInfo>,
+}
+
+#[derive(FromCef, Debug, PartialEq)]
+enum StrictProcessEvent {
+    #[cef_values(CefHeaderDeviceEventClassID = "Started")]
+    Started(#[cef_ext_field(path)] String),
+}
+
+struct FailingExtensions {}
+
+impl CefExtensions for FailingExtensions {
+    fn cef_extensions(
+        &self,
+        _collector: &mut dyn rust_cef::CefCollector,
+    ) -> rust_cef::CefExtensionsResult {
+        Err(CefConversionError::InvalidExtensionKey {
+            key: "bad key".to_owned(),
+        })
+    }
+}
+
+impl CefHeaderName for FailingExtensions {
+    fn cef_header_name(&self) -> rust_cef::CefResult {
+        Err(CefConversionError::MissingHeader {
+            header: "CefHeaderName".to_owned(),
+        })
+    }
+}
+
+#[derive(CefExtensions, CefHeaderName)]
+struct FailingInner {
+    #[cef_ext_gobble]
+    #[cef_inherit(CefHeaderName)]
+    failing: FailingExtensions,
+}
+
+#[derive(CefExtensions, CefHeaderName)]
+enum FailingOuter {
+    Variant(
+        #[cef_ext_gobble]
+        #[cef_inherit(CefHeaderName)]
+        FailingInner,
+    ),
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Dup",
+    CefHeaderName = "Duplicate names",
+    CefHeaderSeverity = "1"
+)]
+#[cef_options(duplicate_keys = "Error")]
+struct DuplicateNames {
+    #[cef_ext_gobble]
+    primary: NameStruct,
+
+    #[cef_ext_gobble]
+    secondary: NameStruct,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Dup",
+    CefHeaderName = "Duplicate inheritors",
+    CefHeaderSeverity = "1"
+)]
+struct DuplicateInheritors(
+    #[cef_ext_gobble] NameStruct,
+    #[cef_ext_gobble] NameInheritorStruct,
+);
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToOcsf,
+    ToCef,
+    FromCef,
+    Debug,
+    PartialEq,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Alert",
+    CefHeaderName = "Alert raised"
+)]
+#[ocsf(class_uid = 2004)]
+struct Alert {
+    #[cef_field(CefHeaderSeverity)]
+    severity: CefSeverity,
+
+    #[cef_ext_field]
+    level: u8,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Alert",
+    CefHeaderName = "Strict alert"
+)]
+#[cef_options(severity = "Reject")]
+struct StrictAlert {
+    #[cef_field(CefHeaderSeverity)]
+    level: u8,
+}
+
+#[derive(CefExtensions, FromCef, Debug, PartialEq)]
+struct PodInfo {
+    #[cef_ext_custom(label = "Pod", kind = "string")]
+    pod: String,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToOcsf,
+    ToCef,
+    FromCef,
+    Debug,
+    PartialEq,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Container",
+    CefHeaderName = "Container restarted",
+    CefHeaderSeverity = "3"
+)]
+#[cef_options(duplicate_keys = "Error")]
+struct ContainerEvent {
+    #[cef_ext_custom(label = "Container ID", kind = "string")]
+    container_id: String,
+
+    #[cef_ext_custom(label = "Image", kind = "string")]
+    image: Option<String>,
+
+    #[cef_ext_custom(label = "Restarts", kind = "number")]
+    restarts: u32,
+
+    #[cef_ext_gobble]
+    pod: PodInfo,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Crowded",
+    CefHeaderName = "Crowded",
+    CefHeaderSeverity = "3"
+)]
+struct CrowdedEvent {
+    #[cef_ext_custom(label = "C1", kind = "string")]
+    c1: String,
+    #[cef_ext_custom(label = "C2", kind = "string")]
+    c2: String,
+    #[cef_ext_custom(label = "C3", kind = "string")]
+    c3: String,
+    #[cef_ext_custom(label = "C4", kind = "string")]
+    c4: String,
+    #[cef_ext_custom(label = "C5", kind = "string")]
+    c5: String,
+    #[cef_ext_gobble]
+    pod: PodInfo,
+    #[cef_ext_gobble]
+    pod2: PodInfo,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Login",
+    CefHeaderName = "Login attempted",
+    CefHeaderSeverity = "3"
+)]
+struct LoginAttempt {
+    #[cef_ext_field(suser, redact)]
+    username: String,
+
+    #[cef_ext_field(cs1, mask = "last4")]
+    token: String,
+
+    #[cef_ext_field(filePath)]
+    path: Option<String>,
+
+    #[cef_ext_field(src)]
+    source: IpAddr,
+}
+
+#[derive(
+    CefHeaderVersion,
+    CefHeaderDeviceVendor,
+    CefHeaderDeviceProduct,
+    CefHeaderDeviceVersion,
+    CefHeaderDeviceEventClassID,
+    CefHeaderName,
+    CefHeaderSeverity,
+    CefExtensions,
+    ToCef,
+)]
+#[cef_values(
+    CefHeaderVersion = "0",
+    CefHeaderDeviceVendor = "polyverse",
+    CefHeaderDeviceProduct = "zerotect",
+    CefHeaderDeviceVersion = "V1",
+    CefHeaderDeviceEventClassID = "Impersonation",
+    CefHeaderName = "User impersonated",
+    CefHeaderSeverity = "7"
+)]
+struct Impersonation {
+    #[cef_ext_field(suser, pseudonymize)]
+    username: String,
+
+    #[cef_ext_field(duser, pseudonymize)]
+    target: String,
+}
@@ -0,0 +1,9 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+struct Labelled {
+    #[cef_ext_custom(label = "Name", kind = "text")]
+    name: String,
+}
+
+fn main() {}
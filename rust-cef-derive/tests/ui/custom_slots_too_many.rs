@@ -0,0 +1,15 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+struct Counters {
+    #[cef_ext_custom(label = "First", kind = "number")]
+    first: u32,
+    #[cef_ext_custom(label = "Second", kind = "number")]
+    second: u32,
+    #[cef_ext_custom(label = "Third", kind = "number")]
+    third: u32,
+    #[cef_ext_custom(label = "Fourth", kind = "number")]
+    fourth: u32,
+}
+
+fn main() {}
@@ -0,0 +1,24 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+#[cef_ext_values(SRC = "10.0.0.1")]
+#[cef_ext_custom_keys]
+struct CustomItem {
+    #[cef_ext_field(scr)]
+    source: String,
+}
+
+#[derive(CefExtensions)]
+enum CustomVariants {
+    #[cef_ext_custom_keys]
+    #[cef_ext_values(mgs = "variant")]
+    Variant,
+
+    Field(
+        #[cef_ext_field(suser1)]
+        #[cef_ext_custom_keys]
+        String,
+    ),
+}
+
+fn main() {}
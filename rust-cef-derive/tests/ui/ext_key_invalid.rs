@@ -0,0 +1,9 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+struct Event {
+    #[cef_ext_field(größe)]
+    size: u64,
+}
+
+fn main() {}
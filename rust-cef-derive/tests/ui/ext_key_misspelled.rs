@@ -0,0 +1,9 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+struct Connection {
+    #[cef_ext_field(scr)]
+    source: String,
+}
+
+fn main() {}
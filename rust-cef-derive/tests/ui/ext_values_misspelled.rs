@@ -0,0 +1,7 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+#[cef_ext_values(SRC = "10.0.0.1")]
+struct Connection {}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use rust_cef_derive::ToOcsf;
+
+#[derive(ToOcsf)]
+#[ocsf(class_uid = "Detection Finding")]
+struct Alert {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use rust_cef_derive::FromCef;
+use std::net::IpAddr;
+
+#[derive(FromCef)]
+struct Login {
+    #[cef_ext_field(src, redact)]
+    source: IpAddr,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rust_cef_derive::CefExtensions;
+
+#[derive(CefExtensions)]
+struct Login {
+    #[cef_ext_field(suser, mask = "first4")]
+    username: String,
+}
+
+fn main() {}
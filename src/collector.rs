@@ -0,0 +1,645 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides the CefCollector trait which receives CEF extensions
+/// from `CefExtensions` implementations, along with implementations for
+/// common collections and a collector that streams straight into a writer.
+use crate::escape::ExtensionEscaper;
+use crate::redact::REDACTED;
+use crate::{
+    is_valid_extension_key, CefConversionError, CefCustomKind, CefExtensionsResult,
+    DuplicateKeyPolicy,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Arguments, Write};
+use std::ops::Range;
+
+/// A destination for CEF extensions.
+///
+/// `CefExtensions` implementations hand every key/value pair they produce
+/// to a collector. Keys and values are borrowed, so collectors only allocate
+/// when they need to own the data.
+pub trait CefCollector {
+    /// Collects an extension whose value is already a string
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult;
+
+    /// Collects an extension whose value is formatted (i.e. with `format_args!`).
+    ///
+    /// The default implementation formats the value into a string and calls `insert`,
+    /// collectors that can write formatted values directly should override it.
+    fn insert_fmt(&mut self, key: &str, value: Arguments) -> CefExtensionsResult {
+        match value.as_str() {
+            Some(value) => self.insert(key, value),
+            None => self.insert(key, &value.to_string()),
+        }
+    }
+
+    /// Has this key been collected already? Used to find free custom slots.
+    ///
+    /// The default implementation can't tell and returns false,
+    /// collectors that store extensions should override it.
+    fn contains_key(&self, _key: &str) -> bool {
+        false
+    }
+
+    /// Collects a value into the first free custom slot of the given kind
+    /// (i.e. `cs1` when neither `cs1` nor `cs1Label` were collected, otherwise `cs2`...),
+    /// along with the slot's label key.
+    ///
+    /// Fails with `CustomSlotsExhausted` when every slot of that kind is taken.
+    fn insert_custom(
+        &mut self,
+        kind: CefCustomKind,
+        label: &str,
+        value: Arguments,
+    ) -> CefExtensionsResult {
+        let free_slot = kind
+            .slots()
+            .iter()
+            .find(|(slot, label_key)| !self.contains_key(slot) && !self.contains_key(label_key));
+
+        match free_slot {
+            Some((slot, label_key)) => {
+                self.insert(label_key, label)?;
+                self.insert_fmt(slot, value)
+            }
+            None => Err(CefConversionError::CustomSlotsExhausted {
+                kind: kind.to_string(),
+                label: label.to_owned(),
+            }),
+        }
+    }
+
+    /// Collects the pseudonym of a value (i.e. for `#[cef_ext_field(suser, pseudonymize)]`).
+    ///
+    /// Pseudonyms need a secret, which only a `RedactingCollector` knows (from its
+    /// `RedactionPolicy`). The default implementation redacts the value instead.
+    fn insert_pseudonymized(&mut self, key: &str, _value: Arguments) -> CefExtensionsResult {
+        self.insert(key, REDACTED)
+    }
+
+    /// Called (i.e. by derived implementations) before collecting the
+    /// extensions produced by a field, so collectors can tell which field
+    /// a key came from. Calls nest when a field gobbles another item.
+    ///
+    /// The default implementation ignores it.
+    fn enter_field(&mut self, _field: &str) {}
+
+    /// Called after the extensions of the field most recently passed
+    /// to `enter_field` were collected. It may be skipped when collection
+    /// fails part-way.
+    fn exit_field(&mut self) {}
+}
+
+/// Collects into a HashMap. A key collected more than once
+/// keeps the last value.
+impl CefCollector for HashMap<String, String> {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        HashMap::insert(self, key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        HashMap::contains_key(self, key)
+    }
+}
+
+/// Collects into a BTreeMap, which keeps keys sorted. A key collected
+/// more than once keeps the last value.
+impl CefCollector for BTreeMap<String, String> {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        BTreeMap::insert(self, key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        BTreeMap::contains_key(self, key)
+    }
+}
+
+/// Collects into a Vec in the order extensions were produced.
+/// A key collected more than once appears more than once.
+impl CefCollector for Vec<(String, String)> {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        self.push((key.to_owned(), value.to_owned()));
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.iter().any(|(k, _)| k == key)
+    }
+}
+
+/// Streams extensions into a writer as space-separated `key=value` pairs,
+/// escaping values as they are written.
+///
+/// Values aren't buffered, so extensions appear in the order they were produced.
+/// Keys are remembered (along with the field that produced them, as reported
+/// through `enter_field`) in a single growing buffer rather than one allocation
+/// per key, so a key collected more than once is resolved by a `DuplicateKeyPolicy`.
+///
+/// A value already written can't be taken back, so on its own the collector
+/// writes every value of a key under `KeepLast` (which is also what `new` does).
+/// `ToCef::write_cef` counts the keys first, so only the last value is written.
+pub struct CefWriterCollector<'a, W: Write + ?Sized> {
+    writer: &'a mut W,
+    policy: DuplicateKeyPolicy,
+    fields: FieldPath,
+    keys: SeenKeys,
+    // how often each key will be collected, when counted beforehand
+    totals: Option<SeenKeys>,
+    written: bool,
+}
+
+impl<'a, W: Write + ?Sized> CefWriterCollector<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            policy: DuplicateKeyPolicy::KeepLast,
+            fields: FieldPath::default(),
+            keys: SeenKeys::default(),
+            totals: None,
+            written: false,
+        }
+    }
+
+    /// Returns this collector resolving keys collected more than once
+    /// with the given policy
+    pub fn with_duplicate_keys(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns this collector writing only the last value of every key
+    /// under `KeepLast`, given how often each key will be collected
+    pub(crate) fn with_key_counts(mut self, totals: SeenKeys) -> Self {
+        self.totals = Some(totals);
+        self
+    }
+
+    /// Writes the separator and key, so the value may follow. Returns
+    /// false when the duplicate key policy drops the value instead.
+    fn write_key(&mut self, key: &str) -> Result<bool, CefConversionError> {
+        if !is_valid_extension_key(key) {
+            return Err(CefConversionError::InvalidExtensionKey {
+                key: key.to_owned(),
+            });
+        }
+
+        let seen = self.keys.find(key);
+        let suffixed = match (seen, self.policy) {
+            (None, _) => None,
+            (Some(_), DuplicateKeyPolicy::KeepLast) => None,
+            (Some(_), DuplicateKeyPolicy::KeepFirst) => return Ok(false),
+            (Some(index), DuplicateKeyPolicy::Error) => {
+                return Err(CefConversionError::DuplicateKey {
+                    key: key.to_owned(),
+                    first_field: self.keys.field(index).map(str::to_owned),
+                    second_field: self.fields.current().map(str::to_owned),
+                })
+            }
+            (Some(_), DuplicateKeyPolicy::AutoSuffix) => (2..)
+                .map(|n| format!("{}_{}", key, n))
+                .find(|suffixed| self.keys.find(suffixed).is_none()),
+        };
+        let key = suffixed.as_deref().unwrap_or(key);
+
+        let count = self.keys.record(key, self.fields.current());
+        if self.policy == DuplicateKeyPolicy::KeepLast {
+            // earlier values of a key collected again later are skipped
+            if let Some(total) = self.totals.as_ref().and_then(|totals| totals.count(key)) {
+                if count < total {
+                    return Ok(false);
+                }
+            }
+        }
+
+        if self.written {
+            self.writer.write_char(' ')?;
+        }
+        self.written = true;
+        self.writer.write_str(key)?;
+        self.writer.write_char('=')?;
+        Ok(true)
+    }
+}
+
+impl<'a, W: Write + ?Sized> CefCollector for CefWriterCollector<'a, W> {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        if self.write_key(key)? {
+            ExtensionEscaper(self.writer).write_str(value)?;
+        }
+        Ok(())
+    }
+
+    fn insert_fmt(&mut self, key: &str, value: Arguments) -> CefExtensionsResult {
+        if self.write_key(key)? {
+            ExtensionEscaper(self.writer).write_fmt(value)?;
+        }
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.keys.find(key).is_some()
+    }
+
+    fn enter_field(&mut self, field: &str) {
+        self.fields.enter(field);
+    }
+
+    fn exit_field(&mut self) {
+        self.fields.exit();
+    }
+}
+
+/// Counts how often every key is collected, without keeping values, so
+/// a `CefWriterCollector` can tell which value of a key is the last one.
+#[derive(Default)]
+pub(crate) struct KeyCounter {
+    pub(crate) keys: SeenKeys,
+}
+
+impl CefCollector for KeyCounter {
+    fn insert(&mut self, key: &str, _value: &str) -> CefExtensionsResult {
+        self.keys.record(key, None);
+        Ok(())
+    }
+
+    fn insert_fmt(&mut self, key: &str, _value: Arguments) -> CefExtensionsResult {
+        self.keys.record(key, None);
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.keys.find(key).is_some()
+    }
+}
+
+/// The dotted path of the fields being collected, i.e. `outer.inner`
+#[derive(Default)]
+struct FieldPath {
+    path: String,
+    starts: Vec<usize>,
+}
+
+impl FieldPath {
+    fn enter(&mut self, field: &str) {
+        self.starts.push(self.path.len());
+        if !self.path.is_empty() {
+            self.path.push('.');
+        }
+        self.path.push_str(field);
+    }
+
+    fn exit(&mut self) {
+        if let Some(start) = self.starts.pop() {
+            self.path.truncate(start);
+        }
+    }
+
+    fn current(&self) -> Option<&str> {
+        match self.path.is_empty() {
+            true => None,
+            false => Some(&self.path),
+        }
+    }
+}
+
+/// Keys collected so far, with the field that first produced them and how
+/// often they were collected. Keys and fields share one buffer.
+#[derive(Default)]
+pub(crate) struct SeenKeys {
+    buffer: String,
+    keys: Vec<SeenKey>,
+}
+
+struct SeenKey {
+    key: Range<usize>,
+    field: Option<Range<usize>>,
+    count: usize,
+}
+
+impl SeenKeys {
+    fn find(&self, key: &str) -> Option<usize> {
+        self.keys
+            .iter()
+            .position(|seen| &self.buffer[seen.key.clone()] == key)
+    }
+
+    fn field(&self, index: usize) -> Option<&str> {
+        self.keys[index]
+            .field
+            .clone()
+            .map(|field| &self.buffer[field])
+    }
+
+    fn count(&self, key: &str) -> Option<usize> {
+        self.find(key).map(|index| self.keys[index].count)
+    }
+
+    /// Records another occurrence of the key, returning how often it was seen
+    fn record(&mut self, key: &str, field: Option<&str>) -> usize {
+        if let Some(index) = self.find(key) {
+            self.keys[index].count += 1;
+            return self.keys[index].count;
+        }
+
+        let key = self.push(key);
+        let field = field.map(|field| self.push(field));
+        self.keys.push(SeenKey {
+            key,
+            field,
+            count: 1,
+        });
+        1
+    }
+
+    fn push(&mut self, text: &str) -> Range<usize> {
+        let start = self.buffer.len();
+        self.buffer.push_str(text);
+        start..self.buffer.len()
+    }
+}
+
+/// Collects into a BTreeMap (sorted by key), resolving keys collected more
+/// than once according to a `DuplicateKeyPolicy`. It remembers the field
+/// (as reported through `enter_field`) that produced every key, so conflicts
+/// can name both fields.
+pub(crate) struct DedupCollector {
+    policy: DuplicateKeyPolicy,
+    fields: Vec<String>,
+    extensions: BTreeMap<String, (String, Option<String>)>,
+}
+
+impl DedupCollector {
+    pub fn new(policy: DuplicateKeyPolicy) -> Self {
+        Self {
+            policy,
+            fields: vec![],
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    pub fn into_extensions(self) -> BTreeMap<String, String> {
+        self.extensions
+            .into_iter()
+            .map(|(key, (value, _))| (key, value))
+            .collect()
+    }
+
+    fn current_field(&self) -> Option<String> {
+        match self.fields.is_empty() {
+            true => None,
+            false => Some(self.fields.join(".")),
+        }
+    }
+}
+
+impl CefCollector for DedupCollector {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        let field = self.current_field();
+
+        let first_field = match self.extensions.get(key) {
+            None => {
+                self.extensions
+                    .insert(key.to_owned(), (value.to_owned(), field));
+                return Ok(());
+            }
+            Some((_, first_field)) => first_field.clone(),
+        };
+
+        match self.policy {
+            DuplicateKeyPolicy::Error => Err(CefConversionError::DuplicateKey {
+                key: key.to_owned(),
+                first_field,
+                second_field: field,
+            }),
+            DuplicateKeyPolicy::KeepFirst => Ok(()),
+            DuplicateKeyPolicy::KeepLast => {
+                self.extensions
+                    .insert(key.to_owned(), (value.to_owned(), field));
+                Ok(())
+            }
+            DuplicateKeyPolicy::AutoSuffix => {
+                let suffixed = (2..)
+                    .map(|n| format!("{}_{}", key, n))
+                    .find(|suffixed| !self.extensions.contains_key(suffixed))
+                    .unwrap();
+                self.extensions.insert(suffixed, (value.to_owned(), field));
+                Ok(())
+            }
+        }
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.extensions.contains_key(key)
+    }
+
+    fn enter_field(&mut self, field: &str) {
+        self.fields.push(field.to_owned());
+    }
+
+    fn exit_field(&mut self) {
+        self.fields.pop();
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn collect(collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        collector.insert("b", "first")?;
+        collector.insert_fmt("a", format_args!("{}={}", 1, 2))?;
+        collector.insert("b", "second")
+    }
+
+    #[test]
+    fn test_hashmap_collector() {
+        let mut collector = HashMap::<String, String>::new();
+        assert!(collect(&mut collector).is_ok());
+        assert_eq!(collector.len(), 2);
+        assert_eq!(collector.get("a").unwrap(), "1=2");
+        assert_eq!(collector.get("b").unwrap(), "second");
+    }
+
+    #[test]
+    fn test_btreemap_collector() {
+        let mut collector = BTreeMap::<String, String>::new();
+        assert!(collect(&mut collector).is_ok());
+        let keys: Vec<&String> = collector.keys().collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(collector.get("b").unwrap(), "second");
+    }
+
+    #[test]
+    fn test_vec_collector() {
+        let mut collector = Vec::<(String, String)>::new();
+        assert!(collect(&mut collector).is_ok());
+        assert_eq!(
+            collector,
+            vec![
+                ("b".to_owned(), "first".to_owned()),
+                ("a".to_owned(), "1=2".to_owned()),
+                ("b".to_owned(), "second".to_owned()),
+            ]
+        );
+    }
+
+    fn collect_from_fields(collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        collector.insert("a", "top")?;
+        collector.enter_field("outer");
+        collector.enter_field("inner");
+        collector.insert("b", "first")?;
+        collector.exit_field();
+        collector.insert("a", "outer")?;
+        collector.exit_field();
+        collector.insert("a_2", "taken")?;
+        collector.insert("b", "second")
+    }
+
+    fn dedup(policy: DuplicateKeyPolicy) -> Result<Vec<(String, String)>, CefConversionError> {
+        let mut collector = DedupCollector::new(policy);
+        collect_from_fields(&mut collector)?;
+        Ok(collector.into_extensions().into_iter().collect())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dedup_collector() {
+        assert_eq!(
+            dedup(DuplicateKeyPolicy::Error),
+            Err(CefConversionError::DuplicateKey {
+                key: "a".to_owned(),
+                first_field: None,
+                second_field: Some("outer".to_owned()),
+            })
+        );
+        assert_eq!(
+            dedup(DuplicateKeyPolicy::KeepFirst),
+            Ok(pairs(&[("a", "top"), ("a_2", "taken"), ("b", "first")]))
+        );
+        assert_eq!(
+            dedup(DuplicateKeyPolicy::KeepLast),
+            Ok(pairs(&[("a", "outer"), ("a_2", "taken"), ("b", "second")]))
+        );
+        assert_eq!(
+            dedup(DuplicateKeyPolicy::AutoSuffix),
+            Ok(pairs(&[
+                ("a", "top"),
+                ("a_2", "outer"),
+                ("a_2_2", "taken"),
+                ("b", "first"),
+                ("b_2", "second")
+            ]))
+        );
+
+        // nested fields are reported by their full path
+        let mut collector = DedupCollector::new(DuplicateKeyPolicy::Error);
+        collector.enter_field("outer");
+        collector.enter_field("inner");
+        assert!(collector.insert("b", "first").is_ok());
+        collector.exit_field();
+        collector.exit_field();
+        assert_eq!(
+            collector.insert("b", "second"),
+            Err(CefConversionError::DuplicateKey {
+                key: "b".to_owned(),
+                first_field: Some("outer.inner".to_owned()),
+                second_field: None,
+            })
+        );
+    }
+
+    fn collect_custom(collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        collector.insert("cn1", "7")?;
+        collector.insert_custom(CefCustomKind::Number, "Retries", format_args!("{}", 3))?;
+        collector.insert_custom(CefCustomKind::Number, "Threads", format_args!("{}", 8))?;
+        collector.insert_custom(CefCustomKind::Number, "Overflow", format_args!("{}", 1))
+    }
+
+    #[test]
+    fn test_custom_slots() {
+        let exhausted = Err(CefConversionError::CustomSlotsExhausted {
+            kind: "number".to_owned(),
+            label: "Overflow".to_owned(),
+        });
+
+        let mut collector = Vec::<(String, String)>::new();
+        assert_eq!(collect_custom(&mut collector), exhausted);
+        assert_eq!(
+            collector,
+            pairs(&[
+                ("cn1", "7"),
+                ("cn2Label", "Retries"),
+                ("cn2", "3"),
+                ("cn3Label", "Threads"),
+                ("cn3", "8"),
+            ])
+        );
+
+        let mut collector = DedupCollector::new(DuplicateKeyPolicy::Error);
+        assert_eq!(collect_custom(&mut collector), exhausted);
+
+        let mut output = String::new();
+        let mut collector = CefWriterCollector::new(&mut output);
+        assert_eq!(collect_custom(&mut collector), exhausted);
+        assert_eq!(
+            output,
+            "cn1=7 cn2Label=Retries cn2=3 cn3Label=Threads cn3=8"
+        );
+
+        let mut collector = HashMap::<String, String>::new();
+        assert!(collector
+            .insert_custom(CefCustomKind::String, "Container ID", format_args!("abc"))
+            .is_ok());
+        assert_eq!(collector.get("cs1Label").unwrap(), "Container ID");
+        assert_eq!(collector.get("cs1").unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_writer_collector() {
+        let mut output = String::new();
+        let mut collector = CefWriterCollector::new(&mut output);
+        assert!(collect(&mut collector).is_ok());
+        assert_eq!(output, "b=first a=1\\=2 b=second");
+
+        let mut output = String::new();
+        let mut collector =
+            CefWriterCollector::new(&mut output).with_duplicate_keys(DuplicateKeyPolicy::KeepFirst);
+        assert!(collect(&mut collector).is_ok());
+        assert_eq!(output, "b=first a=1\\=2");
+
+        let mut output = String::new();
+        let mut collector = CefWriterCollector::new(&mut output)
+            .with_duplicate_keys(DuplicateKeyPolicy::AutoSuffix);
+        assert!(collect(&mut collector).is_ok());
+        assert_eq!(output, "b=first a=1\\=2 b_2=second");
+
+        let mut output = String::new();
+        let mut collector =
+            CefWriterCollector::new(&mut output).with_duplicate_keys(DuplicateKeyPolicy::Error);
+        assert_eq!(
+            collect_from_fields(&mut collector),
+            dedup(DuplicateKeyPolicy::Error).map(|_| ())
+        );
+
+        let mut output = String::new();
+        let mut collector = CefWriterCollector::new(&mut output);
+        assert_eq!(
+            collector.insert("bad key", "value"),
+            Err(CefConversionError::InvalidExtensionKey {
+                key: "bad key".to_owned()
+            })
+        );
+        assert_eq!(output, "");
+    }
+}
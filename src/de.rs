@@ -0,0 +1,785 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a serde Deserializer that reads items back from parsed
+/// CEF records, the inverse of the Serializer in `ser`.
+/// It's only available with the `serde` feature.
+use crate::ser::{CefHeader, NestedKeys};
+use crate::{parse_cef, CefConversionError, CefRecord};
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+impl de::Error for CefConversionError {
+    fn custom<T: Display>(msg: T) -> Self {
+        CefConversionError::Unexpected(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        CefConversionError::MissingExtension {
+            key: field.to_owned(),
+        }
+    }
+}
+
+/// Reads items implementing `serde::Deserialize` from CEF records.
+///
+/// Fields are read from extensions under the same keys `CefSerdeEncoder` writes them
+/// (see `NestedKeys`), and headers can be read into designated fields. Values are
+/// coerced from strings into whatever the field asks for: numbers, bools, IP addresses
+/// (anything implementing FromStr through serde), comma-separated sequences and enum
+/// variants by name. Timestamps written as milliseconds since the epoch (like the
+/// `rt` extension `OffsetDateTime` writes) are read with `#[serde(with = "epoch_millis")]`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CefSerdeDecoder {
+    pub headers: BTreeMap<CefHeader, String>,
+    pub nested_keys: NestedKeys,
+}
+
+impl CefSerdeDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the header into the field with this (joined) key
+    pub fn with_header_field(mut self, header: CefHeader, key: &str) -> Self {
+        self.headers.insert(header, key.to_owned());
+        self
+    }
+
+    pub fn with_nested_keys(mut self, nested_keys: NestedKeys) -> Self {
+        self.nested_keys = nested_keys;
+        self
+    }
+
+    /// Deserializes an item from a parsed record
+    pub fn from_record<'de, T: Deserialize<'de>>(
+        &self,
+        record: &'de CefRecord,
+    ) -> Result<T, CefConversionError> {
+        // header fields first, so they take precedence over extensions
+        let mut entries: Vec<(&str, &str)> = vec![];
+        for (header, key) in self.headers.iter() {
+            if let Some(value) = record.header(header.trait_name()) {
+                entries.push((key, value));
+            }
+        }
+        entries.extend(
+            record
+                .extensions
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str())),
+        );
+
+        T::deserialize(KeyDeserializer {
+            entries: &entries,
+            nested_keys: self.nested_keys,
+            key: None,
+        })
+    }
+
+    /// Parses a CEF line and deserializes an item from it
+    pub fn from_cef<T: for<'de> Deserialize<'de>>(
+        &self,
+        line: &str,
+    ) -> Result<T, CefConversionError> {
+        self.from_record(&parse_cef(line)?)
+    }
+}
+
+/// Serializes an `OffsetDateTime` as milliseconds since the epoch, and deserializes
+/// it back from either an integer or a string of digits. This is the format the
+/// `OffsetDateTime` `CefExtensions` implementation writes to `rt`.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Event {
+///     #[serde(with = "rust_cef::de::epoch_millis")]
+///     rt: OffsetDateTime,
+/// }
+/// ```
+pub mod epoch_millis {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use time::OffsetDateTime;
+
+    pub fn serialize<S: Serializer>(
+        time: &OffsetDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i128(time.unix_timestamp_nanos() / 1000000)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OffsetDateTime, D::Error> {
+        let millis = i128::deserialize(deserializer)?;
+        OffsetDateTime::from_unix_timestamp_nanos(millis * 1000000).map_err(de::Error::custom)
+    }
+}
+
+/// Deserializes the value under a key: the extension with exactly that
+/// key, or all the extensions nested under it.
+struct KeyDeserializer<'a, 'de> {
+    entries: &'a [(&'a str, &'de str)],
+    nested_keys: NestedKeys,
+    key: Option<String>,
+}
+
+impl<'a, 'de> KeyDeserializer<'a, 'de> {
+    fn separator(&self) -> char {
+        match self.nested_keys {
+            NestedKeys::Dotted => '.',
+            NestedKeys::Underscored => '_',
+        }
+    }
+
+    fn child(&self, name: &str) -> Self {
+        let key = match &self.key {
+            None => name.to_owned(),
+            Some(key) => format!("{}{}{}", key, self.separator(), name),
+        };
+
+        Self {
+            entries: self.entries,
+            nested_keys: self.nested_keys,
+            key: Some(key),
+        }
+    }
+
+    fn value(&self) -> Option<&'de str> {
+        let key = self.key.as_deref()?;
+        self.entries
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    // The keys (and values) nested under this one, without the prefix
+    fn nested(&self) -> Vec<(&'a str, &'de str)> {
+        let key = match &self.key {
+            None => return self.entries.to_vec(),
+            Some(key) => key,
+        };
+
+        let separator = self.separator();
+        self.entries
+            .iter()
+            .filter_map(|(k, v)| {
+                k.strip_prefix(key.as_str())
+                    .and_then(|rest| rest.strip_prefix(separator))
+                    .map(|rest| (rest, *v))
+            })
+            .collect()
+    }
+
+    fn is_present(&self) -> bool {
+        self.value().is_some() || !self.nested().is_empty()
+    }
+
+    fn required_value(&self) -> Result<&'de str, CefConversionError> {
+        match (&self.key, self.value()) {
+            (_, Some(value)) => Ok(value),
+            (Some(key), None) => Err(CefConversionError::MissingExtension { key: key.clone() }),
+            (None, None) => Err(CefConversionError::Unexpected(
+                "Only structs and maps can be read from CEF records".to_owned(),
+            )),
+        }
+    }
+
+    fn parse<T>(&self) -> Result<T, CefConversionError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.required_value()?;
+        value
+            .parse()
+            .map_err(|e: T::Err| CefConversionError::InvalidExtensionValue {
+                key: self.key.clone().unwrap_or_default(),
+                value: value.to_owned(),
+                reason: e.to_string(),
+            })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> Deserializer<'de> for KeyDeserializer<'a, 'de> {
+    type Error = CefConversionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        match self.value() {
+            Some(value) => visitor.visit_borrowed_str(value),
+            None => self.deserialize_map(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        let value = self.required_value()?;
+        match value.to_ascii_lowercase().as_str() {
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            _ => Err(CefConversionError::InvalidExtensionValue {
+                key: self.key.clone().unwrap_or_default(),
+                value: value.to_owned(),
+                reason: "expected true or false".to_owned(),
+            }),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        visitor.visit_borrowed_str(self.required_value()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        visitor.visit_borrowed_bytes(self.required_value()?.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        match self.is_present() {
+            true => visitor.visit_some(self),
+            false => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // sequences are comma-separated values
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        let value = self.required_value()?;
+        let values = match value.is_empty() {
+            true => vec![],
+            false => value.split(',').collect(),
+        };
+
+        visitor.visit_seq(ValueSeq {
+            key: self.key.clone().unwrap_or_default(),
+            nested_keys: self.nested_keys,
+            values: values.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_seq(visitor)
+    }
+
+    // map keys are everything after the prefix, so they may contain separators
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CefConversionError> {
+        let entries = self.nested();
+        let mut seen = vec![];
+        let entries: Vec<(&'a str, &'de str)> = entries
+            .into_iter()
+            .filter(|(k, _)| {
+                let first = !seen.contains(k);
+                seen.push(*k);
+                first
+            })
+            .collect();
+
+        visitor.visit_map(EntryMap {
+            entries: entries.into_iter(),
+            nested_keys: self.nested_keys,
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        // only present fields are handed over, so serde applies
+        // its defaults (and errors) to the missing ones
+        let fields: Vec<&'static str> = fields
+            .iter()
+            .filter(|field| self.child(field).is_present())
+            .copied()
+            .collect();
+
+        visitor.visit_map(FieldMap {
+            parent: self,
+            fields: fields.into_iter(),
+            current: None,
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        if let Some(value) = self.value() {
+            return visitor.visit_enum(value.into_deserializer());
+        }
+
+        // variants with contents nest them under the variant's name
+        let variant = variants
+            .iter()
+            .find(|variant| self.child(variant).is_present());
+        match variant {
+            Some(variant) => visitor.visit_enum(NestedVariant {
+                variant,
+                contents: self.child(variant),
+            }),
+            None => Err(self.required_value().err().unwrap_or_else(|| {
+                CefConversionError::Unexpected("No enum variant found".to_owned())
+            })),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        visitor.visit_unit()
+    }
+}
+
+/// The fields of a struct that are present in the record
+struct FieldMap<'a, 'de> {
+    parent: KeyDeserializer<'a, 'de>,
+    fields: std::vec::IntoIter<&'static str>,
+    current: Option<&'static str>,
+}
+
+impl<'a, 'de> MapAccess<'de> for FieldMap<'a, 'de> {
+    type Error = CefConversionError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, CefConversionError> {
+        self.current = self.fields.next();
+        match self.current {
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, CefConversionError> {
+        match self.current.take() {
+            Some(field) => seed.deserialize(self.parent.child(field)),
+            None => Err(CefConversionError::Unexpected(
+                "Struct field value read before its name".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The entries of a map
+struct EntryMap<'a, 'de> {
+    entries: std::vec::IntoIter<(&'a str, &'de str)>,
+    nested_keys: NestedKeys,
+    value: Option<(&'a str, &'de str)>,
+}
+
+impl<'a, 'de> MapAccess<'de> for EntryMap<'a, 'de> {
+    type Error = CefConversionError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, CefConversionError> {
+        self.value = self.entries.next();
+        match self.value {
+            Some((key, _)) => seed.deserialize(key.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, CefConversionError> {
+        match self.value.take() {
+            Some((key, value)) => {
+                let entries = [(key, value)];
+                seed.deserialize(KeyDeserializer {
+                    entries: &entries,
+                    nested_keys: self.nested_keys,
+                    key: Some(key.to_owned()),
+                })
+            }
+            None => Err(CefConversionError::Unexpected(
+                "Map value read before its key".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The elements of a comma-separated value
+struct ValueSeq<'de> {
+    key: String,
+    nested_keys: NestedKeys,
+    values: std::vec::IntoIter<&'de str>,
+}
+
+impl<'de> SeqAccess<'de> for ValueSeq<'de> {
+    type Error = CefConversionError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, CefConversionError> {
+        match self.values.next() {
+            Some(value) => {
+                let entries = [(self.key.as_str(), value)];
+                seed.deserialize(KeyDeserializer {
+                    entries: &entries,
+                    nested_keys: self.nested_keys,
+                    key: Some(self.key.clone()),
+                })
+                .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// An enum variant whose contents are nested under its name
+struct NestedVariant<'a, 'de> {
+    variant: &'static str,
+    contents: KeyDeserializer<'a, 'de>,
+}
+
+impl<'a, 'de> EnumAccess<'de> for NestedVariant<'a, 'de> {
+    type Error = CefConversionError;
+    type Variant = KeyDeserializer<'a, 'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), CefConversionError> {
+        let variant = seed.deserialize(
+            IntoDeserializer::<CefConversionError>::into_deserializer(self.variant),
+        )?;
+        Ok((variant, self.contents))
+    }
+}
+
+impl<'a, 'de> VariantAccess<'de> for KeyDeserializer<'a, 'de> {
+    type Error = CefConversionError;
+
+    fn unit_variant(self) -> Result<(), CefConversionError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, CefConversionError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CefConversionError> {
+        self.deserialize_struct("", fields, visitor)
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ser::CefSerdeEncoder;
+    use crate::{CefExtensions, FromCef};
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use time::OffsetDateTime;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Login {
+        event: String,
+        level: u8,
+        src: IpAddr,
+        suser: Option<String>,
+        cnt: u32,
+        success: bool,
+        outcome: Outcome,
+        geo: Geo,
+        tags: Vec<String>,
+        labels: BTreeMap<String, f64>,
+        #[serde(with = "epoch_millis")]
+        rt: OffsetDateTime,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Geo {
+        country: String,
+        city: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Outcome {
+        Failure,
+        Blocked { rule: u32 },
+    }
+
+    fn login() -> Login {
+        Login {
+            event: "Login failed".to_owned(),
+            level: 7,
+            src: "10.0.0.1".parse().unwrap(),
+            suser: None,
+            cnt: 3,
+            success: false,
+            outcome: Outcome::Failure,
+            geo: Geo {
+                country: "NZ".to_owned(),
+                city: Some("Wellington".to_owned()),
+            },
+            tags: vec!["auth".to_owned(), "ssh".to_owned()],
+            labels: vec![("risk".to_owned(), 0.5)].into_iter().collect(),
+            rt: OffsetDateTime::from_unix_timestamp_nanos(1588803149000000000).unwrap(),
+        }
+    }
+
+    fn decoder() -> CefSerdeDecoder {
+        CefSerdeDecoder::new()
+            .with_header_field(CefHeader::Name, "event")
+            .with_header_field(CefHeader::Severity, "level")
+    }
+
+    #[test]
+    fn test_coercions() {
+        let line = "CEF:0|polyverse|zerotect|V1|Login|Login failed|7|cnt=3 geo.city=Wellington geo.country=NZ labels.risk=0.5 outcome=Failure rt=1588803149000 src=10.0.0.1 success=false tags=auth,ssh";
+        let decoded: Login = decoder().from_cef(line).unwrap();
+        assert_eq!(decoded, login());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let encoder = CefSerdeEncoder::new()
+            .with_header(CefHeader::Version, "0")
+            .with_header(CefHeader::DeviceVendor, "polyverse")
+            .with_header(CefHeader::DeviceProduct, "zerotect")
+            .with_header(CefHeader::DeviceVersion, "V1")
+            .with_header(CefHeader::DeviceEventClassID, "Login")
+            .with_header_field(CefHeader::Name, "event")
+            .with_header_field(CefHeader::Severity, "level");
+
+        let mut value = login();
+        value.suser = Some("alice".to_owned());
+        value.outcome = Outcome::Blocked { rule: 12 };
+        value.src = "2001:db8::1".parse().unwrap();
+
+        for nested_keys in [NestedKeys::Dotted, NestedKeys::Underscored] {
+            let line = encoder
+                .clone()
+                .with_nested_keys(nested_keys)
+                .to_cef(&value)
+                .unwrap();
+            let decoded: Login = decoder()
+                .with_nested_keys(nested_keys)
+                .from_cef(&line)
+                .unwrap();
+            assert_eq!(decoded, value);
+        }
+
+        // elements with commas wouldn't read back as they were
+        value.tags = vec!["a,b".to_owned(), "c".to_owned()];
+        assert_eq!(
+            encoder.to_cef(&value),
+            Err(CefConversionError::InvalidExtensionValue {
+                key: "tags".to_owned(),
+                value: "a,b".to_owned(),
+                reason: "elements of a sequence can't contain commas".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_rt_from_cef_extensions() {
+        // the rt extension written by OffsetDateTime's CefExtensions
+        let mut extensions: Vec<(String, String)> = vec![];
+        login().rt.cef_extensions(&mut extensions).unwrap();
+        let record = CefRecord {
+            version: "0".to_owned(),
+            device_vendor: "polyverse".to_owned(),
+            device_product: "zerotect".to_owned(),
+            device_version: "V1".to_owned(),
+            device_event_class_id: "Login".to_owned(),
+            name: "Login".to_owned(),
+            severity: "1".to_owned(),
+            extensions,
+        };
+
+        #[derive(Deserialize)]
+        struct Timed {
+            #[serde(with = "epoch_millis")]
+            rt: OffsetDateTime,
+        }
+        let timed: Timed = CefSerdeDecoder::new().from_record(&record).unwrap();
+        assert_eq!(timed.rt, login().rt);
+        assert_eq!(timed.rt, OffsetDateTime::from_cef_record(&record).unwrap());
+    }
+
+    #[test]
+    fn test_borrowed_and_maps() {
+        let record =
+            parse_cef("CEF:0|polyverse|zerotect|V1|Login|Login|1|suser=alice ad.user=bob").unwrap();
+
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            suser: &'a str,
+        }
+        let borrowed: Borrowed = CefSerdeDecoder::new().from_record(&record).unwrap();
+        assert_eq!(borrowed.suser, "alice");
+
+        let map: HashMap<String, String> = CefSerdeDecoder::new().from_record(&record).unwrap();
+        assert_eq!(map.get("ad.user"), Some(&"bob".to_owned()));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let decoder = decoder();
+
+        let missing =
+            decoder.from_cef::<Login>("CEF:0|polyverse|zerotect|V1|Login|Login|7|src=10.0.0.1");
+        assert!(matches!(
+            missing,
+            Err(CefConversionError::MissingExtension { .. })
+        ));
+
+        #[derive(Debug, Deserialize)]
+        struct Count {
+            #[allow(dead_code)]
+            cnt: u8,
+        }
+        assert_eq!(
+            decoder
+                .from_cef::<Count>("CEF:0|polyverse|zerotect|V1|Login|Login|7|cnt=300")
+                .unwrap_err(),
+            CefConversionError::InvalidExtensionValue {
+                key: "cnt".to_owned(),
+                value: "300".to_owned(),
+                reason: "number too large to fit in target type".to_owned(),
+            }
+        );
+
+        #[derive(Debug, Deserialize)]
+        struct Flag {
+            #[allow(dead_code)]
+            success: bool,
+        }
+        assert!(decoder
+            .from_cef::<Flag>("CEF:0|polyverse|zerotect|V1|Login|Login|7|success=maybe")
+            .is_err());
+
+        // headers that aren't asked for aren't read
+        #[derive(Debug, Deserialize)]
+        struct Named {
+            #[allow(dead_code)]
+            event: String,
+        }
+        assert!(CefSerdeDecoder::new()
+            .from_cef::<Named>("CEF:0|polyverse|zerotect|V1|Login|Login|7|")
+            .is_err());
+    }
+}
@@ -0,0 +1,593 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a dictionary of the extension keys defined by the
+/// ArcSight Common Event Format standard, with their full names, data types
+/// and maximum lengths, so keys and values can be checked before they reach a SIEM.
+use crate::{CefConversionError, CefExtensionsResult};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// The data type the CEF standard specifies for an extension's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CefDataType {
+    Integer,
+    Long,
+    FloatingPoint,
+    Double,
+    String,
+    /// An IPv4 address (IPv6 is accepted too, as later revisions of the standard allow)
+    IpAddress,
+    Ipv4Address,
+    Ipv6Address,
+    MacAddress,
+    /// Milliseconds since the epoch, or one of the standard's date formats
+    Timestamp,
+}
+
+impl Display for CefDataType {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let name = match self {
+            CefDataType::Integer => "Integer",
+            CefDataType::Long => "Long",
+            CefDataType::FloatingPoint => "Floating Point",
+            CefDataType::Double => "Double",
+            CefDataType::String => "String",
+            CefDataType::IpAddress => "IP Address",
+            CefDataType::Ipv4Address => "IPv4 Address",
+            CefDataType::Ipv6Address => "IPv6 Address",
+            CefDataType::MacAddress => "MAC Address",
+            CefDataType::Timestamp => "Time Stamp",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A standard extension key as defined by the CEF standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CefKeyDefinition {
+    /// The key used in CEF strings (i.e. `src`)
+    pub key: &'static str,
+    /// The full (dictionary) name of the key (i.e. `sourceAddress`)
+    pub full_name: &'static str,
+    pub data_type: CefDataType,
+    /// The maximum length of a String value, in characters
+    pub max_length: Option<usize>,
+}
+
+impl CefKeyDefinition {
+    /// Checks that a value conforms to this key's data type and maximum length
+    pub fn validate_value(&self, value: &str) -> CefExtensionsResult {
+        if let Some(max_length) = self.max_length {
+            let length = value.chars().count();
+            if length > max_length {
+                return Err(CefConversionError::ValueTooLong {
+                    key: self.key.to_owned(),
+                    length,
+                    max_length,
+                });
+            }
+        }
+
+        let valid = match self.data_type {
+            CefDataType::Integer => value.parse::<i32>().is_ok(),
+            CefDataType::Long => value.parse::<i64>().is_ok(),
+            CefDataType::FloatingPoint => value.parse::<f32>().is_ok(),
+            CefDataType::Double => value.parse::<f64>().is_ok(),
+            CefDataType::String | CefDataType::Timestamp => true,
+            CefDataType::IpAddress => value.parse::<IpAddr>().is_ok(),
+            CefDataType::Ipv4Address => value.parse::<Ipv4Addr>().is_ok(),
+            CefDataType::Ipv6Address => value.parse::<Ipv6Addr>().is_ok(),
+            CefDataType::MacAddress => is_mac_address(value),
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(CefConversionError::InvalidExtensionValue {
+                key: self.key.to_owned(),
+                value: value.to_owned(),
+                reason: format!("expected {}", self.data_type),
+            }),
+        }
+    }
+}
+
+/// The kinds of custom extension slots the CEF standard provides for data
+/// that has no standard key. Each slot (i.e. `cs1`) carries its value, and a
+/// matching label key (i.e. `cs1Label`) names what the value means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CefCustomKind {
+    /// `cs1` to `cs6`
+    String,
+    /// `cn1` to `cn3`
+    Number,
+    /// `cfp1` to `cfp4`
+    FloatingPoint,
+    /// `deviceCustomDate1` to `deviceCustomDate2`
+    Date,
+    /// `c6a1` to `c6a4`
+    Ipv6Address,
+}
+
+impl CefCustomKind {
+    /// The (slot key, label key) pairs for this kind, in allocation order
+    pub fn slots(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            CefCustomKind::String => &[
+                ("cs1", "cs1Label"),
+                ("cs2", "cs2Label"),
+                ("cs3", "cs3Label"),
+                ("cs4", "cs4Label"),
+                ("cs5", "cs5Label"),
+                ("cs6", "cs6Label"),
+            ],
+            CefCustomKind::Number => &[
+                ("cn1", "cn1Label"),
+                ("cn2", "cn2Label"),
+                ("cn3", "cn3Label"),
+            ],
+            CefCustomKind::FloatingPoint => &[
+                ("cfp1", "cfp1Label"),
+                ("cfp2", "cfp2Label"),
+                ("cfp3", "cfp3Label"),
+                ("cfp4", "cfp4Label"),
+            ],
+            CefCustomKind::Date => &[
+                ("deviceCustomDate1", "deviceCustomDate1Label"),
+                ("deviceCustomDate2", "deviceCustomDate2Label"),
+            ],
+            CefCustomKind::Ipv6Address => &[
+                ("c6a1", "c6a1Label"),
+                ("c6a2", "c6a2Label"),
+                ("c6a3", "c6a3Label"),
+                ("c6a4", "c6a4Label"),
+            ],
+        }
+    }
+
+    /// Every kind of custom slot
+    pub fn all() -> &'static [CefCustomKind] {
+        &[
+            CefCustomKind::String,
+            CefCustomKind::Number,
+            CefCustomKind::FloatingPoint,
+            CefCustomKind::Date,
+            CefCustomKind::Ipv6Address,
+        ]
+    }
+}
+
+impl Display for CefCustomKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let name = match self {
+            CefCustomKind::String => "string",
+            CefCustomKind::Number => "number",
+            CefCustomKind::FloatingPoint => "float",
+            CefCustomKind::Date => "date",
+            CefCustomKind::Ipv6Address => "ipv6",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CefCustomKind {
+    type Err = CefConversionError;
+
+    /// Parses the names used by `Display`: string, number, float, date and ipv6
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CefCustomKind::all()
+            .iter()
+            .find(|kind| kind.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                CefConversionError::Unexpected(format!(
+                    "'{}' is not a custom slot kind. Expected one of: string, number, float, date, ipv6",
+                    s
+                ))
+            })
+    }
+}
+
+/// Looks up a standard extension key (i.e. `src`)
+pub fn lookup(key: &str) -> Option<&'static CefKeyDefinition> {
+    STANDARD_KEYS
+        .iter()
+        .find(|definition| definition.key == key)
+}
+
+/// Looks up a standard extension key by its full name (i.e. `sourceAddress`)
+pub fn lookup_full_name(full_name: &str) -> Option<&'static CefKeyDefinition> {
+    STANDARD_KEYS
+        .iter()
+        .find(|definition| definition.full_name == full_name)
+}
+
+/// Is this one of the keys defined by the CEF standard?
+pub fn is_standard_key(key: &str) -> bool {
+    lookup(key).is_some()
+}
+
+/// Returns the standard key that a non-standard key differs from only in case
+/// (i.e. `src` for `SRC`), or None when the key is standard or there's none.
+pub fn standard_key_ignoring_case(key: &str) -> Option<&'static str> {
+    if is_standard_key(key) {
+        return None;
+    }
+
+    STANDARD_KEYS
+        .iter()
+        .find(|definition| definition.key.eq_ignore_ascii_case(key))
+        .map(|definition| definition.key)
+}
+
+/// Suggests the standard key that a non-standard key was likely meant to be:
+/// one that differs only in case, or (for keys of three or more characters)
+/// by a single typo (a changed, added, removed or swapped character).
+///
+/// Returns None when the key is standard or nothing is close enough.
+pub fn suggest_standard_key(key: &str) -> Option<&'static str> {
+    if is_standard_key(key) {
+        return None;
+    }
+
+    if let Some(standard_key) = standard_key_ignoring_case(key) {
+        return Some(standard_key);
+    }
+
+    if key.chars().count() < 3 {
+        return None;
+    }
+
+    STANDARD_KEYS
+        .iter()
+        .find(|definition| is_single_typo(key, definition.key))
+        .map(|definition| definition.key)
+}
+
+// Are the strings exactly one edit apart, counting a swap of
+// adjacent characters as one edit?
+fn is_single_typo(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // strip the common prefix and suffix, what's left is the typo
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a = &a[prefix..a.len() - suffix];
+    let b = &b[prefix..b.len() - suffix];
+
+    match (a.len(), b.len()) {
+        (1, 1) | (1, 0) | (0, 1) => true,
+        (2, 2) => a[0] == b[1] && a[1] == b[0],
+        _ => false,
+    }
+}
+
+// Six colon-separated (or dash-separated) pairs of hex digits
+fn is_mac_address(value: &str) -> bool {
+    let separator = match value.chars().nth(2) {
+        Some(c) if c == ':' || c == '-' => c,
+        _ => return false,
+    };
+
+    let octets: Vec<&str> = value.split(separator).collect();
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+const fn key(
+    key: &'static str,
+    full_name: &'static str,
+    data_type: CefDataType,
+    max_length: Option<usize>,
+) -> CefKeyDefinition {
+    CefKeyDefinition {
+        key,
+        full_name,
+        data_type,
+        max_length,
+    }
+}
+
+/// Every extension key defined by the CEF standard
+pub static STANDARD_KEYS: &[CefKeyDefinition] = standard_keys::STANDARD_KEYS;
+
+// kept apart so the data type variants (i.e. `String`) don't shadow std types elsewhere
+mod standard_keys {
+    use super::{key, CefDataType::*, CefKeyDefinition};
+
+    #[rustfmt::skip]
+    pub static STANDARD_KEYS: &[CefKeyDefinition] = &[
+        key("act", "deviceAction", String, Some(63)),
+        key("app", "applicationProtocol", String, Some(31)),
+        key("c6a1", "deviceCustomIPv6Address1", Ipv6Address, None),
+        key("c6a1Label", "deviceCustomIPv6Address1Label", String, Some(1023)),
+        key("c6a2", "deviceCustomIPv6Address2", Ipv6Address, None),
+        key("c6a2Label", "deviceCustomIPv6Address2Label", String, Some(1023)),
+        key("c6a3", "deviceCustomIPv6Address3", Ipv6Address, None),
+        key("c6a3Label", "deviceCustomIPv6Address3Label", String, Some(1023)),
+        key("c6a4", "deviceCustomIPv6Address4", Ipv6Address, None),
+        key("c6a4Label", "deviceCustomIPv6Address4Label", String, Some(1023)),
+        key("cat", "deviceEventCategory", String, Some(1023)),
+        key("cfp1", "deviceCustomFloatingPoint1", FloatingPoint, None),
+        key("cfp1Label", "deviceCustomFloatingPoint1Label", String, Some(1023)),
+        key("cfp2", "deviceCustomFloatingPoint2", FloatingPoint, None),
+        key("cfp2Label", "deviceCustomFloatingPoint2Label", String, Some(1023)),
+        key("cfp3", "deviceCustomFloatingPoint3", FloatingPoint, None),
+        key("cfp3Label", "deviceCustomFloatingPoint3Label", String, Some(1023)),
+        key("cfp4", "deviceCustomFloatingPoint4", FloatingPoint, None),
+        key("cfp4Label", "deviceCustomFloatingPoint4Label", String, Some(1023)),
+        key("cn1", "deviceCustomNumber1", Long, None),
+        key("cn1Label", "deviceCustomNumber1Label", String, Some(1023)),
+        key("cn2", "deviceCustomNumber2", Long, None),
+        key("cn2Label", "deviceCustomNumber2Label", String, Some(1023)),
+        key("cn3", "deviceCustomNumber3", Long, None),
+        key("cn3Label", "deviceCustomNumber3Label", String, Some(1023)),
+        key("cnt", "baseEventCount", Integer, None),
+        key("cs1", "deviceCustomString1", String, Some(4000)),
+        key("cs1Label", "deviceCustomString1Label", String, Some(1023)),
+        key("cs2", "deviceCustomString2", String, Some(4000)),
+        key("cs2Label", "deviceCustomString2Label", String, Some(1023)),
+        key("cs3", "deviceCustomString3", String, Some(4000)),
+        key("cs3Label", "deviceCustomString3Label", String, Some(1023)),
+        key("cs4", "deviceCustomString4", String, Some(4000)),
+        key("cs4Label", "deviceCustomString4Label", String, Some(1023)),
+        key("cs5", "deviceCustomString5", String, Some(4000)),
+        key("cs5Label", "deviceCustomString5Label", String, Some(1023)),
+        key("cs6", "deviceCustomString6", String, Some(4000)),
+        key("cs6Label", "deviceCustomString6Label", String, Some(1023)),
+        key("destinationDnsDomain", "destinationDnsDomain", String, Some(255)),
+        key("destinationServiceName", "destinationServiceName", String, Some(1023)),
+        key("destinationTranslatedAddress", "destinationTranslatedAddress", Ipv4Address, None),
+        key("destinationTranslatedPort", "destinationTranslatedPort", Integer, None),
+        key("deviceCustomDate1", "deviceCustomDate1", Timestamp, None),
+        key("deviceCustomDate1Label", "deviceCustomDate1Label", String, Some(1023)),
+        key("deviceCustomDate2", "deviceCustomDate2", Timestamp, None),
+        key("deviceCustomDate2Label", "deviceCustomDate2Label", String, Some(1023)),
+        key("deviceDirection", "deviceDirection", Integer, None),
+        key("deviceDnsDomain", "deviceDnsDomain", String, Some(255)),
+        key("deviceExternalId", "deviceExternalId", String, Some(255)),
+        key("deviceFacility", "deviceFacility", String, Some(1023)),
+        key("deviceInboundInterface", "deviceInboundInterface", String, Some(128)),
+        key("deviceNtDomain", "deviceNtDomain", String, Some(255)),
+        key("deviceOutboundInterface", "deviceOutboundInterface", String, Some(128)),
+        key("devicePayloadId", "devicePayloadId", String, Some(128)),
+        key("deviceProcessName", "deviceProcessName", String, Some(1023)),
+        key("deviceTranslatedAddress", "deviceTranslatedAddress", Ipv4Address, None),
+        key("dhost", "destinationHostName", String, Some(1023)),
+        key("dlat", "destinationGeoLatitude", Double, None),
+        key("dlong", "destinationGeoLongitude", Double, None),
+        key("dmac", "destinationMacAddress", MacAddress, None),
+        key("dntdom", "destinationNtDomain", String, Some(255)),
+        key("dpid", "destinationProcessId", Integer, None),
+        key("dpriv", "destinationUserPrivileges", String, Some(1023)),
+        key("dproc", "destinationProcessName", String, Some(1023)),
+        key("dpt", "destinationPort", Integer, None),
+        key("dst", "destinationAddress", IpAddress, None),
+        key("dtz", "deviceTimeZone", String, Some(255)),
+        key("duid", "destinationUserId", String, Some(1023)),
+        key("duser", "destinationUserName", String, Some(1023)),
+        key("dvc", "deviceAddress", IpAddress, None),
+        key("dvchost", "deviceHostName", String, Some(100)),
+        key("dvcmac", "deviceMacAddress", MacAddress, None),
+        key("dvcpid", "deviceProcessId", Integer, None),
+        key("end", "endTime", Timestamp, None),
+        key("eventId", "eventId", Long, None),
+        key("externalId", "externalId", String, Some(40)),
+        key("fileCreateTime", "fileCreateTime", Timestamp, None),
+        key("fileHash", "fileHash", String, Some(255)),
+        key("fileId", "fileId", String, Some(1023)),
+        key("fileModificationTime", "fileModificationTime", Timestamp, None),
+        key("filePath", "filePath", String, Some(1023)),
+        key("filePermission", "filePermission", String, Some(1023)),
+        key("fileType", "fileType", String, Some(1023)),
+        key("flexDate1", "flexDate1", Timestamp, None),
+        key("flexDate1Label", "flexDate1Label", String, Some(128)),
+        key("flexNumber1", "flexNumber1", Long, None),
+        key("flexNumber1Label", "flexNumber1Label", String, Some(128)),
+        key("flexNumber2", "flexNumber2", Long, None),
+        key("flexNumber2Label", "flexNumber2Label", String, Some(128)),
+        key("flexString1", "flexString1", String, Some(1023)),
+        key("flexString1Label", "flexString1Label", String, Some(128)),
+        key("flexString2", "flexString2", String, Some(1023)),
+        key("flexString2Label", "flexString2Label", String, Some(128)),
+        key("fname", "filename", String, Some(1023)),
+        key("fsize", "fileSize", Integer, None),
+        key("in", "bytesIn", Integer, None),
+        key("msg", "message", String, Some(1023)),
+        key("oldFileCreateTime", "oldFileCreateTime", Timestamp, None),
+        key("oldFileHash", "oldFileHash", String, Some(255)),
+        key("oldFileId", "oldFileId", String, Some(1023)),
+        key("oldFileModificationTime", "oldFileModificationTime", Timestamp, None),
+        key("oldFileName", "oldFileName", String, Some(1023)),
+        key("oldFilePath", "oldFilePath", String, Some(1023)),
+        key("oldFilePermission", "oldFilePermission", String, Some(1023)),
+        key("oldFileSize", "oldFileSize", Integer, None),
+        key("oldFileType", "oldFileType", String, Some(1023)),
+        key("out", "bytesOut", Integer, None),
+        key("outcome", "eventOutcome", String, Some(63)),
+        key("proto", "transportProtocol", String, Some(31)),
+        key("reason", "Reason", String, Some(1023)),
+        key("request", "requestUrl", String, Some(1023)),
+        key("requestClientApplication", "requestClientApplication", String, Some(1023)),
+        key("requestContext", "requestContext", String, Some(2048)),
+        key("requestCookies", "requestCookies", String, Some(1023)),
+        key("requestMethod", "requestMethod", String, Some(1023)),
+        key("rt", "deviceReceiptTime", Timestamp, None),
+        key("shost", "sourceHostName", String, Some(1023)),
+        key("slat", "sourceGeoLatitude", Double, None),
+        key("slong", "sourceGeoLongitude", Double, None),
+        key("smac", "sourceMacAddress", MacAddress, None),
+        key("sntdom", "sourceNtDomain", String, Some(255)),
+        key("sourceDnsDomain", "sourceDnsDomain", String, Some(255)),
+        key("sourceServiceName", "sourceServiceName", String, Some(1023)),
+        key("sourceTranslatedAddress", "sourceTranslatedAddress", Ipv4Address, None),
+        key("sourceTranslatedPort", "sourceTranslatedPort", Integer, None),
+        key("spid", "sourceProcessId", Integer, None),
+        key("spriv", "sourceUserPrivileges", String, Some(1023)),
+        key("sproc", "sourceProcessName", String, Some(1023)),
+        key("spt", "sourcePort", Integer, None),
+        key("src", "sourceAddress", IpAddress, None),
+        key("start", "startTime", Timestamp, None),
+        key("suid", "sourceUserId", String, Some(1023)),
+        key("suser", "sourceUserName", String, Some(1023)),
+        key("type", "type", Integer, None),
+        // keys set by ArcSight components, which devices may also send
+        key("agentDnsDomain", "agentDnsDomain", String, Some(255)),
+        key("agentNtDomain", "agentNtDomain", String, Some(255)),
+        key("agentTranslatedAddress", "agentTranslatedAddress", IpAddress, None),
+        key("agentTranslatedZoneExternalID", "agentTranslatedZoneExternalID", String, Some(200)),
+        key("agentTranslatedZoneURI", "agentTranslatedZoneURI", String, Some(2048)),
+        key("agentZoneExternalID", "agentZoneExternalID", String, Some(200)),
+        key("agentZoneURI", "agentZoneURI", String, Some(2048)),
+        key("agt", "agentAddress", IpAddress, None),
+        key("ahost", "agentHostName", String, Some(1023)),
+        key("aid", "agentId", String, Some(40)),
+        key("amac", "agentMacAddress", MacAddress, None),
+        key("art", "agentReceiptTime", Timestamp, None),
+        key("at", "agentType", String, Some(63)),
+        key("atz", "agentTimeZone", String, Some(255)),
+        key("av", "agentVersion", String, Some(31)),
+        key("customerExternalID", "customerExternalID", String, Some(200)),
+        key("customerURI", "customerURI", String, Some(2048)),
+        key("destinationTranslatedZoneExternalID", "destinationTranslatedZoneExternalID", String, Some(200)),
+        key("destinationTranslatedZoneURI", "destinationTranslatedZoneURI", String, Some(2048)),
+        key("destinationZoneExternalID", "destinationZoneExternalID", String, Some(200)),
+        key("destinationZoneURI", "destinationZoneURI", String, Some(2048)),
+        key("deviceTranslatedZoneExternalID", "deviceTranslatedZoneExternalID", String, Some(200)),
+        key("deviceTranslatedZoneURI", "deviceTranslatedZoneURI", String, Some(2048)),
+        key("deviceZoneExternalID", "deviceZoneExternalID", String, Some(200)),
+        key("deviceZoneURI", "deviceZoneURI", String, Some(2048)),
+        key("rawEvent", "rawEvent", String, Some(4000)),
+        key("sourceTranslatedZoneExternalID", "sourceTranslatedZoneExternalID", String, Some(200)),
+        key("sourceTranslatedZoneURI", "sourceTranslatedZoneURI", String, Some(2048)),
+        key("sourceZoneExternalID", "sourceZoneExternalID", String, Some(200)),
+        key("sourceZoneURI", "sourceZoneURI", String, Some(2048)),
+    ];
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_dictionary_is_consistent() {
+        let mut keys = HashSet::new();
+        let mut full_names = HashSet::new();
+        for definition in STANDARD_KEYS {
+            assert!(crate::is_valid_extension_key(definition.key));
+            assert!(keys.insert(definition.key), "{}", definition.key);
+            assert!(
+                full_names.insert(definition.full_name),
+                "{}",
+                definition.full_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let src = lookup("src").unwrap();
+        assert_eq!(src.full_name, "sourceAddress");
+        assert_eq!(src.data_type, CefDataType::IpAddress);
+        assert_eq!(lookup_full_name("deviceCustomString3").unwrap().key, "cs3");
+        assert_eq!(lookup("msg").unwrap().max_length, Some(1023));
+
+        assert!(is_standard_key("cn3Label"));
+        assert!(!is_standard_key("scr"));
+        assert!(!is_standard_key("SRC"));
+        assert!(lookup_full_name("src").is_none());
+    }
+
+    #[test]
+    fn test_suggest_standard_key() {
+        assert_eq!(suggest_standard_key("scr"), Some("src"));
+        assert_eq!(suggest_standard_key("sr"), None);
+        assert_eq!(suggest_standard_key("SRC"), Some("src"));
+        assert_eq!(suggest_standard_key("suser1"), Some("suser"));
+        assert_eq!(suggest_standard_key("susr"), Some("suser"));
+        assert_eq!(suggest_standard_key("mgs"), Some("msg"));
+        assert_eq!(suggest_standard_key("cs7"), Some("cs1"));
+        assert_eq!(suggest_standard_key("src"), None);
+        assert_eq!(suggest_standard_key("newname"), None);
+        assert_eq!(suggest_standard_key("person_age"), None);
+        assert_eq!(suggest_standard_key("ab"), None);
+
+        assert_eq!(standard_key_ignoring_case("SRC"), Some("src"));
+        assert_eq!(standard_key_ignoring_case("Suser"), Some("suser"));
+        assert_eq!(standard_key_ignoring_case("src"), None);
+        assert_eq!(standard_key_ignoring_case("scr"), None);
+        assert_eq!(standard_key_ignoring_case("name"), None);
+    }
+
+    #[test]
+    fn test_translations_use_standard_keys() {
+        let translations = crate::leef::CEF_TO_LEEF_KEYS
+            .iter()
+            .chain(crate::ecs::CEF_TO_ECS_FIELDS)
+            .chain(crate::ocsf::CEF_TO_OCSF_FIELDS);
+        for (cef, _) in translations {
+            assert!(is_standard_key(cef), "{}", cef);
+        }
+    }
+
+    #[test]
+    fn test_custom_kinds() {
+        for kind in CefCustomKind::all() {
+            assert_eq!(kind.to_string().parse::<CefCustomKind>(), Ok(*kind));
+            for (slot, label) in kind.slots() {
+                assert!(is_standard_key(slot), "{}", slot);
+                assert_eq!(lookup(label).unwrap().data_type, CefDataType::String);
+            }
+        }
+        assert_eq!(CefCustomKind::Number.slots()[2], ("cn3", "cn3Label"));
+        assert!("text".parse::<CefCustomKind>().is_err());
+    }
+
+    #[test]
+    fn test_validate_value() {
+        let validate = |key: &str, value: &str| lookup(key).unwrap().validate_value(value);
+
+        assert!(validate("src", "10.0.0.1").is_ok());
+        assert!(validate("src", "::1").is_ok());
+        assert!(validate("dst", "localhost").is_err());
+        assert!(validate("c6a1", "10.0.0.1").is_err());
+        assert!(validate("spt", "8080").is_ok());
+        assert!(validate("spt", "http").is_err());
+        assert!(validate("cn1", "-9000000000").is_ok());
+        assert!(validate("cfp1", "3.25").is_ok());
+        assert!(validate("smac", "00:0d:60:AF:1B:61").is_ok());
+        assert!(validate("smac", "00-0d-60-af-1b-61").is_ok());
+        assert!(validate("smac", "00:0d:60:af:1b").is_err());
+        assert!(validate("smac", "00:0d-60:af:1b:61").is_err());
+        assert!(validate("rt", "1589916600000").is_ok());
+
+        assert_eq!(
+            validate("act", &"a".repeat(64)),
+            Err(CefConversionError::ValueTooLong {
+                key: "act".to_owned(),
+                length: 64,
+                max_length: 63,
+            })
+        );
+        assert_eq!(
+            validate("dpt", "x"),
+            Err(CefConversionError::InvalidExtensionValue {
+                key: "dpt".to_owned(),
+                value: "x".to_owned(),
+                reason: "expected Integer".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,166 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module renders items as nested JSON following the Elastic Common Schema (ECS).
+use crate::dictionary::{self, CefDataType};
+use crate::json::{extension_value, is_numeric_key, JsonValue};
+use crate::syslog::rfc5424_timestamp;
+use crate::{collect_record, CefResult, CefSeverity, ToCef};
+use time::OffsetDateTime;
+
+/// Standard CEF keys and the ECS fields they're written to.
+/// Keys not listed are kept under `cef.extensions`.
+#[rustfmt::skip]
+pub const CEF_TO_ECS_FIELDS: &[(&str, &str)] = &[
+    ("act", "event.action"),
+    ("app", "network.protocol"),
+    ("dhost", "destination.domain"),
+    ("dmac", "destination.mac"),
+    ("dpid", "process.pid"),
+    ("dpt", "destination.port"),
+    ("dproc", "process.name"),
+    ("dst", "destination.ip"),
+    ("duid", "destination.user.id"),
+    ("duser", "destination.user.name"),
+    ("dvc", "observer.ip"),
+    ("dvchost", "observer.hostname"),
+    ("end", "event.end"),
+    ("filePath", "file.path"),
+    ("fname", "file.name"),
+    ("fsize", "file.size"),
+    ("in", "source.bytes"),
+    ("msg", "message"),
+    ("out", "destination.bytes"),
+    ("outcome", "event.outcome"),
+    ("proto", "network.transport"),
+    ("request", "url.original"),
+    ("requestClientApplication", "user_agent.original"),
+    ("requestMethod", "http.request.method"),
+    ("rt", "@timestamp"),
+    ("shost", "source.domain"),
+    ("smac", "source.mac"),
+    ("spt", "source.port"),
+    ("src", "source.ip"),
+    ("start", "event.start"),
+    ("suid", "source.user.id"),
+    ("suser", "source.user.name"),
+];
+
+/// The ECS field for a CEF extension key, if it has one
+pub fn ecs_field(cef_key: &str) -> Option<&'static str> {
+    CEF_TO_ECS_FIELDS
+        .iter()
+        .find(|(cef, _)| *cef == cef_key)
+        .map(|(_, ecs)| *ecs)
+}
+
+/// This trait emits an ECS document, and is implemented for everything
+/// that implements `ToCef`.
+///
+/// DeviceVendor, DeviceProduct and DeviceVersion become `observer.vendor`,
+/// `observer.product` and `observer.version`, DeviceEventClassID becomes
+/// `event.code`, Name becomes `event.reason` and the severity's level becomes
+/// `event.severity`. The CEF version is kept as `cef.version`.
+///
+/// Extensions are written to their fields in `CEF_TO_ECS_FIELDS`, or under
+/// `cef.extensions` when they have none. Numeric extensions are JSON numbers, and
+/// timestamps in milliseconds since the epoch are written in RFC 3339. Values that
+/// don't fit their field's type (a port that isn't a number, or a timestamp in the
+/// standard's other formats) are kept under `cef.extensions` as they are.
+pub trait ToEcs: ToCef {
+    fn to_ecs(&self) -> CefResult {
+        let record = collect_record(self, &self.cef_encode_options())?;
+
+        let mut document = JsonValue::Object(vec![]);
+        for (field, value) in [
+            ("observer.vendor", &record.device_vendor),
+            ("observer.product", &record.device_product),
+            ("observer.version", &record.device_version),
+            ("event.code", &record.device_event_class_id),
+            ("event.reason", &record.name),
+            ("cef.version", &record.version),
+        ] {
+            document.insert_dotted(field, JsonValue::from(value));
+        }
+
+        // severities the spec doesn't allow (when passed through) are left out
+        let severity = record.severity.parse().unwrap_or(CefSeverity::Unknown);
+        if let Some(level) = severity.to_level() {
+            document.insert_dotted("event.severity", JsonValue::Number(level.to_string()));
+        }
+
+        for (key, value) in record.extensions.iter() {
+            match (ecs_field(key), typed_value(key, value)) {
+                (Some(field), Some(typed)) => document.insert_dotted(field, typed),
+                // extension keys may contain dots, so they're kept whole
+                (_, typed) => document.insert(
+                    &["cef", "extensions", key],
+                    typed.unwrap_or_else(|| JsonValue::from(value)),
+                ),
+            }
+        }
+
+        Ok(document.to_string())
+    }
+}
+
+impl<T: ToCef + ?Sized> ToEcs for T {}
+
+// Timestamps are written in RFC 3339, and numbers get their JSON type.
+// None when a numeric or timestamp value can't be converted.
+fn typed_value(key: &str, value: &str) -> Option<JsonValue> {
+    let data_type = dictionary::lookup(key).map(|definition| definition.data_type);
+    if data_type != Some(CefDataType::Timestamp) {
+        return match extension_value(key, value) {
+            JsonValue::String(_) if is_numeric_key(key) => None,
+            typed => Some(typed),
+        };
+    }
+
+    value
+        .parse::<i128>()
+        .ok()
+        .and_then(|millis| millis.checked_mul(1_000_000))
+        .and_then(|nanos| OffsetDateTime::from_unix_timestamp_nanos(nanos).ok())
+        .map(|timestamp| JsonValue::String(rfc5424_timestamp(timestamp)))
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+
+    #[test]
+    fn test_to_ecs() {
+        let record = parse_cef("CEF:0|polyverse|zerotect|V1|LoginFailed|Login failed|High|src=10.0.0.1 spt=4242 dst=10.0.0.2 dpt=22 suser=alice rt=1588803149123 cn1=7 cn1Label=Attempts dpt2=x").unwrap();
+        assert_eq!(
+            record.to_ecs().unwrap(),
+            concat!(
+                r#"{"observer":{"vendor":"polyverse","product":"zerotect","version":"V1"},"#,
+                r#""event":{"code":"LoginFailed","reason":"Login failed","severity":8},"#,
+                r#""cef":{"version":"0","extensions":{"cn1":7,"cn1Label":"Attempts","dpt2":"x"}},"#,
+                r#""destination":{"port":22,"ip":"10.0.0.2"},"#,
+                r#""@timestamp":"2020-05-06T22:12:29.123000Z","#,
+                r#""source":{"port":4242,"ip":"10.0.0.1","user":{"name":"alice"}}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_ecs_values() {
+        let record = parse_cef(
+            "CEF:0|polyverse|zerotect|V1|Login|Login|Unknown|dpt=http rt=May 06 2020 22:12:29",
+        )
+        .unwrap();
+        assert_eq!(
+            record.to_ecs().unwrap(),
+            concat!(
+                r#"{"observer":{"vendor":"polyverse","product":"zerotect","version":"V1"},"#,
+                r#""event":{"code":"Login","reason":"Login"},"#,
+                r#""cef":{"version":"0","extensions":{"dpt":"http","rt":"May 06 2020 22:12:29"}}}"#
+            )
+        );
+    }
+}
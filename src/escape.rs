@@ -0,0 +1,70 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module escapes CEF header and extension values according to the CEF spec.
+///
+/// The escaping is implemented as `fmt::Write` adapters so values may be
+/// streamed into any writer without intermediate allocations.
+use std::fmt::{Result as FmtResult, Write};
+
+/// Escapes a CEF header value according to the CEF spec:
+/// backslashes and pipes are prefixed with a backslash.
+///
+/// The spec does not allow multi-line headers, so carriage returns
+/// and line feeds are encoded as `\r` and `\n` (as they are in extensions)
+/// to prevent a value from breaking out of the current log line.
+pub fn escape_header_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    // writing into a String never fails
+    let _ = HeaderEscaper(&mut escaped).write_str(value);
+    escaped
+}
+
+/// Escapes a CEF extension value according to the CEF spec:
+/// backslashes and equal signs are prefixed with a backslash,
+/// and carriage returns/line feeds are encoded as `\r` and `\n`.
+///
+/// Pipes need no escaping in extension values.
+pub fn escape_extension_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    // writing into a String never fails
+    let _ = ExtensionEscaper(&mut escaped).write_str(value);
+    escaped
+}
+
+/// Writes everything written through it into the inner writer
+/// escaped as a CEF header value
+pub(crate) struct HeaderEscaper<'a, W: Write + ?Sized>(pub &'a mut W);
+
+impl<'a, W: Write + ?Sized> Write for HeaderEscaper<'a, W> {
+    fn write_str(&mut self, s: &str) -> FmtResult {
+        for c in s.chars() {
+            match c {
+                '\\' => self.0.write_str("\\\\")?,
+                '|' => self.0.write_str("\\|")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\n' => self.0.write_str("\\n")?,
+                _ => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes everything written through it into the inner writer
+/// escaped as a CEF extension value
+pub(crate) struct ExtensionEscaper<'a, W: Write + ?Sized>(pub &'a mut W);
+
+impl<'a, W: Write + ?Sized> Write for ExtensionEscaper<'a, W> {
+    fn write_str(&mut self, s: &str) -> FmtResult {
+        for c in s.chars() {
+            match c {
+                '\\' => self.0.write_str("\\\\")?,
+                '=' => self.0.write_str("\\=")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\n' => self.0.write_str("\\n")?,
+                _ => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module renders items as JSON objects, and holds the minimal JSON
+/// document the other JSON renderings (ECS, OCSF) are built with.
+use crate::dictionary::{self, CefDataType};
+use crate::{collect_record, CefRecord, CefResult, ToCef};
+
+/// The names extension keys are given in the `extensions` object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonKeys {
+    /// Keys as they appear in CEF (i.e. `src`)
+    #[default]
+    Short,
+    /// Standard keys expanded to their full ArcSight names (i.e. `sourceAddress`).
+    /// Custom keys are left as they are.
+    Full,
+}
+
+/// This trait emits a JSON object with a `header` object holding the seven
+/// CEF headers and an `extensions` object holding the extensions, and is
+/// implemented for everything that implements `ToCef`.
+///
+/// Both are collected exactly as `to_cef` collects them (duplicate keys,
+/// severity policy and key validation included), so the two never disagree.
+/// Every value is a JSON string.
+pub trait ToCefJson: ToCef {
+    fn to_cef_json(&self) -> CefResult {
+        self.to_cef_json_with_keys(JsonKeys::default())
+    }
+
+    fn to_cef_json_with_keys(&self, keys: JsonKeys) -> CefResult {
+        let record = collect_record(self, &self.cef_encode_options())?;
+        Ok(record_to_json(&record, keys).to_string())
+    }
+}
+
+impl<T: ToCef + ?Sized> ToCefJson for T {}
+
+fn record_to_json(record: &CefRecord, keys: JsonKeys) -> JsonValue {
+    let header = JsonValue::Object(vec![
+        ("version".to_owned(), JsonValue::from(&record.version)),
+        (
+            "deviceVendor".to_owned(),
+            JsonValue::from(&record.device_vendor),
+        ),
+        (
+            "deviceProduct".to_owned(),
+            JsonValue::from(&record.device_product),
+        ),
+        (
+            "deviceVersion".to_owned(),
+            JsonValue::from(&record.device_version),
+        ),
+        (
+            "deviceEventClassId".to_owned(),
+            JsonValue::from(&record.device_event_class_id),
+        ),
+        ("name".to_owned(), JsonValue::from(&record.name)),
+        ("severity".to_owned(), JsonValue::from(&record.severity)),
+    ]);
+
+    let extensions = record
+        .extensions
+        .iter()
+        .map(|(key, value)| {
+            let key = match (keys, dictionary::lookup(key)) {
+                (JsonKeys::Full, Some(definition)) => definition.full_name,
+                _ => key.as_str(),
+            };
+            (key.to_owned(), JsonValue::from(value))
+        })
+        .collect();
+
+    JsonValue::Object(vec![
+        ("header".to_owned(), header),
+        ("extensions".to_owned(), JsonValue::Object(extensions)),
+    ])
+}
+
+/// Is the extension's standard key numeric (timestamps in milliseconds since the epoch included)?
+pub(crate) fn is_numeric_key(key: &str) -> bool {
+    matches!(
+        dictionary::lookup(key).map(|definition| definition.data_type),
+        Some(CefDataType::Integer)
+            | Some(CefDataType::Long)
+            | Some(CefDataType::Timestamp)
+            | Some(CefDataType::FloatingPoint)
+            | Some(CefDataType::Double)
+    )
+}
+
+/// An extension's value as a JSON number when its standard key is numeric (see
+/// `is_numeric_key`) and the value parses as one, otherwise as a JSON string
+pub(crate) fn extension_value(key: &str, value: &str) -> JsonValue {
+    let number = match dictionary::lookup(key).map(|definition| definition.data_type) {
+        Some(CefDataType::Integer) | Some(CefDataType::Long) | Some(CefDataType::Timestamp) => {
+            value.parse::<i64>().ok().map(|n| n.to_string())
+        }
+        Some(CefDataType::FloatingPoint) | Some(CefDataType::Double) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map(|n| n.to_string()),
+        _ => None,
+    };
+
+    match number {
+        Some(number) => JsonValue::Number(number),
+        None => JsonValue::String(value.to_owned()),
+    }
+}
+
+/// A minimal JSON document, written with its members in the order given
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonValue {
+    String(String),
+    /// A number, already formatted
+    Number(String),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Sets the member at the path of keys (i.e. `["source", "ip"]`), creating
+    /// the objects along the way and replacing anything that isn't one
+    pub(crate) fn insert(&mut self, path: &[&str], value: JsonValue) {
+        let (key, rest) = match path.split_first() {
+            Some(split) => split,
+            None => {
+                *self = value;
+                return;
+            }
+        };
+
+        if !matches!(self, JsonValue::Object(_)) {
+            *self = JsonValue::Object(vec![]);
+        }
+        if let JsonValue::Object(members) = self {
+            let index = match members.iter().position(|(k, _)| k == key) {
+                Some(index) => index,
+                None => {
+                    members.push((key.to_string(), JsonValue::Object(vec![])));
+                    members.len() - 1
+                }
+            };
+            members[index].1.insert(rest, value);
+        }
+    }
+
+    /// Sets the member at a dotted path (i.e. `source.ip`), as `insert` does
+    pub(crate) fn insert_dotted(&mut self, path: &str, value: JsonValue) {
+        let path: Vec<&str> = path.split('.').collect();
+        self.insert(&path, value);
+    }
+}
+
+impl From<&String> for JsonValue {
+    fn from(s: &String) -> Self {
+        JsonValue::String(s.clone())
+    }
+}
+
+impl std::fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonValue::String(s) => write_json_string(f, s),
+            JsonValue::Number(n) => f.write_str(n),
+            JsonValue::Object(members) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+
+    #[test]
+    fn test_to_cef_json() {
+        let record = parse_cef("CEF:0|polyverse|zerotect|V1|LoginFailed|Login \"failed\"|7|src=10.0.0.1 suser=alice cs1=a\\\\b cs1Label=Path msg=line\\nbreak").unwrap();
+        assert_eq!(
+            record.to_cef_json().unwrap(),
+            concat!(
+                r#"{"header":{"version":"0","deviceVendor":"polyverse","deviceProduct":"zerotect","deviceVersion":"V1","#,
+                r#""deviceEventClassId":"LoginFailed","name":"Login \"failed\"","severity":"7"},"#,
+                r#""extensions":{"cs1":"a\\b","cs1Label":"Path","msg":"line\nbreak","src":"10.0.0.1","suser":"alice"}}"#
+            )
+        );
+
+        assert_eq!(
+            record.to_cef_json_with_keys(JsonKeys::Full).unwrap(),
+            concat!(
+                r#"{"header":{"version":"0","deviceVendor":"polyverse","deviceProduct":"zerotect","deviceVersion":"V1","#,
+                r#""deviceEventClassId":"LoginFailed","name":"Login \"failed\"","severity":"7"},"#,
+                r#""extensions":{"deviceCustomString1":"a\\b","deviceCustomString1Label":"Path","message":"line\nbreak","#,
+                r#""sourceAddress":"10.0.0.1","sourceUserName":"alice"}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_matches_cef() {
+        let record = parse_cef(
+            "CEF:0|polyverse|zerotect|V1|Login|Login|10|dst=10.0.0.2 src=10.0.0.1 x=\u{1}",
+        )
+        .unwrap();
+        let reparsed = parse_cef(&record.to_cef().unwrap()).unwrap();
+        assert_eq!(
+            record.to_cef_json().unwrap(),
+            reparsed.to_cef_json().unwrap()
+        );
+        assert!(record
+            .to_cef_json()
+            .unwrap()
+            .ends_with(r#""extensions":{"dst":"10.0.0.2","src":"10.0.0.1","x":"\u0001"}}"#));
+    }
+}
@@ -0,0 +1,204 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module renders items as IBM QRadar's Log Event Extended Format (LEEF),
+/// reusing the CEF header and extension traits.
+use crate::{
+    collect_record, escape_header_value, CefConversionError, CefResult, CefSeverity, ToCef,
+};
+
+/// Standard CEF keys whose LEEF equivalents are named differently (or identically,
+/// for keys LEEF predefines). Keys not listed keep their CEF names.
+#[rustfmt::skip]
+pub const CEF_TO_LEEF_KEYS: &[(&str, &str)] = &[
+    ("cat", "cat"),
+    ("destinationTranslatedAddress", "dstPostNAT"),
+    ("dmac", "dstMAC"),
+    ("dpt", "dstPort"),
+    ("dst", "dst"),
+    ("in", "srcBytes"),
+    ("out", "dstBytes"),
+    ("proto", "proto"),
+    ("request", "url"),
+    ("rt", "devTime"),
+    ("smac", "srcMAC"),
+    ("sourceTranslatedAddress", "srcPostNAT"),
+    ("spt", "srcPort"),
+    ("src", "src"),
+    ("suser", "usrName"),
+];
+
+/// The format of `rt` values that aren't milliseconds since the epoch (the CEF spec's
+/// other timestamp format), announced to QRadar through `devTimeFormat`
+pub const LEEF_DEV_TIME_FORMAT: &str = "MMM dd yyyy HH:mm:ss";
+
+/// The LEEF key for a CEF extension key
+pub fn leef_key(cef_key: &str) -> &str {
+    CEF_TO_LEEF_KEYS
+        .iter()
+        .find(|(cef, _)| *cef == cef_key)
+        .map(|(_, leef)| *leef)
+        .unwrap_or(cef_key)
+}
+
+/// The LEEF version to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeefVersion {
+    /// LEEF 1.0, with tab-separated attributes
+    #[default]
+    V1,
+    /// LEEF 2.0, with attributes separated by the given delimiter
+    V2 { delimiter: char },
+}
+
+/// This trait emits a LEEF string, and is implemented for everything
+/// that implements `ToCef`.
+///
+/// The DeviceVendor, DeviceProduct, DeviceVersion and DeviceEventClassID headers
+/// become LEEF's Vendor, Product, Version and EventID headers. LEEF has no Name
+/// header, so it's left out, and the severity becomes the `sev` attribute.
+/// Extension keys are translated with `leef_key`.
+///
+/// Values are escaped by prefixing backslashes and the delimiter with a backslash,
+/// and encoding carriage returns and line feeds as `\r` and `\n`.
+pub trait ToLeef: ToCef {
+    fn to_leef(&self) -> CefResult {
+        self.to_leef_with_version(LeefVersion::default())
+    }
+
+    fn to_leef_with_version(&self, version: LeefVersion) -> CefResult {
+        let record = collect_record(self, &self.cef_encode_options())?;
+
+        let (mut leef, delimiter) = match version {
+            LeefVersion::V1 => ("LEEF:1.0|".to_owned(), '\t'),
+            LeefVersion::V2 { delimiter } => ("LEEF:2.0|".to_owned(), delimiter),
+        };
+
+        for header in [
+            &record.device_vendor,
+            &record.device_product,
+            &record.device_version,
+            &record.device_event_class_id,
+        ] {
+            leef.push_str(&escape_header_value(header));
+            leef.push('|');
+        }
+
+        if let LeefVersion::V2 { delimiter } = version {
+            leef.push_str(&delimiter_header(delimiter)?);
+            leef.push('|');
+        }
+
+        let mut attributes = vec![];
+        // severities the spec doesn't allow (when passed through) are left out
+        let severity = record.severity.parse().unwrap_or(CefSeverity::Unknown);
+        if let Some(level) = severity.to_level() {
+            attributes.push(("sev".to_owned(), level.to_string()));
+        }
+
+        for (key, value) in record.extensions.iter() {
+            if key == "rt" && !value.chars().all(|c| c.is_ascii_digit()) {
+                attributes.push(("devTimeFormat".to_owned(), LEEF_DEV_TIME_FORMAT.to_owned()));
+            }
+            attributes.push((
+                leef_key(key).to_owned(),
+                escape_leef_value(value, delimiter),
+            ));
+        }
+
+        let attributes: Vec<String> = attributes
+            .into_iter()
+            .map(|(key, value)| [key, value].join("="))
+            .collect();
+        leef.push_str(&attributes.join(&delimiter.to_string()));
+
+        Ok(leef)
+    }
+}
+
+impl<T: ToCef + ?Sized> ToLeef for T {}
+
+// Printable delimiters are written as they are, others in hex (i.e. `x09` for tab)
+fn delimiter_header(delimiter: char) -> CefResult {
+    match delimiter {
+        '|' | '\\' | '=' => Err(CefConversionError::InvalidHeaderValue {
+            header: "Delimiter".to_owned(),
+            value: delimiter.to_string(),
+            reason: "the delimiter can't be a pipe, backslash or equal sign".to_owned(),
+        }),
+        c if c.is_ascii_graphic() => Ok(c.to_string()),
+        c if c.is_ascii() => Ok(format!("x{:02X}", c as u8)),
+        c => Err(CefConversionError::InvalidHeaderValue {
+            header: "Delimiter".to_owned(),
+            value: c.to_string(),
+            reason: "the delimiter must be a single ASCII character".to_owned(),
+        }),
+    }
+}
+
+fn escape_leef_value(value: &str, delimiter: char) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c if c == delimiter => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+
+    #[test]
+    fn test_leef_1() {
+        let record = parse_cef("CEF:0|polyverse|zerotect|V1|LoginFailed|Login failed|7|src=10.0.0.1 spt=4242 suser=alice rt=1588803149000 msg=tab\there|pipe").unwrap();
+        assert_eq!(
+            record.to_leef().unwrap(),
+            "LEEF:1.0|polyverse|zerotect|V1|LoginFailed|sev=7\tmsg=tab\\\there|pipe\tdevTime=1588803149000\tsrcPort=4242\tsrc=10.0.0.1\tusrName=alice"
+        );
+    }
+
+    #[test]
+    fn test_leef_2() {
+        let record = parse_cef("CEF:0|poly\\|verse|zerotect|V1|LoginFailed|Login failed|High|src=10.0.0.1 cs1=a^b rt=May 06 2020 22:12:29").unwrap();
+        assert_eq!(
+            record.to_leef_with_version(LeefVersion::V2 { delimiter: '^' }).unwrap(),
+            "LEEF:2.0|poly\\|verse|zerotect|V1|LoginFailed|^|sev=8^cs1=a\\^b^devTimeFormat=MMM dd yyyy HH:mm:ss^devTime=May 06 2020 22:12:29^src=10.0.0.1"
+        );
+
+        let record =
+            parse_cef("CEF:0|polyverse|zerotect|V1|Login|Login|Unknown|src=10.0.0.1").unwrap();
+        assert_eq!(
+            record
+                .to_leef_with_version(LeefVersion::V2 { delimiter: '\t' })
+                .unwrap(),
+            "LEEF:2.0|polyverse|zerotect|V1|Login|x09|src=10.0.0.1"
+        );
+
+        assert!(record
+            .to_leef_with_version(LeefVersion::V2 { delimiter: '|' })
+            .is_err());
+        assert!(record
+            .to_leef_with_version(LeefVersion::V2 { delimiter: 'é' })
+            .is_err());
+    }
+
+    #[test]
+    fn test_leef_keys() {
+        assert_eq!(leef_key("suser"), "usrName");
+        assert_eq!(leef_key("rt"), "devTime");
+        assert_eq!(leef_key("src"), "src");
+        assert_eq!(leef_key("cs1"), "cs1");
+    }
+}
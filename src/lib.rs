@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 /// Copyright 2020 Polyverse Corporation
 /// This module provides traits to allow arbitrary Rust items (structs, enums, etc.)
 /// to be converted into Common Event Format strings used by popular loggers around the world.
@@ -6,9 +5,52 @@ use std::collections::HashMap;
 /// This is primarily built to have guard rails and ensure the CEF doesn't
 /// break by accident when making changes to Rust items.
 use std::error::Error;
-use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fmt::{self, Display, Formatter, Result as FmtResult, Write};
+use std::io;
 use time::OffsetDateTime;
 
+pub mod collector;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod dictionary;
+pub mod ecs;
+mod escape;
+pub mod json;
+pub mod leef;
+#[cfg(any(feature = "logger", feature = "tracing"))]
+pub mod log_event;
+#[cfg(feature = "logger")]
+pub mod logger;
+pub mod ocsf;
+pub mod options;
+pub mod parser;
+pub mod redact;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod severity;
+#[cfg(feature = "sign")]
+pub mod sign;
+pub mod sink;
+pub mod syslog;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tracing")]
+pub mod tracing_layer;
+
+pub use collector::{CefCollector, CefWriterCollector};
+use collector::{DedupCollector, KeyCounter};
+pub use dictionary::{CefCustomKind, CefDataType, CefKeyDefinition};
+pub use ecs::ToEcs;
+use escape::HeaderEscaper;
+pub use escape::{escape_extension_value, escape_header_value};
+pub use json::{JsonKeys, ToCefJson};
+pub use leef::{LeefVersion, ToLeef};
+pub use ocsf::ToOcsf;
+pub use options::{CefEncodeOptions, DuplicateKeyPolicy};
+pub use parser::{parse_cef, CefRecord};
+use redact::RedactingCollector;
+pub use severity::{CefSeverity, CefSeverityLevel, SeverityPolicy, MAX_SEVERITY_LEVEL};
+
 /// An error consistently used all code
 /// in this module and sub-modules.
 ///
@@ -16,21 +58,177 @@ use time::OffsetDateTime;
 /// are flagged as `Unexpected(s)` with the string `s`
 /// containing the message.
 ///
+/// Errors raised inside a nested item (such as a field marked with
+/// `#[cef_ext_gobble]` or `#[cef_inherit]`) are wrapped in `Nested`
+/// with the name of the field they came from, so the full path to
+/// the offending field can be recovered using `path()`.
+///
 #[derive(Debug, PartialEq)]
 pub enum CefConversionError {
     Unexpected(String),
+    MissingHeader {
+        header: String,
+    },
+    InvalidHeaderValue {
+        header: String,
+        value: String,
+        reason: String,
+    },
+    InvalidExtensionKey {
+        key: String,
+    },
+    MissingExtension {
+        key: String,
+    },
+    InvalidExtensionValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    ValueTooLong {
+        key: String,
+        length: usize,
+        max_length: usize,
+    },
+    DatagramTooLarge {
+        length: usize,
+        max_size: usize,
+    },
+    DuplicateKey {
+        key: String,
+        first_field: Option<String>,
+        second_field: Option<String>,
+    },
+    CustomSlotsExhausted {
+        kind: String,
+        label: String,
+    },
+    WriteFailed(String),
+    Nested {
+        field: String,
+        source: Box<CefConversionError>,
+    },
+}
+
+impl CefConversionError {
+    /// Wraps an error raised by the item in the given field
+    pub fn nested(field: &str, source: CefConversionError) -> Self {
+        CefConversionError::Nested {
+            field: field.to_owned(),
+            source: Box::new(source),
+        }
+    }
+
+    /// The fields (outermost first) through which this error was raised
+    pub fn path(&self) -> Vec<&str> {
+        let mut path = vec![];
+        let mut current = self;
+        while let CefConversionError::Nested { field, source } = current {
+            path.push(field.as_str());
+            current = source;
+        }
+        path
+    }
+
+    /// The error that was originally raised, without any Nested wrappers
+    pub fn innermost(&self) -> &CefConversionError {
+        match self {
+            CefConversionError::Nested { source, .. } => source.innermost(),
+            _ => self,
+        }
+    }
 }
-impl Error for CefConversionError {}
+
+impl Error for CefConversionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CefConversionError::Nested { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
 impl Display for CefConversionError {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         match self {
             CefConversionError::Unexpected(message) => {
                 write!(f, "CefConversionError::Unexpected {}", message)
             }
+            CefConversionError::MissingHeader { header } => {
+                write!(f, "Header {} is missing", header)
+            }
+            CefConversionError::InvalidHeaderValue {
+                header,
+                value,
+                reason,
+            } => write!(
+                f,
+                "Invalid value '{}' for header {}: {}",
+                value, header, reason
+            ),
+            CefConversionError::InvalidExtensionKey { key } => {
+                write!(f, "Invalid extension key '{}'", key)
+            }
+            CefConversionError::MissingExtension { key } => {
+                write!(f, "Extension {} is missing", key)
+            }
+            CefConversionError::InvalidExtensionValue { key, value, reason } => write!(
+                f,
+                "Invalid value '{}' for extension {}: {}",
+                value, key, reason
+            ),
+            CefConversionError::ValueTooLong {
+                key,
+                length,
+                max_length,
+            } => write!(
+                f,
+                "Value for {} is {} characters long, exceeding the maximum of {}",
+                key, length, max_length
+            ),
+            CefConversionError::DatagramTooLarge { length, max_size } => write!(
+                f,
+                "Message is {} bytes long, exceeding the maximum datagram size of {}",
+                length, max_size
+            ),
+            CefConversionError::DuplicateKey {
+                key,
+                first_field,
+                second_field,
+            } => write!(
+                f,
+                "Extension key {} was provided more than once (by {} and {})",
+                key,
+                first_field.as_deref().unwrap_or("the item itself"),
+                second_field.as_deref().unwrap_or("the item itself")
+            ),
+            CefConversionError::CustomSlotsExhausted { kind, label } => write!(
+                f,
+                "No free custom {} slot left for '{}', all of them are taken",
+                kind, label
+            ),
+            CefConversionError::WriteFailed(message) => {
+                write!(f, "Unable to write CEF: {}", message)
+            }
+            CefConversionError::Nested { .. } => {
+                write!(f, "{}: {}", self.path().join("."), self.innermost())
+            }
         }
     }
 }
 
+impl From<fmt::Error> for CefConversionError {
+    fn from(_: fmt::Error) -> Self {
+        CefConversionError::WriteFailed("formatter returned an error".to_owned())
+    }
+}
+
+impl From<io::Error> for CefConversionError {
+    fn from(e: io::Error) -> Self {
+        CefConversionError::WriteFailed(e.to_string())
+    }
+}
+
 /// CefResult is the consistent result type used by all
 /// code in this module and sub-modules
 pub type CefResult = Result<String, CefConversionError>;
@@ -79,8 +277,11 @@ pub trait CefHeaderSeverity {
 /// trait that should ideally take into account any CEF extensions
 /// added by sub-fields or sub-objects from the object on which
 /// this is implemented.
+///
+/// Extensions are handed to a `CefCollector`, which decides
+/// how (and whether) to store them.
 pub trait CefExtensions {
-    fn cef_extensions(&self, collector: &mut HashMap<String, String>) -> CefExtensionsResult;
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult;
 }
 
 /// This trait emits an ArcSight Common Event Format
@@ -96,59 +297,216 @@ pub trait ToCef:
     + CefHeaderSeverity
     + CefExtensions
 {
+    /// The options `to_cef` encodes this item with. Defaults to
+    /// `CefEncodeOptions::default()`; types may override it.
+    fn cef_encode_options(&self) -> CefEncodeOptions {
+        CefEncodeOptions::default()
+    }
+
     fn to_cef(&self) -> CefResult {
-        let mut extensions: HashMap<String, String> = HashMap::new();
+        self.to_cef_with_options(&self.cef_encode_options())
+    }
+
+    /// Same as `to_cef`, but with options provided by the caller
+    /// rather than the item's own `cef_encode_options`.
+    fn to_cef_with_options(&self, options: &CefEncodeOptions) -> CefResult {
+        let record = collect_record(self, options)?;
+        Ok(record_to_cef(&record))
+    }
+
+    /// Streams the CEF string into a `fmt::Write` without buffering extensions,
+    /// escaping headers and values as they are written.
+    ///
+    /// Unlike `to_cef`, extensions are written in the order they are collected
+    /// (rather than sorted by key). The item's `SeverityPolicy`, `RedactionPolicy`
+    /// and `DuplicateKeyPolicy` are applied as they are by `to_cef`. A value already
+    /// written can't be replaced, so under `KeepLast` the extensions are collected
+    /// twice: once to count the keys, then to write the last value of each.
+    ///
+    /// Headers are all obtained before anything is written, but an error raised
+    /// while collecting extensions may leave a partial line in the writer.
+    fn write_cef(&self, w: &mut impl fmt::Write) -> CefExtensionsResult {
+        let options = self.cef_encode_options();
+        let headers = [
+            self.cef_header_version()?,
+            self.cef_header_device_vendor()?,
+            self.cef_header_device_product()?,
+            self.cef_header_device_version()?,
+            self.cef_header_device_event_class_id()?,
+            self.cef_header_name()?,
+            options.severity.apply(self.cef_header_severity()?)?,
+        ];
+
+        w.write_str("CEF:")?;
+        for header in headers.iter() {
+            HeaderEscaper(w).write_str(header)?;
+            w.write_char('|')?;
+        }
 
-        // get our extensions
-        if let Err(err) = self.cef_extensions(&mut extensions) {
-            return Err(err);
+        let mut writer = CefWriterCollector::new(w).with_duplicate_keys(options.duplicate_keys);
+        if options.duplicate_keys == DuplicateKeyPolicy::KeepLast {
+            // values already written can't be replaced, so find the last ones first
+            let mut counter = KeyCounter::default();
+            self.cef_extensions(&mut RedactingCollector::new(
+                &mut counter,
+                &options.redaction,
+            ))?;
+            writer = writer.with_key_counts(counter.keys);
+        }
+
+        self.cef_extensions(&mut RedactingCollector::new(
+            &mut writer,
+            &options.redaction,
+        ))
+    }
+
+    /// Streams the CEF string into an `io::Write` the same way `write_cef` does.
+    ///
+    /// Many small writes are issued, so unbuffered writers (files, sockets)
+    /// should be wrapped in a `std::io::BufWriter`.
+    fn write_cef_io(&self, w: &mut impl io::Write) -> CefExtensionsResult {
+        let mut adapter = IoWriteAdapter {
+            inner: w,
+            error: None,
         };
 
-        // make it into key=value strings
-        let mut kvstrs: Vec<String> = extensions
-            .into_iter()
-            .map(|(key, value)| [key, value].join("="))
-            .collect();
+        match (self.write_cef(&mut adapter), adapter.error) {
+            (Err(CefConversionError::WriteFailed(_)), Some(io_error)) => Err(io_error.into()),
+            (result, _) => result,
+        }
+    }
+}
 
-        kvstrs.sort_unstable();
+/// Collects an item's headers and extensions the way `to_cef` does, with the
+/// duplicate key and severity policies applied, extensions sorted by key and
+/// keys validated, but nothing escaped. Every rendering of an item is built
+/// from this, so they never disagree.
+pub(crate) fn collect_record<T: ToCef + ?Sized>(
+    item: &T,
+    options: &CefEncodeOptions,
+) -> Result<CefRecord, CefConversionError> {
+    // sorted by key, so the output is stable
+    let mut collector = DedupCollector::new(options.duplicate_keys);
+
+    // get our extensions
+    item.cef_extensions(&mut RedactingCollector::new(
+        &mut collector,
+        &options.redaction,
+    ))?;
+    let extensions = collector.into_extensions();
+
+    if let Some(key) = extensions.keys().find(|key| !is_valid_extension_key(key)) {
+        return Err(CefConversionError::InvalidExtensionKey { key: key.clone() });
+    }
 
-        // Make it into a "key1=value1 key2=value2" string (each key=value string concatenated and separated by spaces)
-        let extensionsstr = kvstrs.join(" ");
+    Ok(CefRecord {
+        version: item.cef_header_version()?,
+        device_vendor: item.cef_header_device_vendor()?,
+        device_product: item.cef_header_device_product()?,
+        device_version: item.cef_header_device_version()?,
+        device_event_class_id: item.cef_header_device_event_class_id()?,
+        name: item.cef_header_name()?,
+        severity: options.severity.apply(item.cef_header_severity()?)?,
+        extensions: extensions.into_iter().collect(),
+    })
+}
 
-        let mut cef_entry = String::new();
-        cef_entry.push_str("CEF:");
-        cef_entry.push_str(&self.cef_header_version()?);
-        cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_device_vendor()?);
-        cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_device_product()?);
-        cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_device_version()?);
+/// Writes an already collected record as a CEF string
+pub(crate) fn record_to_cef(record: &CefRecord) -> String {
+    // make it into key=value strings
+    let kvstrs: Vec<String> = record
+        .extensions
+        .iter()
+        .map(|(key, value)| [key.as_str(), &escape_extension_value(value)].join("="))
+        .collect();
+
+    // Make it into a "key1=value1 key2=value2" string (each key=value string concatenated and separated by spaces)
+    let extensionsstr = kvstrs.join(" ");
+
+    let mut cef_entry = String::new();
+    cef_entry.push_str("CEF:");
+    for header in [
+        &record.version,
+        &record.device_vendor,
+        &record.device_product,
+        &record.device_version,
+        &record.device_event_class_id,
+        &record.name,
+        &record.severity,
+    ] {
+        cef_entry.push_str(&escape_header_value(header));
         cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_device_event_class_id()?);
-        cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_name()?);
-        cef_entry.push('|');
-        cef_entry.push_str(&self.cef_header_severity()?);
-        cef_entry.push('|');
-        cef_entry.push_str(extensionsstr.as_str());
+    }
+    cef_entry.push_str(extensionsstr.as_str());
+
+    cef_entry
+}
+
+/// Adapts an io::Write into a fmt::Write, holding on to
+/// the io::Error that fmt::Error is unable to carry
+struct IoWriteAdapter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write> fmt::Write for IoWriteAdapter<'a, W> {
+    fn write_str(&mut self, s: &str) -> FmtResult {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// This trait constructs an item from a parsed CEF line,
+/// reading back the headers and extensions that `ToCef` emits.
+///
+/// It is the inverse of `ToCef` and is intended to be derived
+/// alongside it using the same attributes.
+pub trait FromCef: Sized {
+    fn from_cef_record(record: &CefRecord) -> Result<Self, CefConversionError>;
 
-        Ok(cef_entry)
+    fn from_cef(line: &str) -> Result<Self, CefConversionError> {
+        Self::from_cef_record(&parse_cef(line)?)
     }
 }
 
+/// Checks an extension key against the CEF key grammar: keys are
+/// non-empty and made up of ASCII alphanumerics, underscores and dots,
+/// which guarantees they never contain spaces, equal signs or pipes.
+pub fn is_valid_extension_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
 /// Implement CefExtensions (since it's defined here) for type
 /// DateTime<Utc>
 impl CefExtensions for OffsetDateTime {
     /// we serialize using:
     /// Milliseconds since January 1, 1970 (integer). (This time format supplies an integer
     /// with the count in milliseconds from January 1, 1970 to the time the event occurred.)
-    fn cef_extensions(&self, collector: &mut HashMap<String, String>) -> CefExtensionsResult {
-        collector.insert(
-            "rt".to_owned(),
-            format!("{}", self.unix_timestamp_nanos() / 1000000),
-        );
-        Ok(())
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        collector.insert_fmt(
+            "rt",
+            format_args!("{}", self.unix_timestamp_nanos() / 1000000),
+        )
+    }
+}
+
+/// Implement FromCef (since it's defined here) for type
+/// DateTime<Utc>, reading back the "rt" extension emitted above.
+impl FromCef for OffsetDateTime {
+    fn from_cef_record(record: &CefRecord) -> Result<Self, CefConversionError> {
+        let millis: i128 = record.parse_extension("rt")?;
+        OffsetDateTime::from_unix_timestamp_nanos(millis * 1000000).map_err(|e| {
+            CefConversionError::InvalidExtensionValue {
+                key: "rt".to_owned(),
+                value: millis.to_string(),
+                reason: e.to_string(),
+            }
+        })
     }
 }
 
@@ -158,6 +516,7 @@ impl CefExtensions for OffsetDateTime {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::HashMap;
 
     struct GoodExample {}
 
@@ -205,11 +564,11 @@ mod test {
     }
 
     impl CefExtensions for GoodExample {
-        fn cef_extensions(&self, collector: &mut HashMap<String, String>) -> CefExtensionsResult {
-            collector.insert("customField1".to_owned(), "customValue1".to_owned());
-            collector.insert("customField2".to_owned(), "customValue2".to_owned());
-            collector.insert("customField3".to_owned(), "customValue2".to_owned());
-            collector.insert("customField4".to_owned(), "customValue3".to_owned());
+        fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+            collector.insert("customField1", "customValue1")?;
+            collector.insert("customField2", "customValue2")?;
+            collector.insert("customField3", "customValue2")?;
+            collector.insert("customField4", "customValue3")?;
             Ok(())
         }
     }
@@ -261,8 +620,62 @@ mod test {
     }
 
     impl CefExtensions for BadExample {
-        fn cef_extensions(&self, collector: &mut HashMap<String, String>) -> CefExtensionsResult {
-            collector.insert("customField".to_owned(), "customValue".to_owned());
+        fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+            collector.insert("customField", "customValue")?;
+            Ok(())
+        }
+    }
+
+    struct HostileExample {}
+    impl ToCef for HostileExample {}
+    impl CefHeaderVersion for HostileExample {
+        fn cef_header_version(&self) -> CefResult {
+            Ok("0".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceVendor for HostileExample {
+        fn cef_header_device_vendor(&self) -> CefResult {
+            Ok("poly|verse".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceProduct for HostileExample {
+        fn cef_header_device_product(&self) -> CefResult {
+            Ok("zero\\tect".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceVersion for HostileExample {
+        fn cef_header_device_version(&self) -> CefResult {
+            Ok("V1=V2".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceEventClassID for HostileExample {
+        fn cef_header_device_event_class_id(&self) -> CefResult {
+            Ok("Linux|Kernel\\|Trap".to_owned())
+        }
+    }
+
+    impl CefHeaderName for HostileExample {
+        fn cef_header_name(&self) -> CefResult {
+            Ok("Linux Kernel Trap\nCEF:0|injected|line".to_owned())
+        }
+    }
+
+    impl CefHeaderSeverity for HostileExample {
+        fn cef_header_severity(&self) -> CefResult {
+            Ok("10".to_owned())
+        }
+    }
+
+    impl CefExtensions for HostileExample {
+        fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+            collector.insert("equals", "a=b c=d")?;
+            collector.insert("backslash", "C:\\Windows\\")?;
+            collector.insert("newlines", "line1\r\nline2")?;
+            collector.insert("pipes", "a|b")?;
             Ok(())
         }
     }
@@ -286,6 +699,225 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_hostile_values_are_escaped() {
+        let example = HostileExample {};
+        let result = example.to_cef();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "CEF:0|poly\\|verse|zero\\\\tect|V1=V2|Linux\\|Kernel\\\\\\|Trap|Linux Kernel Trap\\nCEF:0\\|injected\\|line|10|backslash=C:\\\\Windows\\\\ equals=a\\=b c\\=d newlines=line1\\r\\nline2 pipes=a|b");
+    }
+
+    struct DuplicateExample {}
+    impl ToCef for DuplicateExample {
+        fn cef_encode_options(&self) -> CefEncodeOptions {
+            CefEncodeOptions::default().with_duplicate_keys(DuplicateKeyPolicy::KeepFirst)
+        }
+    }
+    impl CefHeaderVersion for DuplicateExample {
+        fn cef_header_version(&self) -> CefResult {
+            Ok("0".to_owned())
+        }
+    }
+    impl CefHeaderDeviceVendor for DuplicateExample {
+        fn cef_header_device_vendor(&self) -> CefResult {
+            Ok("polyverse".to_owned())
+        }
+    }
+    impl CefHeaderDeviceProduct for DuplicateExample {
+        fn cef_header_device_product(&self) -> CefResult {
+            Ok("zerotect".to_owned())
+        }
+    }
+    impl CefHeaderDeviceVersion for DuplicateExample {
+        fn cef_header_device_version(&self) -> CefResult {
+            Ok("V1".to_owned())
+        }
+    }
+    impl CefHeaderDeviceEventClassID for DuplicateExample {
+        fn cef_header_device_event_class_id(&self) -> CefResult {
+            Ok("Dup".to_owned())
+        }
+    }
+    impl CefHeaderName for DuplicateExample {
+        fn cef_header_name(&self) -> CefResult {
+            Ok("Duplicate".to_owned())
+        }
+    }
+    impl CefHeaderSeverity for DuplicateExample {
+        fn cef_header_severity(&self) -> CefResult {
+            Ok("1".to_owned())
+        }
+    }
+    impl CefExtensions for DuplicateExample {
+        fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+            collector.insert("src", "10.0.0.1")?;
+            collector.enter_field("peer");
+            collector.insert("src", "10.0.0.2")?;
+            collector.exit_field();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_duplicate_key_policy() {
+        let example = DuplicateExample {};
+        let prefix = "CEF:0|polyverse|zerotect|V1|Dup|Duplicate|1|";
+
+        // the type's own options apply by default
+        assert_eq!(example.to_cef().unwrap(), format!("{}src=10.0.0.1", prefix));
+
+        // and may be overridden per call
+        let options = CefEncodeOptions::default();
+        assert_eq!(
+            example.to_cef_with_options(&options).unwrap(),
+            format!("{}src=10.0.0.2", prefix)
+        );
+
+        let options = options.with_duplicate_keys(DuplicateKeyPolicy::AutoSuffix);
+        assert_eq!(
+            example.to_cef_with_options(&options).unwrap(),
+            format!("{}src=10.0.0.1 src_2=10.0.0.2", prefix)
+        );
+
+        let options = options.with_duplicate_keys(DuplicateKeyPolicy::Error);
+        assert_eq!(
+            example.to_cef_with_options(&options),
+            Err(CefConversionError::DuplicateKey {
+                key: "src".to_owned(),
+                first_field: None,
+                second_field: Some("peer".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_cef() {
+        let example = GoodExample {};
+        let mut output = String::new();
+        assert!(example.write_cef(&mut output).is_ok());
+        assert_eq!(output, example.to_cef().unwrap());
+
+        let mut bytes = Vec::<u8>::new();
+        assert!(example.write_cef_io(&mut bytes).is_ok());
+        assert_eq!(String::from_utf8(bytes).unwrap(), output);
+
+        let example = HostileExample {};
+        let mut output = String::new();
+        assert!(example.write_cef(&mut output).is_ok());
+        assert!(output.starts_with("CEF:0|poly\\|verse|zero\\\\tect|"));
+        assert!(output.contains("equals=a\\=b c\\=d"));
+
+        // only the last value of a repeated key is written, as to_cef keeps it
+        let record = parse_cef("CEF:0|v|p|1|c|n|3|a=1 b=first b=last").unwrap();
+        let mut output = String::new();
+        assert!(record.write_cef(&mut output).is_ok());
+        assert_eq!(output, "CEF:0|v|p|1|c|n|3|a=1 b=last");
+        assert_eq!(output, record.to_cef().unwrap());
+    }
+
+    #[test]
+    fn test_write_cef_errors() {
+        // header errors surface before anything is written
+        let mut output = String::new();
+        assert_eq!(
+            BadExample {}.write_cef(&mut output),
+            Err(CefConversionError::Unexpected(
+                "This error should propagate".to_owned()
+            ))
+        );
+        assert_eq!(output, "");
+
+        struct FailingWriter {}
+        impl io::Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert_eq!(
+            GoodExample {}.write_cef_io(&mut FailingWriter {}),
+            Err(CefConversionError::WriteFailed("disk on fire".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_escape_header_value() {
+        assert_eq!(escape_header_value("plain value"), "plain value");
+        assert_eq!(escape_header_value("a|b"), "a\\|b");
+        assert_eq!(escape_header_value("a\\b"), "a\\\\b");
+        assert_eq!(escape_header_value("a\\|b"), "a\\\\\\|b");
+        assert_eq!(escape_header_value("a=b"), "a=b");
+        assert_eq!(escape_header_value("a\r\nb"), "a\\r\\nb");
+    }
+
+    #[test]
+    fn test_escape_extension_value() {
+        assert_eq!(escape_extension_value("plain value"), "plain value");
+        assert_eq!(escape_extension_value("a=b"), "a\\=b");
+        assert_eq!(escape_extension_value("a\\b"), "a\\\\b");
+        assert_eq!(escape_extension_value("a\\=b"), "a\\\\\\=b");
+        assert_eq!(escape_extension_value("a|b"), "a|b");
+        assert_eq!(escape_extension_value("a\r\nb"), "a\\r\\nb");
+    }
+
+    #[test]
+    fn test_invalid_extension_key() {
+        for key in &["bad key", "bad=key", "bad|key", ""] {
+            let record = CefRecord {
+                extensions: vec![((*key).to_owned(), "value".to_owned())],
+                ..Default::default()
+            };
+            assert_eq!(
+                record.to_cef(),
+                Err(CefConversionError::InvalidExtensionKey {
+                    key: (*key).to_owned()
+                })
+            );
+        }
+
+        assert!(is_valid_extension_key("cs1Label"));
+        assert!(is_valid_extension_key("person_age"));
+        assert!(is_valid_extension_key("process.name"));
+    }
+
+    #[test]
+    fn test_nested_error_path() {
+        let err = CefConversionError::nested(
+            "outer",
+            CefConversionError::nested(
+                "inner",
+                CefConversionError::MissingExtension {
+                    key: "src".to_owned(),
+                },
+            ),
+        );
+        assert_eq!(err.path(), vec!["outer", "inner"]);
+        assert_eq!(
+            err.innermost(),
+            &CefConversionError::MissingExtension {
+                key: "src".to_owned()
+            }
+        );
+        assert_eq!(format!("{}", err), "outer.inner: Extension src is missing");
+        assert!(err.source().is_some());
+
+        let flat = CefConversionError::DuplicateKey {
+            key: "src".to_owned(),
+            first_field: None,
+            second_field: Some("inner".to_owned()),
+        };
+        assert_eq!(
+            format!("{}", flat),
+            "Extension key src was provided more than once (by the item itself and inner)"
+        );
+        assert!(flat.path().is_empty());
+        assert_eq!(flat.innermost(), &flat);
+        assert!(flat.source().is_none());
+    }
+
     #[test]
     fn test_ext_for_datetime() {
         let mut collector = HashMap::<String, String>::new();
@@ -299,4 +931,15 @@ mod test {
         let rt = maybe_rt.unwrap();
         assert_eq!(rt, "3435315515325");
     }
+
+    #[test]
+    fn test_from_cef_for_datetime() {
+        let example = OffsetDateTime::from_unix_timestamp_nanos(3435315515325000000).unwrap();
+        let record = CefRecord {
+            extensions: vec![("rt".to_owned(), "3435315515325".to_owned())],
+            ..Default::default()
+        };
+        assert_eq!(OffsetDateTime::from_cef_record(&record), Ok(example));
+        assert!(OffsetDateTime::from_cef_record(&CefRecord::default()).is_err());
+    }
 }
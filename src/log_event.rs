@@ -0,0 +1,141 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides the CEF event that the `log` and `tracing` integrations
+/// turn their records into. It's only available with the `logger` or `tracing` feature.
+use crate::{
+    CefCollector, CefExtensions, CefExtensionsResult, CefHeaderDeviceEventClassID,
+    CefHeaderDeviceProduct, CefHeaderDeviceVendor, CefHeaderDeviceVersion, CefHeaderName,
+    CefHeaderSeverity, CefHeaderVersion, CefResult, CefSeverity, ToCef,
+};
+use std::borrow::Cow;
+
+/// The levels `log` and `tracing` have in common
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CefLogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl CefLogLevel {
+    /// The CEF severity for the level:
+    /// Error is 8 (High), Warn is 5 (Medium), Info is 3 (Low), Debug is 1 and Trace is 0.
+    pub fn severity(self) -> CefSeverity {
+        match self {
+            CefLogLevel::Error => CefSeverity::clamped(8),
+            CefLogLevel::Warn => CefSeverity::clamped(5),
+            CefLogLevel::Info => CefSeverity::clamped(3),
+            CefLogLevel::Debug => CefSeverity::clamped(1),
+            CefLogLevel::Trace => CefSeverity::clamped(0),
+        }
+    }
+}
+
+/// A logging framework's record seen as a CEF event.
+///
+/// The device headers come from the integration's configuration, the level is
+/// the Severity (see `CefLogLevel::severity`) and the fields are extensions,
+/// already limited to valid extension keys.
+pub struct CefLogEvent<'a> {
+    pub(crate) device_vendor: &'a str,
+    pub(crate) device_product: &'a str,
+    pub(crate) device_version: &'a str,
+    pub(crate) device_event_class_id: &'a str,
+    pub(crate) name: Cow<'a, str>,
+    pub(crate) level: CefLogLevel,
+    pub(crate) fields: Vec<(Cow<'a, str>, String)>,
+}
+
+impl CefHeaderVersion for CefLogEvent<'_> {
+    fn cef_header_version(&self) -> CefResult {
+        Ok("0".to_owned())
+    }
+}
+
+impl CefHeaderDeviceVendor for CefLogEvent<'_> {
+    fn cef_header_device_vendor(&self) -> CefResult {
+        Ok(self.device_vendor.to_owned())
+    }
+}
+
+impl CefHeaderDeviceProduct for CefLogEvent<'_> {
+    fn cef_header_device_product(&self) -> CefResult {
+        Ok(self.device_product.to_owned())
+    }
+}
+
+impl CefHeaderDeviceVersion for CefLogEvent<'_> {
+    fn cef_header_device_version(&self) -> CefResult {
+        Ok(self.device_version.to_owned())
+    }
+}
+
+impl CefHeaderDeviceEventClassID for CefLogEvent<'_> {
+    fn cef_header_device_event_class_id(&self) -> CefResult {
+        Ok(self.device_event_class_id.to_owned())
+    }
+}
+
+impl CefHeaderName for CefLogEvent<'_> {
+    fn cef_header_name(&self) -> CefResult {
+        Ok(self.name.to_string())
+    }
+}
+
+impl CefHeaderSeverity for CefLogEvent<'_> {
+    fn cef_header_severity(&self) -> CefResult {
+        Ok(self.level.severity().to_string())
+    }
+}
+
+impl CefExtensions for CefLogEvent<'_> {
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        for (key, value) in self.fields.iter() {
+            collector.insert(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToCef for CefLogEvent<'_> {}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::sink::CefSink;
+    use crate::syslog::{SyslogFormat, SyslogFormatter};
+    use crate::CefExtensionsResult;
+    use std::sync::{Arc, Mutex};
+
+    /// A sink keeping the messages it's sent, for the integrations' tests
+    pub(crate) struct MemorySink {
+        formatter: SyslogFormatter,
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MemorySink {
+        pub(crate) fn new(format: SyslogFormat) -> (Self, Arc<Mutex<Vec<String>>>) {
+            let messages = Arc::new(Mutex::new(vec![]));
+            let sink = Self {
+                formatter: SyslogFormatter::new(format),
+                messages: messages.clone(),
+            };
+            (sink, messages)
+        }
+    }
+
+    impl CefSink for MemorySink {
+        fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+            self.messages.lock().unwrap().push(message.to_owned());
+            Ok(())
+        }
+
+        fn formatter(&self) -> &SyslogFormatter {
+            &self.formatter
+        }
+    }
+}
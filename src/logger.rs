@@ -0,0 +1,208 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a `log::Log` backend that emits every record as a CEF event.
+/// It's only available with the `logger` feature.
+use crate::is_valid_extension_key;
+use crate::log_event::{CefLogEvent, CefLogLevel};
+use crate::sink::CefSink;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+/// The device headers stamped on every record, and the most verbose level logged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CefLogConfig {
+    pub device_vendor: String,
+    pub device_product: String,
+    pub device_version: String,
+    pub level: LevelFilter,
+}
+
+impl CefLogConfig {
+    /// A configuration logging Info and above
+    pub fn new(device_vendor: &str, device_product: &str, device_version: &str) -> Self {
+        Self {
+            device_vendor: device_vendor.to_owned(),
+            device_product: device_product.to_owned(),
+            device_version: device_version.to_owned(),
+            level: LevelFilter::Info,
+        }
+    }
+
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+/// A `log::Record` seen as a CEF event.
+///
+/// The module path (or the target, when there's none) is the DeviceEventClassID,
+/// the target is the Name, the level is the Severity, the message is the `msg`
+/// extension and structured key-values are extensions of their own. A key-value
+/// named `msg` becomes `kv.msg` so it can't replace the message, and key-values
+/// whose keys can't be extension keys are skipped.
+pub fn log_event<'a>(config: &'a CefLogConfig, record: &'a Record<'a>) -> CefLogEvent<'a> {
+    let mut fields = vec![(Cow::Borrowed("msg"), record.args().to_string())];
+    // the visitor never fails
+    let _ = record.key_values().visit(&mut FieldVisitor(&mut fields));
+
+    CefLogEvent {
+        device_vendor: &config.device_vendor,
+        device_product: &config.device_product,
+        device_version: &config.device_version,
+        device_event_class_id: record.module_path().unwrap_or_else(|| record.target()),
+        name: Cow::Borrowed(record.target()),
+        level: record.level().into(),
+        fields,
+    }
+}
+
+impl From<Level> for CefLogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => CefLogLevel::Error,
+            Level::Warn => CefLogLevel::Warn,
+            Level::Info => CefLogLevel::Info,
+            Level::Debug => CefLogLevel::Debug,
+            Level::Trace => CefLogLevel::Trace,
+        }
+    }
+}
+
+struct FieldVisitor<'f, 'a>(&'f mut Vec<(Cow<'a, str>, String)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_, '_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let key = match key.as_str() {
+            "msg" => Cow::Borrowed("kv.msg"),
+            key if is_valid_extension_key(key) => Cow::Owned(key.to_owned()),
+            _ => return Ok(()),
+        };
+        self.0.push((key, value.to_string()));
+        Ok(())
+    }
+}
+
+/// A `log::Log` that sends every enabled record to a `CefSink`.
+///
+/// `Log` can't report errors, so records that fail to convert or send are dropped.
+pub struct CefLogger<S> {
+    config: CefLogConfig,
+    sink: Mutex<S>,
+}
+
+impl<S: CefSink + Send + 'static> CefLogger<S> {
+    pub fn new(config: CefLogConfig, sink: S) -> Self {
+        Self {
+            config,
+            sink: Mutex::new(sink),
+        }
+    }
+
+    /// Installs this logger as the `log` crate's global logger
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let level = self.config.level;
+        log::set_boxed_logger(Box::new(self))?;
+        // only once the logger is in place, so a failed init changes nothing
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl<S: CefSink + Send> Log for CefLogger<S> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.config.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = sink.send(&log_event(&self.config, record));
+        }
+    }
+
+    // sinks write every message as it's sent
+    fn flush(&self) {}
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log_event::test::MemorySink;
+    use crate::syslog::SyslogFormat;
+    use crate::ToCef;
+
+    fn config() -> CefLogConfig {
+        CefLogConfig::new("polyverse", "zerotect", "V1")
+    }
+
+    #[test]
+    fn test_record_to_cef() {
+        let config = config();
+        let kvs = [("suser", "alice"), ("bad key", "skipped"), ("cnt", "3")];
+        let record = Record::builder()
+            .args(format_args!("login failed for alice"))
+            .level(Level::Warn)
+            .target("auth")
+            .module_path(Some("myservice::auth"))
+            .key_values(&kvs)
+            .build();
+
+        assert_eq!(
+            log_event(&config, &record).to_cef().unwrap(),
+            "CEF:0|polyverse|zerotect|V1|myservice::auth|auth|5|cnt=3 msg=login failed for alice suser=alice"
+        );
+
+        let record = Record::builder()
+            .args(format_args!("a=b"))
+            .level(Level::Error)
+            .target("pipes|here")
+            .build();
+        assert_eq!(
+            log_event(&config, &record).to_cef().unwrap(),
+            "CEF:0|polyverse|zerotect|V1|pipes\\|here|pipes\\|here|8|msg=a\\=b"
+        );
+
+        // a key-value named msg doesn't replace the message
+        let kvs = [("msg", "from kv")];
+        let record = Record::builder()
+            .args(format_args!("the message"))
+            .level(Level::Info)
+            .target("test")
+            .key_values(&kvs)
+            .build();
+        assert_eq!(
+            log_event(&config, &record).to_cef().unwrap(),
+            "CEF:0|polyverse|zerotect|V1|test|test|3|kv.msg=from kv msg=the message"
+        );
+    }
+
+    #[test]
+    fn test_logger() {
+        let (sink, messages) = MemorySink::new(SyslogFormat::Rfc3164);
+        let logger = CefLogger::new(config().with_level(LevelFilter::Warn), sink);
+
+        for (level, message) in [(Level::Info, "skipped"), (Level::Error, "kept")] {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .target("test")
+                    .build(),
+            );
+        }
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("<11>"));
+        assert!(messages[0].ends_with("CEF: CEF:0|polyverse|zerotect|V1|test|test|8|msg=kept"));
+    }
+}
@@ -0,0 +1,200 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module renders items as Open Cybersecurity Schema Framework (OCSF) events.
+use crate::json::{extension_value, is_numeric_key, JsonValue};
+use crate::{collect_record, CefConversionError, CefRecord, CefResult, CefSeverity, ToCef};
+use time::OffsetDateTime;
+
+/// The OCSF schema version events are written in
+pub const OCSF_SCHEMA_VERSION: &str = "1.1.0";
+
+/// The class of events that don't declare one: the Base Event
+pub const OCSF_BASE_EVENT_CLASS_UID: u32 = 0;
+
+/// Standard CEF keys and the OCSF attributes they're written to.
+/// Keys not listed are kept in the `unmapped` object.
+#[rustfmt::skip]
+pub const CEF_TO_OCSF_FIELDS: &[(&str, &str)] = &[
+    ("dhost", "dst_endpoint.hostname"),
+    ("dmac", "dst_endpoint.mac"),
+    ("dpt", "dst_endpoint.port"),
+    ("dst", "dst_endpoint.ip"),
+    ("duid", "user.uid"),
+    ("duser", "user.name"),
+    ("dvc", "device.ip"),
+    ("dvchost", "device.hostname"),
+    ("end", "end_time"),
+    ("filePath", "file.path"),
+    ("fname", "file.name"),
+    ("fsize", "file.size"),
+    ("in", "traffic.bytes_in"),
+    ("msg", "message"),
+    ("out", "traffic.bytes_out"),
+    ("outcome", "status"),
+    ("proto", "connection_info.protocol_name"),
+    ("request", "http_request.url.url_string"),
+    ("requestClientApplication", "http_request.user_agent"),
+    ("requestMethod", "http_request.http_method"),
+    ("rt", "time"),
+    ("shost", "src_endpoint.hostname"),
+    ("smac", "src_endpoint.mac"),
+    ("spt", "src_endpoint.port"),
+    ("src", "src_endpoint.ip"),
+    ("start", "start_time"),
+    ("suid", "actor.user.uid"),
+    ("suser", "actor.user.name"),
+];
+
+/// The OCSF attribute for a CEF extension key, if it has one
+pub fn ocsf_field(cef_key: &str) -> Option<&'static str> {
+    CEF_TO_OCSF_FIELDS
+        .iter()
+        .find(|(cef, _)| *cef == cef_key)
+        .map(|(_, ocsf)| *ocsf)
+}
+
+/// The OCSF `severity_id` and its caption for a CEF severity:
+/// level 0 is Informational, 1-3 Low, 4-6 Medium, 7-8 High and 9-10 Critical.
+pub fn ocsf_severity(severity: CefSeverity) -> (u8, &'static str) {
+    match (severity, severity.to_named()) {
+        (CefSeverity::Level(level), _) if level.get() == 0 => (1, "Informational"),
+        (_, CefSeverity::Low) => (2, "Low"),
+        (_, CefSeverity::Medium) => (3, "Medium"),
+        (_, CefSeverity::High) => (4, "High"),
+        (_, CefSeverity::VeryHigh) => (5, "Critical"),
+        _ => (0, "Unknown"),
+    }
+}
+
+/// Renders any `ToCef` item as an OCSF event of the given class.
+///
+/// The class's category and its Unknown activity's `type_uid` are derived from
+/// `class_uid`. The severity becomes `severity_id`, DeviceVendor, DeviceProduct and
+/// DeviceVersion become `metadata.product` and DeviceEventClassID becomes
+/// `metadata.event_code`. The Name is the `message`, unless there's a `msg` extension.
+///
+/// Extensions are written to their attributes in `CEF_TO_OCSF_FIELDS`, or kept in
+/// `unmapped` when they have none. Numeric extensions (timestamps in milliseconds
+/// since the epoch included) are JSON numbers, and ones that aren't numbers are
+/// kept in `unmapped` too, since their attributes are numeric. The required `time`
+/// is the time of the conversion when there's no `rt` in milliseconds.
+pub fn to_ocsf<T: ToCef + ?Sized>(item: &T, class_uid: u32) -> CefResult {
+    let record = collect_record(item, &item.cef_encode_options())?;
+    Ok(record_to_ocsf(&record, class_uid, OffsetDateTime::now_utc())?.to_string())
+}
+
+/// This trait emits an OCSF event (see `to_ocsf`) of the item's class.
+///
+/// The class defaults to the Base Event; types may override `ocsf_class_uid`,
+/// which `#[ocsf(class_uid = 3002)]` does when deriving.
+pub trait ToOcsf: ToCef {
+    fn ocsf_class_uid(&self) -> u32 {
+        OCSF_BASE_EVENT_CLASS_UID
+    }
+
+    fn to_ocsf(&self) -> CefResult {
+        to_ocsf(self, self.ocsf_class_uid())
+    }
+}
+
+impl ToOcsf for CefRecord {}
+
+fn record_to_ocsf(
+    record: &CefRecord,
+    class_uid: u32,
+    now: OffsetDateTime,
+) -> Result<JsonValue, CefConversionError> {
+    // severities the spec doesn't allow (when passed through) are Unknown
+    let severity = record.severity.parse().unwrap_or(CefSeverity::Unknown);
+    let (severity_id, severity_name) = ocsf_severity(severity);
+
+    let number = |n: u64| JsonValue::Number(n.to_string());
+    let mut event = JsonValue::Object(vec![
+        ("class_uid".to_owned(), number(class_uid as u64)),
+        ("category_uid".to_owned(), number(class_uid as u64 / 1000)),
+        ("activity_id".to_owned(), number(0)),
+        ("type_uid".to_owned(), number(class_uid as u64 * 100)),
+        ("severity_id".to_owned(), number(severity_id as u64)),
+        (
+            "severity".to_owned(),
+            JsonValue::String(severity_name.to_owned()),
+        ),
+    ]);
+
+    for (field, value) in [
+        ("metadata.version", OCSF_SCHEMA_VERSION),
+        ("metadata.product.vendor_name", &record.device_vendor),
+        ("metadata.product.name", &record.device_product),
+        ("metadata.product.version", &record.device_version),
+        ("metadata.event_code", &record.device_event_class_id),
+        ("message", &record.name),
+    ] {
+        event.insert_dotted(field, JsonValue::String(value.to_owned()));
+    }
+
+    let mut time = None;
+    for (key, value) in record.extensions.iter() {
+        let value = extension_value(key, value);
+        let mistyped = is_numeric_key(key) && matches!(value, JsonValue::String(_));
+        match ocsf_field(key) {
+            Some("time") if !mistyped => time = Some(value),
+            Some(field) if !mistyped => event.insert_dotted(field, value),
+            _ => event.insert(&["unmapped", key], value),
+        }
+    }
+
+    let now_millis = (now.unix_timestamp_nanos() / 1_000_000).to_string();
+    event.insert(&["time"], time.unwrap_or(JsonValue::Number(now_millis)));
+
+    Ok(event)
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1588803149).unwrap()
+    }
+
+    #[test]
+    fn test_to_ocsf() {
+        let record = parse_cef("CEF:0|polyverse|zerotect|V1|LoginFailed|Login failed|High|src=10.0.0.1 spt=4242 dst=10.0.0.2 dpt=22 suser=alice rt=1588803149123 cs1=web cs1Label=Role").unwrap();
+        assert_eq!(
+            to_ocsf(&record, 3002).unwrap(),
+            concat!(
+                r#"{"class_uid":3002,"category_uid":3,"activity_id":0,"type_uid":300200,"severity_id":4,"severity":"High","#,
+                r#""metadata":{"version":"1.1.0","product":{"vendor_name":"polyverse","name":"zerotect","version":"V1"},"event_code":"LoginFailed"},"#,
+                r#""message":"Login failed","unmapped":{"cs1":"web","cs1Label":"Role"},"#,
+                r#""dst_endpoint":{"port":22,"ip":"10.0.0.2"},"src_endpoint":{"port":4242,"ip":"10.0.0.1"},"#,
+                r#""actor":{"user":{"name":"alice"}},"time":1588803149123}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_ocsf_defaults() {
+        // values that don't fit their numeric attributes are unmapped
+        let record = parse_cef(
+            "CEF:0|polyverse|zerotect|V1|Login|Login|0|dpt=http msg=hello rt=May 06 2020 22:12:29",
+        )
+        .unwrap();
+        assert_eq!(
+            record_to_ocsf(&record, 0, now()).unwrap().to_string(),
+            concat!(
+                r#"{"class_uid":0,"category_uid":0,"activity_id":0,"type_uid":0,"severity_id":1,"severity":"Informational","#,
+                r#""metadata":{"version":"1.1.0","product":{"vendor_name":"polyverse","name":"zerotect","version":"V1"},"event_code":"Login"},"#,
+                r#""message":"hello","unmapped":{"dpt":"http","rt":"May 06 2020 22:12:29"},"time":1588803149000}"#
+            )
+        );
+        assert!(record.to_ocsf().unwrap().contains(r#","time":"#));
+
+        assert_eq!(ocsf_severity(CefSeverity::Unknown), (0, "Unknown"));
+        assert_eq!(ocsf_severity(CefSeverity::clamped(5)), (3, "Medium"));
+        assert_eq!(ocsf_severity(CefSeverity::VeryHigh), (5, "Critical"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Copyright 2020 Polyverse Corporation
+//!
+//! This module provides the options which control how an item
+//! is encoded into a CEF string by `ToCef`.
+use crate::redact::RedactionPolicy;
+use crate::SeverityPolicy;
+
+/// What to do when an extension key is collected more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeyPolicy {
+    /// Fail with `CefConversionError::DuplicateKey`, naming the
+    /// fields that produced the key
+    Error,
+    /// Keep the value that was collected first
+    KeepFirst,
+    /// Keep the value that was collected last
+    #[default]
+    KeepLast,
+    /// Keep every value, renaming later ones to `key_2`, `key_3`, ...
+    AutoSuffix,
+}
+
+/// Options that control how `ToCef` encodes an item.
+///
+/// Each type provides its defaults through `ToCef::cef_encode_options`
+/// (which `#[cef_options(...)]` overrides when deriving), and they may be
+/// overridden per call with `ToCef::to_cef_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CefEncodeOptions {
+    pub duplicate_keys: DuplicateKeyPolicy,
+    pub severity: SeverityPolicy,
+    pub redaction: RedactionPolicy,
+}
+
+impl CefEncodeOptions {
+    /// Returns these options with the given duplicate key policy
+    pub fn with_duplicate_keys(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Returns these options with the given severity policy
+    pub fn with_severity(mut self, policy: SeverityPolicy) -> Self {
+        self.severity = policy;
+        self
+    }
+
+    /// Returns these options with the given keys redacted or masked,
+    /// on top of what the item's fields redact themselves
+    pub fn with_redaction(mut self, policy: RedactionPolicy) -> Self {
+        self.redaction = policy;
+        self
+    }
+}
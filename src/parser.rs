@@ -0,0 +1,608 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module parses ArcSight Common Event Format strings back into
+/// a structured `CefRecord`, which is the inverse of what `ToCef::to_cef` emits.
+use crate::{
+    CefCollector, CefConversionError, CefCustomKind, CefExtensions, CefExtensionsResult,
+    CefHeaderDeviceEventClassID, CefHeaderDeviceProduct, CefHeaderDeviceVendor,
+    CefHeaderDeviceVersion, CefHeaderName, CefHeaderSeverity, CefHeaderVersion, CefResult, FromCef,
+    ToCef,
+};
+use std::fmt::Display;
+use std::str::FromStr;
+
+const CEF_PREFIX: &str = "CEF:";
+const CEF_HEADER_COUNT: usize = 7;
+
+// The traits producing each header, in the order they appear on a CEF line
+const CEF_HEADER_TRAITS: [&str; CEF_HEADER_COUNT] = [
+    "CefHeaderVersion",
+    "CefHeaderDeviceVendor",
+    "CefHeaderDeviceProduct",
+    "CefHeaderDeviceVersion",
+    "CefHeaderDeviceEventClassID",
+    "CefHeaderName",
+    "CefHeaderSeverity",
+];
+
+/// A parsed CEF line: the seven CEF headers (unescaped)
+/// plus the extensions in the order they appeared on the line.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CefRecord {
+    pub version: String,
+    pub device_vendor: String,
+    pub device_product: String,
+    pub device_version: String,
+    pub device_event_class_id: String,
+    pub name: String,
+    pub severity: String,
+    pub extensions: Vec<(String, String)>,
+}
+
+impl CefRecord {
+    /// Returns the value of the first extension with the given key
+    pub fn extension(&self, key: &str) -> Option<&str> {
+        self.extensions
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Finds the custom slot of the given kind (i.e. `cs2`) whose
+    /// label key (i.e. `cs2Label`) holds the given label
+    pub fn custom_extension_key(&self, kind: CefCustomKind, label: &str) -> Option<&'static str> {
+        kind.slots()
+            .iter()
+            .find(|(_, label_key)| self.extension(label_key) == Some(label))
+            .map(|(slot, _)| *slot)
+    }
+
+    /// Returns the value of a header by the name of the trait
+    /// that produces it, i.e. `CefHeaderName` returns the Name header.
+    pub fn header(&self, header_trait: &str) -> Option<&str> {
+        match header_trait {
+            "CefHeaderVersion" => Some(&self.version),
+            "CefHeaderDeviceVendor" => Some(&self.device_vendor),
+            "CefHeaderDeviceProduct" => Some(&self.device_product),
+            "CefHeaderDeviceVersion" => Some(&self.device_version),
+            "CefHeaderDeviceEventClassID" => Some(&self.device_event_class_id),
+            "CefHeaderName" => Some(&self.name),
+            "CefHeaderSeverity" => Some(&self.severity),
+            _ => None,
+        }
+    }
+
+    /// Parses a header (named by the trait that produces it)
+    /// using the `FromStr` trait
+    pub fn parse_header<T>(&self, header_trait: &str) -> Result<T, CefConversionError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.header(header_trait) {
+            Some(value) => {
+                value
+                    .parse()
+                    .map_err(|e: T::Err| CefConversionError::InvalidHeaderValue {
+                        header: header_trait.to_owned(),
+                        value: value.to_owned(),
+                        reason: e.to_string(),
+                    })
+            }
+            None => Err(CefConversionError::Unexpected(format!(
+                "{} is not a CEF header",
+                header_trait
+            ))),
+        }
+    }
+
+    /// Parses a header (named by the trait that produces it)
+    /// using the `FromStr` trait, returning `None` when it's empty
+    pub fn parse_optional_header<T>(
+        &self,
+        header_trait: &str,
+    ) -> Result<Option<T>, CefConversionError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.header(header_trait) {
+            Some("") => Ok(None),
+            _ => self.parse_header(header_trait).map(Some),
+        }
+    }
+
+    /// Parses a mandatory extension using the `FromStr` trait
+    pub fn parse_extension<T>(&self, key: &str) -> Result<T, CefConversionError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.parse_optional_extension(key)? {
+            Some(value) => Ok(value),
+            None => Err(CefConversionError::MissingExtension {
+                key: key.to_owned(),
+            }),
+        }
+    }
+
+    /// Parses an optional extension using the `FromStr` trait,
+    /// returning `None` when the key is absent
+    pub fn parse_optional_extension<T>(&self, key: &str) -> Result<Option<T>, CefConversionError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.extension(key) {
+            Some(value) => value.parse().map(Some).map_err(|e: T::Err| {
+                CefConversionError::InvalidExtensionValue {
+                    key: key.to_owned(),
+                    value: value.to_owned(),
+                    reason: e.to_string(),
+                }
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+impl FromStr for CefRecord {
+    type Err = CefConversionError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        parse_cef(line)
+    }
+}
+
+/// Parses a `CEF:Version|Vendor|Product|DeviceVersion|EventClassID|Name|Severity|extensions`
+/// line into a `CefRecord`.
+///
+/// Anything preceding the `CEF:` marker (such as a syslog prefix) is skipped,
+/// and trailing line terminators are ignored.
+///
+/// Headers honor escaped pipes and backslashes. Extension values run until the
+/// next unescaped `key=` token, so they may contain spaces, and escaped equal signs,
+/// backslashes and newlines are unescaped. A backslash that doesn't start one of
+/// these escapes is kept as it is.
+pub fn parse_cef(line: &str) -> Result<CefRecord, CefConversionError> {
+    let (mut headers, extensions) = split_line(line)?;
+
+    let mut record = CefRecord {
+        severity: headers.pop().unwrap_or_default(),
+        name: headers.pop().unwrap_or_default(),
+        device_event_class_id: headers.pop().unwrap_or_default(),
+        device_version: headers.pop().unwrap_or_default(),
+        device_product: headers.pop().unwrap_or_default(),
+        device_vendor: headers.pop().unwrap_or_default(),
+        version: headers.pop().unwrap_or_default(),
+        extensions: vec![],
+    };
+
+    record.extensions = parse_extensions(extensions)?;
+
+    Ok(record)
+}
+
+/// Splits a CEF line (skipping any prefix and trailing line terminators)
+/// into the seven unescaped headers and the raw (still escaped) extensions string
+pub(crate) fn split_line(line: &str) -> Result<(Vec<String>, &str), CefConversionError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    let start = match line.find(CEF_PREFIX) {
+        Some(start) => start,
+        None => {
+            return Err(CefConversionError::Unexpected(format!(
+                "CEF line must begin with '{}'",
+                CEF_PREFIX
+            )))
+        }
+    };
+
+    split_headers(&line[start + CEF_PREFIX.len()..])
+}
+
+/// Splits the seven unescaped headers from the raw (still escaped) extensions string
+fn split_headers(line: &str) -> Result<(Vec<String>, &str), CefConversionError> {
+    let mut headers: Vec<String> = Vec::with_capacity(CEF_HEADER_COUNT);
+    let mut current = String::new();
+    let mut chars = line.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => push_unescaped(&mut current, escaped),
+                None => current.push('\\'),
+            },
+            '|' => {
+                headers.push(std::mem::take(&mut current));
+                if headers.len() == CEF_HEADER_COUNT {
+                    return Ok((headers, &line[index + 1..]));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+
+    // every header must be terminated by a pipe, so the first
+    // unterminated one is missing
+    Err(CefConversionError::MissingHeader {
+        header: CEF_HEADER_TRAITS[headers.len()].to_owned(),
+    })
+}
+
+/// Parses the extension part of a CEF line into ordered key/value pairs.
+///
+/// Every unescaped `=` ends a key which is the run of non-whitespace characters
+/// preceding it, unless that run started before the previous key's `=` (i.e. the
+/// `=` in `request=http://x/?a=b`), in which case the `=` is part of the value.
+/// A value then runs until the single space separating it from the next key.
+fn parse_extensions(extensions: &str) -> Result<Vec<(String, String)>, CefConversionError> {
+    // (key_start, equals_index) for every key found
+    let mut key_positions: Vec<(usize, usize)> = vec![];
+
+    let bytes = extensions.as_bytes();
+    let mut escaped = false;
+    for (index, b) in bytes.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match b {
+            b'\\' => escaped = true,
+            b'=' => {
+                let key_start = bytes[..index]
+                    .iter()
+                    .rposition(|c| c.is_ascii_whitespace())
+                    .map(|p| p + 1)
+                    .unwrap_or(0);
+
+                // an '=' that isn't preceded by a key of its own stays part of the value
+                let value_start = key_positions
+                    .last()
+                    .map(|(_, equals)| equals + 1)
+                    .unwrap_or(0);
+                if key_start < index && key_start >= value_start {
+                    key_positions.push((key_start, index));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match key_positions.first() {
+        None if extensions.trim().is_empty() => return Ok(vec![]),
+        Some((key_start, _)) if extensions[..*key_start].trim().is_empty() => {}
+        _ => {
+            return Err(CefConversionError::Unexpected(format!(
+                "CEF extensions must be a list of key=value pairs, but found: {}",
+                extensions
+            )))
+        }
+    }
+
+    let pairs = key_positions
+        .iter()
+        .enumerate()
+        .map(|(i, (key_start, equals))| {
+            // only the whitespace separating a value from the next key is dropped,
+            // so the last value (and the others) keep their own trailing spaces
+            let value_end = match key_positions.get(i + 1) {
+                Some((next_key_start, _)) => *next_key_start - 1,
+                None => extensions.len(),
+            };
+            let key = extensions[*key_start..*equals].to_owned();
+            let value = unescape_extension_value(&extensions[equals + 1..value_end]);
+            (key, value)
+        })
+        .collect();
+
+    Ok(pairs)
+}
+
+fn unescape_extension_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => push_unescaped(&mut unescaped, escaped),
+                None => unescaped.push('\\'),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Pushes what the character following a backslash stands for. A backslash
+/// that doesn't start a CEF escape sequence is kept, i.e. `a\xb` stays as it is.
+fn push_unescaped(unescaped: &mut String, escaped: char) {
+    match escaped {
+        'n' => unescaped.push('\n'),
+        'r' => unescaped.push('\r'),
+        '\\' | '|' | '=' => unescaped.push(escaped),
+        other => {
+            unescaped.push('\\');
+            unescaped.push(other);
+        }
+    }
+}
+
+/// A CefRecord is trivially constructed from itself, which
+/// lets a raw record be gobbled by derived types.
+impl FromCef for CefRecord {
+    fn from_cef_record(record: &CefRecord) -> Result<Self, CefConversionError> {
+        Ok(record.clone())
+    }
+}
+
+/// A CefRecord can be emitted again, which makes
+/// parsing and encoding round-trip.
+impl ToCef for CefRecord {}
+
+impl CefHeaderVersion for CefRecord {
+    fn cef_header_version(&self) -> CefResult {
+        Ok(self.version.clone())
+    }
+}
+
+impl CefHeaderDeviceVendor for CefRecord {
+    fn cef_header_device_vendor(&self) -> CefResult {
+        Ok(self.device_vendor.clone())
+    }
+}
+
+impl CefHeaderDeviceProduct for CefRecord {
+    fn cef_header_device_product(&self) -> CefResult {
+        Ok(self.device_product.clone())
+    }
+}
+
+impl CefHeaderDeviceVersion for CefRecord {
+    fn cef_header_device_version(&self) -> CefResult {
+        Ok(self.device_version.clone())
+    }
+}
+
+impl CefHeaderDeviceEventClassID for CefRecord {
+    fn cef_header_device_event_class_id(&self) -> CefResult {
+        Ok(self.device_event_class_id.clone())
+    }
+}
+
+impl CefHeaderName for CefRecord {
+    fn cef_header_name(&self) -> CefResult {
+        Ok(self.name.clone())
+    }
+}
+
+impl CefHeaderSeverity for CefRecord {
+    fn cef_header_severity(&self) -> CefResult {
+        Ok(self.severity.clone())
+    }
+}
+
+impl CefExtensions for CefRecord {
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        for (key, value) in &self.extensions {
+            collector.insert(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(kvs: &[(&str, &str)]) -> Vec<(String, String)> {
+        kvs.iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_simple() {
+        let record = parse_cef("CEF:0|polyverse|zerotect|V1|LinuxKernelTrap|Linux Kernel Trap|10|customField1=customValue1 customField2=customValue2").unwrap();
+        assert_eq!(
+            record,
+            CefRecord {
+                version: "0".to_owned(),
+                device_vendor: "polyverse".to_owned(),
+                device_product: "zerotect".to_owned(),
+                device_version: "V1".to_owned(),
+                device_event_class_id: "LinuxKernelTrap".to_owned(),
+                name: "Linux Kernel Trap".to_owned(),
+                severity: "10".to_owned(),
+                extensions: pairs(&[
+                    ("customField1", "customValue1"),
+                    ("customField2", "customValue2")
+                ]),
+            }
+        );
+        assert_eq!(record.extension("customField2"), Some("customValue2"));
+        assert_eq!(record.extension("customField3"), None);
+    }
+
+    #[test]
+    fn test_parse_no_extensions() {
+        let record: CefRecord =
+            "CEF:0|polyverse|zerotect|V1|LinuxKernelTrap|Linux Kernel Trap|10|\n"
+                .parse()
+                .unwrap();
+        assert_eq!(record.severity, "10");
+        assert!(record.extensions.is_empty());
+    }
+
+    #[test]
+    fn test_parse_escaped_headers() {
+        let record = parse_cef(
+            "CEF:0|poly\\|verse|zero\\\\tect|V1=V2|Linux\\|Kernel\\\\\\|Trap|Name\\nLine|10|",
+        )
+        .unwrap();
+        assert_eq!(record.device_vendor, "poly|verse");
+        assert_eq!(record.device_product, "zero\\tect");
+        assert_eq!(record.device_version, "V1=V2");
+        assert_eq!(record.device_event_class_id, "Linux|Kernel\\|Trap");
+        assert_eq!(record.name, "Name\nLine");
+    }
+
+    #[test]
+    fn test_parse_extension_values() {
+        let record = parse_cef("CEF:0|v|p|1|id|name|5|msg=a message with spaces  equals=a\\=b c\\=d path=C:\\\\Windows\\\\ lines=one\\r\\ntwo pipes=a|b empty= last=value  ").unwrap();
+        assert_eq!(
+            record.extensions,
+            pairs(&[
+                ("msg", "a message with spaces "),
+                ("equals", "a=b c=d"),
+                ("path", "C:\\Windows\\"),
+                ("lines", "one\r\ntwo"),
+                ("pipes", "a|b"),
+                ("empty", ""),
+                ("last", "value  "),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_escapes() {
+        let record = parse_cef("CEF:0|v|p|1|c|Tab\\there|3|k=a\\xb dir=C:\\Users").unwrap();
+        assert_eq!(record.name, "Tab\\there");
+        assert_eq!(
+            record.extensions,
+            pairs(&[("k", "a\\xb"), ("dir", "C:\\Users")])
+        );
+    }
+
+    #[test]
+    fn test_parse_unescaped_equals() {
+        let record = parse_cef("CEF:0|v|p|1|c|n|3|request=http://x/?a=b").unwrap();
+        assert_eq!(record.extensions, pairs(&[("request", "http://x/?a=b")]));
+
+        let record = parse_cef("CEF:0|v|p|1|c|n|3|k=v==").unwrap();
+        assert_eq!(record.extensions, pairs(&[("k", "v==")]));
+
+        let record = parse_cef("CEF:0|v|p|1|c|n|3|request=http://x/?a=b&c=d src=10.0.0.1").unwrap();
+        assert_eq!(
+            record.extensions,
+            pairs(&[("request", "http://x/?a=b&c=d"), ("src", "10.0.0.1")])
+        );
+    }
+
+    #[test]
+    fn test_parse_skips_prefix() {
+        let record =
+            parse_cef("<134>Oct 16 10:00:00 host CEF:0|v|p|1|id|name|5|src=10.0.0.1").unwrap();
+        assert_eq!(record.version, "0");
+        assert_eq!(record.extension("src"), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_cef("0|v|p|1|id|name|5|src=10.0.0.1").is_err());
+        assert_eq!(
+            parse_cef("CEF:0|v|p|1|id|name|5"),
+            Err(CefConversionError::MissingHeader {
+                header: "CefHeaderSeverity".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_cef("CEF:0|v|p|1|id|name\\|5|"),
+            Err(CefConversionError::MissingHeader {
+                header: "CefHeaderSeverity".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_cef("CEF:"),
+            Err(CefConversionError::MissingHeader {
+                header: "CefHeaderVersion".to_owned()
+            })
+        );
+        assert!(parse_cef("CEF:0|v|p|1|id|name|5|no pairs here").is_err());
+        assert!(parse_cef("CEF:0|v|p|1|id|name|5|garbage src=10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_parse_typed_values() {
+        let record = parse_cef("CEF:0|v|p|1|id|name|5|spt=8080 src=10.0.0.1").unwrap();
+        assert_eq!(record.header("CefHeaderSeverity"), Some("5"));
+        assert_eq!(record.header("Severity"), None);
+        assert_eq!(record.parse_header::<u8>("CefHeaderSeverity"), Ok(5));
+        assert!(matches!(
+            record.parse_header::<u8>("CefHeaderName"),
+            Err(CefConversionError::InvalidHeaderValue { header, value, .. }) if header == "CefHeaderName" && value == "name"
+        ));
+        assert_eq!(
+            record.parse_optional_header::<u8>("CefHeaderSeverity"),
+            Ok(Some(5))
+        );
+        assert!(record.parse_optional_header::<u8>("CefHeaderName").is_err());
+        assert_eq!(
+            parse_cef("CEF:0|v|p|1|id|name||")
+                .unwrap()
+                .parse_optional_header::<u8>("CefHeaderSeverity"),
+            Ok(None)
+        );
+        assert_eq!(record.parse_extension::<u16>("spt"), Ok(8080));
+        assert_eq!(
+            record.parse_extension::<std::net::IpAddr>("src"),
+            Ok("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            record.parse_extension::<u16>("dpt"),
+            Err(CefConversionError::MissingExtension {
+                key: "dpt".to_owned()
+            })
+        );
+        assert!(matches!(
+            record.parse_extension::<u16>("src"),
+            Err(CefConversionError::InvalidExtensionValue { key, value, .. }) if key == "src" && value == "10.0.0.1"
+        ));
+        assert_eq!(record.parse_optional_extension::<u16>("dpt"), Ok(None));
+        assert_eq!(
+            record.parse_optional_extension::<u16>("spt"),
+            Ok(Some(8080))
+        );
+    }
+
+    #[test]
+    fn test_custom_extension_key() {
+        let record =
+            parse_cef("CEF:0|v|p|1|id|name|5|cs1Label=Pod cs1=web-1 cs2Label=Container ID cs2=abc")
+                .unwrap();
+        assert_eq!(
+            record.custom_extension_key(CefCustomKind::String, "Container ID"),
+            Some("cs2")
+        );
+        assert_eq!(
+            record.custom_extension_key(CefCustomKind::String, "Pod"),
+            Some("cs1")
+        );
+        assert_eq!(
+            record.custom_extension_key(CefCustomKind::Number, "Pod"),
+            None
+        );
+        assert_eq!(
+            record.custom_extension_key(CefCustomKind::String, "Node"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let line = "CEF:0|poly\\|verse|zero\\\\tect|V1=V2|Linux\\|Kernel|Linux Kernel Trap\\nCEF:0\\|injected|10|backslash=C:\\\\Windows\\\\ equals=a\\=b c\\=d newlines=line1\\r\\nline2 pipes=a|b";
+        let record = parse_cef(line).unwrap();
+        assert_eq!(record.extension("equals"), Some("a=b c=d"));
+        assert_eq!(record.to_cef().unwrap(), line);
+        assert_eq!(parse_cef(&record.to_cef().unwrap()).unwrap(), record);
+
+        let line = "CEF:0|v|p|1|id|name|5|msg=trailing spaces  src=10.0.0.1 suser=alice ";
+        let record = parse_cef(line).unwrap();
+        assert_eq!(record.extension("msg"), Some("trailing spaces "));
+        assert_eq!(record.extension("suser"), Some("alice "));
+        assert_eq!(record.to_cef().unwrap(), line);
+    }
+}
@@ -0,0 +1,391 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides redaction, masking and pseudonymization of sensitive
+/// extension values, either per field (`#[cef_ext_field(suser, redact)]`) or
+/// per key at runtime through a `RedactionPolicy`.
+use crate::{CefCollector, CefConversionError, CefExtensionsResult};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt::{Arguments, Debug, Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// What redacted values (and the masked part of masked values) are replaced with
+pub const REDACTED: &str = "***";
+
+/// The number of hex characters pseudonyms are truncated to by default (64 bits)
+pub const DEFAULT_PSEUDONYM_LENGTH: usize = 16;
+
+/// How a sensitive value is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    /// Replaces the whole value with `***`
+    Redact,
+    /// Replaces all but the last given number of characters with `***`
+    /// (i.e. `***1234`). Values no longer than that are redacted entirely.
+    MaskLast(usize),
+    /// Replaces the value with its pseudonym (see `Pseudonymizer`), so the
+    /// same value can be correlated across events without being disclosed.
+    /// Without a `Pseudonymizer` to compute it, the value is redacted instead.
+    Pseudonymize,
+}
+
+impl Redaction {
+    /// Hides the value. Pseudonyms need a secret, so `Pseudonymize`
+    /// redacts here; see `RedactionPolicy::hide`.
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Redaction::Redact | Redaction::Pseudonymize => REDACTED.to_owned(),
+            Redaction::MaskLast(keep) => {
+                let length = value.chars().count();
+                match length > *keep {
+                    true => {
+                        let kept: String = value.chars().skip(length - keep).collect();
+                        [REDACTED, &kept].concat()
+                    }
+                    false => REDACTED.to_owned(),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Redaction {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Redaction::Redact => write!(f, "redact"),
+            Redaction::MaskLast(keep) => write!(f, "last{}", keep),
+            Redaction::Pseudonymize => write!(f, "pseudonymize"),
+        }
+    }
+}
+
+impl FromStr for Redaction {
+    type Err = CefConversionError;
+
+    /// Parses the names used by `Display`: redact, pseudonymize, or last
+    /// followed by the number of characters to keep (i.e. last4)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keep = s.strip_prefix("last").and_then(|n| n.parse().ok());
+        match (s, keep) {
+            ("redact", _) => Ok(Redaction::Redact),
+            ("pseudonymize", _) => Ok(Redaction::Pseudonymize),
+            (_, Some(keep)) => Ok(Redaction::MaskLast(keep)),
+            _ => Err(CefConversionError::Unexpected(format!(
+                "'{}' is not a redaction. Expected redact, pseudonymize or lastN (i.e. last4)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Computes pseudonyms: the HMAC-SHA256 of a value keyed with a caller-provided
+/// secret, in lowercase hex truncated to a configurable length.
+///
+/// The same value always gets the same pseudonym under the same secret, and
+/// pseudonyms can't be reversed (or recomputed) without the secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pseudonymizer {
+    secret: Vec<u8>,
+    length: usize,
+}
+
+impl Pseudonymizer {
+    /// A pseudonymizer keeping `DEFAULT_PSEUDONYM_LENGTH` hex characters
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+            length: DEFAULT_PSEUDONYM_LENGTH,
+        }
+    }
+
+    /// Returns this pseudonymizer keeping the given number of hex characters
+    /// of the digest (at most 64, the whole digest)
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length.min(64);
+        self
+    }
+
+    pub fn pseudonym(&self, value: &str) -> String {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(value.as_bytes());
+
+        let mut pseudonym: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        pseudonym.truncate(self.length);
+        pseudonym
+    }
+}
+
+/// Keeps the secret out of logs
+impl Debug for Pseudonymizer {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("Pseudonymizer")
+            .field("secret", &REDACTED)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+/// The extension keys to redact, mask or pseudonymize at runtime, whichever
+/// type produces them, and the `Pseudonymizer` that computes pseudonyms
+/// (for these keys as well as `#[cef_ext_field(suser, pseudonymize)]` fields).
+///
+/// It applies to `to_cef` (and every other output) through
+/// `CefEncodeOptions::with_redaction`, and to any collector through
+/// `RedactingCollector`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RedactionPolicy {
+    keys: BTreeMap<String, Redaction>,
+    pseudonymizer: Option<Pseudonymizer>,
+}
+
+impl RedactionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns this policy with values of the given key hidden
+    pub fn with_key(mut self, key: &str, redaction: Redaction) -> Self {
+        self.keys.insert(key.to_owned(), redaction);
+        self
+    }
+
+    /// How values of the given key are hidden, if they are
+    pub fn redaction(&self, key: &str) -> Option<Redaction> {
+        self.keys.get(key).copied()
+    }
+
+    /// Returns this policy computing pseudonyms with the given pseudonymizer
+    pub fn with_pseudonymizer(mut self, pseudonymizer: Pseudonymizer) -> Self {
+        self.pseudonymizer = Some(pseudonymizer);
+        self
+    }
+
+    pub fn pseudonymizer(&self) -> Option<&Pseudonymizer> {
+        self.pseudonymizer.as_ref()
+    }
+
+    /// Hides the value, computing pseudonyms with this policy's pseudonymizer
+    pub fn hide(&self, redaction: Redaction, value: &str) -> String {
+        match (redaction, &self.pseudonymizer) {
+            (Redaction::Pseudonymize, Some(pseudonymizer)) => pseudonymizer.pseudonym(value),
+            (redaction, _) => redaction.apply(value),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.pseudonymizer.is_none()
+    }
+}
+
+/// Hides the values of the keys named by a `RedactionPolicy`
+/// before handing them to another collector. Custom slots are
+/// picked here too, so they're hidden by their slot key (i.e. `cs2`).
+pub struct RedactingCollector<'a> {
+    inner: &'a mut dyn CefCollector,
+    policy: &'a RedactionPolicy,
+}
+
+impl<'a> RedactingCollector<'a> {
+    pub fn new(inner: &'a mut dyn CefCollector, policy: &'a RedactionPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl CefCollector for RedactingCollector<'_> {
+    fn insert(&mut self, key: &str, value: &str) -> CefExtensionsResult {
+        match self.policy.redaction(key) {
+            Some(redaction) => self.inner.insert(key, &self.policy.hide(redaction, value)),
+            None => self.inner.insert(key, value),
+        }
+    }
+
+    fn insert_fmt(&mut self, key: &str, value: Arguments) -> CefExtensionsResult {
+        match self.policy.redaction(key) {
+            Some(redaction) => {
+                let hidden = self.policy.hide(redaction, &value.to_string());
+                self.inner.insert(key, &hidden)
+            }
+            None => self.inner.insert_fmt(key, value),
+        }
+    }
+
+    fn insert_pseudonymized(&mut self, key: &str, value: Arguments) -> CefExtensionsResult {
+        // the policy's entry for the key wins over the field's pseudonymization
+        match (self.policy.redaction(key), self.policy.pseudonymizer()) {
+            (Some(redaction), _) => {
+                let hidden = self.policy.hide(redaction, &value.to_string());
+                self.inner.insert(key, &hidden)
+            }
+            (None, Some(pseudonymizer)) => self
+                .inner
+                .insert(key, &pseudonymizer.pseudonym(&value.to_string())),
+            (None, None) => self.inner.insert_pseudonymized(key, value),
+        }
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    fn enter_field(&mut self, field: &str) {
+        self.inner.enter_field(field);
+    }
+
+    fn exit_field(&mut self) {
+        self.inner.exit_field();
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CefCustomKind;
+
+    #[test]
+    fn test_redaction() {
+        assert_eq!(Redaction::Redact.apply("alice"), "***");
+        assert_eq!(Redaction::MaskLast(4).apply("4111111111111111"), "***1111");
+        assert_eq!(Redaction::MaskLast(4).apply("1234"), "***");
+        assert_eq!(Redaction::MaskLast(2).apply("héllo"), "***lo");
+
+        assert_eq!("redact".parse::<Redaction>().unwrap(), Redaction::Redact);
+        assert_eq!(
+            "last4".parse::<Redaction>().unwrap(),
+            Redaction::MaskLast(4)
+        );
+        assert_eq!(Redaction::MaskLast(4).to_string(), "last4");
+        assert!("first4".parse::<Redaction>().is_err());
+        assert!("last".parse::<Redaction>().is_err());
+        assert_eq!(
+            "pseudonymize".parse::<Redaction>().unwrap(),
+            Redaction::Pseudonymize
+        );
+        assert_eq!(Redaction::Pseudonymize.apply("alice"), "***");
+    }
+
+    #[test]
+    fn test_pseudonymizer() {
+        // RFC 4231, test case 2
+        let pseudonymizer = Pseudonymizer::new(b"Jefe").with_length(100);
+        assert_eq!(
+            pseudonymizer.pseudonym("what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let pseudonymizer = Pseudonymizer::new(b"secret");
+        assert_eq!(pseudonymizer.pseudonym("alice").len(), 16);
+        assert_eq!(
+            pseudonymizer.pseudonym("alice"),
+            pseudonymizer.pseudonym("alice")
+        );
+        assert_ne!(
+            pseudonymizer.pseudonym("alice"),
+            pseudonymizer.pseudonym("bob")
+        );
+        assert_ne!(
+            pseudonymizer.pseudonym("alice"),
+            Pseudonymizer::new(b"other").pseudonym("alice")
+        );
+        assert_eq!(
+            format!("{:?}", pseudonymizer),
+            "Pseudonymizer { secret: \"***\", length: 16 }"
+        );
+
+        let pseudonym = pseudonymizer.pseudonym("alice");
+        let policy = RedactionPolicy::new()
+            .with_key("duser", Redaction::Pseudonymize)
+            .with_pseudonymizer(pseudonymizer);
+        let mut extensions = Vec::<(String, String)>::new();
+        let mut collector = RedactingCollector::new(&mut extensions, &policy);
+        assert!(collector.insert("duser", "alice").is_ok());
+        assert!(collector
+            .insert_pseudonymized("suser", format_args!("{}", "alice"))
+            .is_ok());
+        assert_eq!(
+            extensions,
+            vec![
+                ("duser".to_owned(), pseudonym.clone()),
+                ("suser".to_owned(), pseudonym),
+            ]
+        );
+
+        // without a secret, pseudonymized values are redacted
+        let mut extensions = Vec::<(String, String)>::new();
+        let policy = RedactionPolicy::new().with_key("duser", Redaction::Pseudonymize);
+        let mut collector = RedactingCollector::new(&mut extensions, &policy);
+        assert!(collector.insert("duser", "alice").is_ok());
+        assert!(collector
+            .insert_pseudonymized("suser", format_args!("{}", "alice"))
+            .is_ok());
+        assert_eq!(
+            extensions,
+            vec![
+                ("duser".to_owned(), "***".to_owned()),
+                ("suser".to_owned(), "***".to_owned()),
+            ]
+        );
+
+        // a key the policy masks stays masked when its field is pseudonymized
+        let policy = RedactionPolicy::new()
+            .with_key("suser", Redaction::MaskLast(2))
+            .with_key("duser", Redaction::Redact)
+            .with_pseudonymizer(Pseudonymizer::new(b"secret"));
+        let mut extensions = Vec::<(String, String)>::new();
+        let mut collector = RedactingCollector::new(&mut extensions, &policy);
+        assert!(collector
+            .insert_pseudonymized("suser", format_args!("{}", "alice"))
+            .is_ok());
+        assert!(collector
+            .insert_pseudonymized("duser", format_args!("{}", "bob"))
+            .is_ok());
+        assert_eq!(
+            extensions,
+            vec![
+                ("suser".to_owned(), "***ce".to_owned()),
+                ("duser".to_owned(), "***".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redacting_collector() {
+        let policy = RedactionPolicy::new()
+            .with_key("suser", Redaction::Redact)
+            .with_key("cs2", Redaction::MaskLast(3));
+
+        let mut extensions = Vec::<(String, String)>::new();
+        let mut collector = RedactingCollector::new(&mut extensions, &policy);
+        assert!(collector.insert("suser", "alice").is_ok());
+        assert!(collector
+            .insert_fmt("src", format_args!("{}", "10.0.0.1"))
+            .is_ok());
+        assert!(collector
+            .insert_custom(CefCustomKind::String, "Session", format_args!("abc"))
+            .is_ok());
+        assert!(collector
+            .insert_custom(CefCustomKind::String, "Token", format_args!("secret-xyz"))
+            .is_ok());
+
+        assert_eq!(
+            extensions,
+            vec![
+                ("suser".to_owned(), "***".to_owned()),
+                ("src".to_owned(), "10.0.0.1".to_owned()),
+                ("cs1Label".to_owned(), "Session".to_owned()),
+                ("cs1".to_owned(), "abc".to_owned()),
+                ("cs2Label".to_owned(), "Token".to_owned()),
+                ("cs2".to_owned(), "***xyz".to_owned()),
+            ]
+        );
+    }
+}
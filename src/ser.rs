@@ -0,0 +1,1000 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a serde Serializer that flattens items into CEF extensions,
+/// for types that implement `serde::Serialize` but can't use the derive macros.
+/// It's only available with the `serde` feature.
+use crate::{
+    CefCollector, CefConversionError, CefEncodeOptions, CefExtensions, CefExtensionsResult,
+    CefHeaderDeviceEventClassID, CefHeaderDeviceProduct, CefHeaderDeviceVendor,
+    CefHeaderDeviceVersion, CefHeaderName, CefHeaderSeverity, CefHeaderVersion, CefResult, ToCef,
+};
+use serde::ser::{
+    self, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+    SerializeStructVariant, SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
+    Serializer,
+};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+impl ser::Error for CefConversionError {
+    fn custom<T: Display>(msg: T) -> Self {
+        CefConversionError::Unexpected(msg.to_string())
+    }
+}
+
+/// How the keys of nested structs and maps are joined with their parent's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NestedKeys {
+    /// `parent.child`
+    #[default]
+    Dotted,
+    /// `parent_child`
+    Underscored,
+}
+
+impl NestedKeys {
+    fn join(&self, parent: &Option<String>, child: &str) -> String {
+        match (parent, self) {
+            (None, _) => child.to_owned(),
+            (Some(parent), NestedKeys::Dotted) => format!("{}.{}", parent, child),
+            (Some(parent), NestedKeys::Underscored) => format!("{}_{}", parent, child),
+        }
+    }
+}
+
+/// The CEF headers, named after the traits that provide them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CefHeader {
+    Version,
+    DeviceVendor,
+    DeviceProduct,
+    DeviceVersion,
+    DeviceEventClassID,
+    Name,
+    Severity,
+}
+
+impl CefHeader {
+    /// The name of the trait providing this header (i.e. `CefHeaderName`), as used in errors
+    pub fn trait_name(&self) -> &'static str {
+        match self {
+            CefHeader::Version => "CefHeaderVersion",
+            CefHeader::DeviceVendor => "CefHeaderDeviceVendor",
+            CefHeader::DeviceProduct => "CefHeaderDeviceProduct",
+            CefHeader::DeviceVersion => "CefHeaderDeviceVersion",
+            CefHeader::DeviceEventClassID => "CefHeaderDeviceEventClassID",
+            CefHeader::Name => "CefHeaderName",
+            CefHeader::Severity => "CefHeaderSeverity",
+        }
+    }
+}
+
+/// Where a header's value comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderSource {
+    /// A fixed value
+    Value(String),
+    /// A serialized field, named by its (joined) extension key. The field
+    /// provides the header and is not written as an extension.
+    Field(String),
+}
+
+/// Flattens items into CEF extensions with `CefExtensionSerializer`,
+/// and reads headers from fixed values or designated fields.
+///
+/// The resulting `CefSerdeEvent` implements `ToCef`, so output (escaping, key
+/// validation, duplicate keys and severity policies) is the same `to_cef` produces.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CefSerdeEncoder {
+    pub headers: BTreeMap<CefHeader, HeaderSource>,
+    pub nested_keys: NestedKeys,
+    pub options: CefEncodeOptions,
+}
+
+impl CefSerdeEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses a fixed value for the header
+    pub fn with_header(mut self, header: CefHeader, value: &str) -> Self {
+        self.headers
+            .insert(header, HeaderSource::Value(value.to_owned()));
+        self
+    }
+
+    /// Reads the header from the field with this (joined) key
+    pub fn with_header_field(mut self, header: CefHeader, key: &str) -> Self {
+        self.headers
+            .insert(header, HeaderSource::Field(key.to_owned()));
+        self
+    }
+
+    pub fn with_nested_keys(mut self, nested_keys: NestedKeys) -> Self {
+        self.nested_keys = nested_keys;
+        self
+    }
+
+    pub fn with_options(mut self, options: CefEncodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Flattens the value into an item that can be converted to CEF or sent to a sink
+    pub fn to_event<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+    ) -> Result<CefSerdeEvent, CefConversionError> {
+        let mut extensions: Vec<(String, String)> = vec![];
+        value.serialize(CefExtensionSerializer::new(
+            &mut extensions,
+            self.nested_keys,
+        ))?;
+
+        let mut headers = BTreeMap::new();
+        for (header, source) in self.headers.iter() {
+            let value = match source {
+                HeaderSource::Value(value) => Some(value.clone()),
+                HeaderSource::Field(key) => {
+                    let position = extensions.iter().position(|(k, _)| k == key);
+                    position.map(|position| extensions.remove(position).1)
+                }
+            };
+
+            if let Some(value) = value {
+                headers.insert(*header, value);
+            }
+        }
+
+        Ok(CefSerdeEvent {
+            headers,
+            extensions,
+            options: self.options.clone(),
+        })
+    }
+
+    /// Flattens the value and converts it to a CEF string
+    pub fn to_cef<T: Serialize + ?Sized>(&self, value: &T) -> CefResult {
+        self.to_event(value)?.to_cef()
+    }
+}
+
+/// A flattened item produced by `CefSerdeEncoder`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CefSerdeEvent {
+    headers: BTreeMap<CefHeader, String>,
+    extensions: Vec<(String, String)>,
+    options: CefEncodeOptions,
+}
+
+impl CefSerdeEvent {
+    fn header(&self, header: CefHeader) -> CefResult {
+        self.headers
+            .get(&header)
+            .cloned()
+            .ok_or_else(|| CefConversionError::MissingHeader {
+                header: header.trait_name().to_owned(),
+            })
+    }
+}
+
+impl CefHeaderVersion for CefSerdeEvent {
+    fn cef_header_version(&self) -> CefResult {
+        self.header(CefHeader::Version)
+    }
+}
+
+impl CefHeaderDeviceVendor for CefSerdeEvent {
+    fn cef_header_device_vendor(&self) -> CefResult {
+        self.header(CefHeader::DeviceVendor)
+    }
+}
+
+impl CefHeaderDeviceProduct for CefSerdeEvent {
+    fn cef_header_device_product(&self) -> CefResult {
+        self.header(CefHeader::DeviceProduct)
+    }
+}
+
+impl CefHeaderDeviceVersion for CefSerdeEvent {
+    fn cef_header_device_version(&self) -> CefResult {
+        self.header(CefHeader::DeviceVersion)
+    }
+}
+
+impl CefHeaderDeviceEventClassID for CefSerdeEvent {
+    fn cef_header_device_event_class_id(&self) -> CefResult {
+        self.header(CefHeader::DeviceEventClassID)
+    }
+}
+
+impl CefHeaderName for CefSerdeEvent {
+    fn cef_header_name(&self) -> CefResult {
+        self.header(CefHeader::Name)
+    }
+}
+
+impl CefHeaderSeverity for CefSerdeEvent {
+    fn cef_header_severity(&self) -> CefResult {
+        self.header(CefHeader::Severity)
+    }
+}
+
+impl CefExtensions for CefSerdeEvent {
+    fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+        for (key, value) in self.extensions.iter() {
+            collector.insert(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToCef for CefSerdeEvent {
+    fn cef_encode_options(&self) -> CefEncodeOptions {
+        self.options.clone()
+    }
+}
+
+/// A serde Serializer that flattens structs and maps into a `CefCollector`.
+///
+/// Fields are written under their own names, and fields of nested structs and maps
+/// under their names joined to their parent's (see `NestedKeys`). Scalars are written
+/// the way `Display` writes them, sequences as comma-separated values, `None` and
+/// unit values are skipped, and enum variants are written by name (or nest their
+/// contents under their name). Only structs and maps can be serialized at the top.
+///
+/// Types that implement Serialize can implement CefExtensions with it:
+///
+/// ```ignore
+/// fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+///     self.serialize(CefExtensionSerializer::new(collector, NestedKeys::Dotted))
+/// }
+/// ```
+pub struct CefExtensionSerializer<'c> {
+    collector: &'c mut dyn CefCollector,
+    nested_keys: NestedKeys,
+    key: Option<String>,
+}
+
+impl<'c> CefExtensionSerializer<'c> {
+    pub fn new(collector: &'c mut dyn CefCollector, nested_keys: NestedKeys) -> Self {
+        Self {
+            collector,
+            nested_keys,
+            key: None,
+        }
+    }
+
+    fn child(&mut self, name: &str) -> CefExtensionSerializer<'_> {
+        CefExtensionSerializer {
+            key: Some(self.nested_keys.join(&self.key, name)),
+            collector: &mut *self.collector,
+            nested_keys: self.nested_keys,
+        }
+    }
+
+    fn write(self, value: &str) -> CefExtensionsResult {
+        match &self.key {
+            Some(key) => self.collector.insert(key, value),
+            None => Err(top_level_error()),
+        }
+    }
+}
+
+fn top_level_error() -> CefConversionError {
+    CefConversionError::Unexpected(
+        "Only structs and maps can be flattened into CEF extensions".to_owned(),
+    )
+}
+
+impl<'c> Serializer for CefExtensionSerializer<'c> {
+    type Ok = ();
+    type Error = CefConversionError;
+    type SerializeSeq = ValueJoiner<'c>;
+    type SerializeTuple = ValueJoiner<'c>;
+    type SerializeTupleStruct = ValueJoiner<'c>;
+    type SerializeTupleVariant = ValueJoiner<'c>;
+    type SerializeMap = Flattener<'c>;
+    type SerializeStruct = Flattener<'c>;
+    type SerializeStructVariant = Flattener<'c>;
+
+    fn serialize_bool(self, v: bool) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> CefExtensionsResult {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> CefExtensionsResult {
+        self.write(v)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> CefExtensionsResult {
+        Err(CefConversionError::Unexpected(
+            "Bytes can't be written as CEF extensions".to_owned(),
+        ))
+    }
+
+    fn serialize_none(self) -> CefExtensionsResult {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> CefExtensionsResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> CefExtensionsResult {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> CefExtensionsResult {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> CefExtensionsResult {
+        self.write(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> CefExtensionsResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> CefExtensionsResult {
+        value.serialize(self.child(variant))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<ValueJoiner<'c>, CefConversionError> {
+        Ok(ValueJoiner {
+            serializer: self,
+            values: vec![],
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ValueJoiner<'c>, CefConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ValueJoiner<'c>, CefConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<ValueJoiner<'c>, CefConversionError> {
+        let key = Some(self.nested_keys.join(&self.key, variant));
+        CefExtensionSerializer { key, ..self }.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Flattener<'c>, CefConversionError> {
+        Ok(Flattener {
+            serializer: self,
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Flattener<'c>, CefConversionError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Flattener<'c>, CefConversionError> {
+        let key = Some(self.nested_keys.join(&self.key, variant));
+        CefExtensionSerializer { key, ..self }.serialize_map(Some(len))
+    }
+}
+
+/// Writes the fields of a struct or the entries of a map, each under its own key
+pub struct Flattener<'c> {
+    serializer: CefExtensionSerializer<'c>,
+    next_key: Option<String>,
+}
+
+impl SerializeMap for Flattener<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> CefExtensionsResult {
+        self.next_key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        let key = self.next_key.take().ok_or_else(|| {
+            CefConversionError::Unexpected("Map value serialized before its key".to_owned())
+        })?;
+        value.serialize(self.serializer.child(&key))
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        Ok(())
+    }
+}
+
+impl SerializeStruct for Flattener<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> CefExtensionsResult {
+        value.serialize(self.serializer.child(key))
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        Ok(())
+    }
+}
+
+impl SerializeStructVariant for Flattener<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> CefExtensionsResult {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        Ok(())
+    }
+}
+
+/// Writes the elements of a sequence or tuple as a single comma-separated value.
+/// Elements containing commas couldn't be told apart when read back, so they
+/// fail with `CefConversionError::InvalidExtensionValue`.
+pub struct ValueJoiner<'c> {
+    serializer: CefExtensionSerializer<'c>,
+    values: Vec<String>,
+}
+
+impl ValueJoiner<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        let value = value.serialize(ValueSerializer)?;
+        if value.contains(',') {
+            return Err(CefConversionError::InvalidExtensionValue {
+                key: self.serializer.key.clone().unwrap_or_default(),
+                value,
+                reason: "elements of a sequence can't contain commas".to_owned(),
+            });
+        }
+
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> CefExtensionsResult {
+        let value = self.values.join(",");
+        self.serializer.write(&value)
+    }
+}
+
+impl SerializeSeq for ValueJoiner<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        self.push(value)
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for ValueJoiner<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        self.push(value)
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for ValueJoiner<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        self.push(value)
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for ValueJoiner<'_> {
+    type Ok = ();
+    type Error = CefConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> CefExtensionsResult {
+        self.push(value)
+    }
+
+    fn end(self) -> CefExtensionsResult {
+        self.finish()
+    }
+}
+
+/// Serializes scalars (map keys and sequence elements) into strings
+struct ValueSerializer;
+
+type ValueResult = Result<String, CefConversionError>;
+
+fn not_a_value(what: &str) -> CefConversionError {
+    CefConversionError::Unexpected(format!(
+        "{} can't be written as a single CEF extension value",
+        what
+    ))
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = String;
+    type Error = CefConversionError;
+    type SerializeSeq = Impossible<String, CefConversionError>;
+    type SerializeTuple = Impossible<String, CefConversionError>;
+    type SerializeTupleStruct = Impossible<String, CefConversionError>;
+    type SerializeTupleVariant = Impossible<String, CefConversionError>;
+    type SerializeMap = Impossible<String, CefConversionError>;
+    type SerializeStruct = Impossible<String, CefConversionError>;
+    type SerializeStructVariant = Impossible<String, CefConversionError>;
+
+    fn serialize_bool(self, v: bool) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> ValueResult {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> ValueResult {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> ValueResult {
+        Err(not_a_value("Bytes"))
+    }
+
+    fn serialize_none(self) -> ValueResult {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> ValueResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> ValueResult {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> ValueResult {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> ValueResult {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> ValueResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> ValueResult {
+        Err(not_a_value(variant))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, CefConversionError> {
+        Err(not_a_value("A nested sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, CefConversionError> {
+        Err(not_a_value("A nested tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, CefConversionError> {
+        Err(not_a_value(name))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, CefConversionError> {
+        Err(not_a_value(variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, CefConversionError> {
+        Err(not_a_value("A map"))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, CefConversionError> {
+        Err(not_a_value(name))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, CefConversionError> {
+        Err(not_a_value(variant))
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DuplicateKeyPolicy, SeverityPolicy};
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Login {
+        event: String,
+        level: u8,
+        src: String,
+        suser: Option<String>,
+        cnt: u32,
+        outcome: Outcome,
+        geo: Geo,
+        tags: Vec<&'static str>,
+        labels: BTreeMap<String, f64>,
+    }
+
+    #[derive(Serialize)]
+    struct Geo {
+        country: &'static str,
+        city: Option<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    enum Outcome {
+        Failure,
+    }
+
+    fn login() -> Login {
+        Login {
+            event: "Login failed".to_owned(),
+            level: 7,
+            src: "10.0.0.1".to_owned(),
+            suser: None,
+            cnt: 3,
+            outcome: Outcome::Failure,
+            geo: Geo {
+                country: "NZ",
+                city: Some("Wellington"),
+            },
+            tags: vec!["auth", "ssh"],
+            labels: vec![("risk".to_owned(), 0.5)].into_iter().collect(),
+        }
+    }
+
+    fn encoder() -> CefSerdeEncoder {
+        CefSerdeEncoder::new()
+            .with_header(CefHeader::Version, "0")
+            .with_header(CefHeader::DeviceVendor, "polyverse")
+            .with_header(CefHeader::DeviceProduct, "zerotect")
+            .with_header(CefHeader::DeviceVersion, "V1")
+            .with_header(CefHeader::DeviceEventClassID, "Login")
+            .with_header_field(CefHeader::Name, "event")
+            .with_header_field(CefHeader::Severity, "level")
+    }
+
+    // What the derive macros would generate for Login, with gobbled nested items
+    struct DerivedLogin(Login);
+
+    impl CefHeaderVersion for DerivedLogin {
+        fn cef_header_version(&self) -> CefResult {
+            Ok("0".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceVendor for DerivedLogin {
+        fn cef_header_device_vendor(&self) -> CefResult {
+            Ok("polyverse".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceProduct for DerivedLogin {
+        fn cef_header_device_product(&self) -> CefResult {
+            Ok("zerotect".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceVersion for DerivedLogin {
+        fn cef_header_device_version(&self) -> CefResult {
+            Ok("V1".to_owned())
+        }
+    }
+
+    impl CefHeaderDeviceEventClassID for DerivedLogin {
+        fn cef_header_device_event_class_id(&self) -> CefResult {
+            Ok("Login".to_owned())
+        }
+    }
+
+    impl CefHeaderName for DerivedLogin {
+        fn cef_header_name(&self) -> CefResult {
+            Ok(self.0.event.clone())
+        }
+    }
+
+    impl CefHeaderSeverity for DerivedLogin {
+        fn cef_header_severity(&self) -> CefResult {
+            Ok(self.0.level.to_string())
+        }
+    }
+
+    impl CefExtensions for DerivedLogin {
+        fn cef_extensions(&self, collector: &mut dyn CefCollector) -> CefExtensionsResult {
+            collector.insert("src", &self.0.src)?;
+            collector.insert_fmt("cnt", format_args!("{}", self.0.cnt))?;
+            collector.insert("outcome", "Failure")?;
+            collector.insert("geo.country", self.0.geo.country)?;
+            collector.insert("geo.city", "Wellington")?;
+            collector.insert("tags", "auth,ssh")?;
+            collector.insert("labels.risk", "0.5")
+        }
+    }
+
+    impl ToCef for DerivedLogin {}
+
+    #[test]
+    fn test_matches_to_cef() {
+        let expected = "CEF:0|polyverse|zerotect|V1|Login|Login failed|7|cnt=3 geo.city=Wellington geo.country=NZ labels.risk=0.5 outcome=Failure src=10.0.0.1 tags=auth,ssh";
+        assert_eq!(DerivedLogin(login()).to_cef().unwrap(), expected);
+        assert_eq!(encoder().to_cef(&login()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_nested_keys() {
+        let cef = encoder()
+            .with_nested_keys(NestedKeys::Underscored)
+            .to_cef(&login())
+            .unwrap();
+        assert!(cef.contains(" geo_city=Wellington geo_country=NZ labels_risk=0.5 "));
+    }
+
+    #[test]
+    fn test_headers() {
+        assert_eq!(
+            CefSerdeEncoder::new().to_cef(&login()),
+            Err(CefConversionError::MissingHeader {
+                header: "CefHeaderVersion".to_owned()
+            })
+        );
+
+        // a missing header field is a missing header
+        let mut value = login();
+        value.suser = None;
+        assert_eq!(
+            encoder()
+                .with_header_field(CefHeader::Name, "suser")
+                .to_cef(&value),
+            Err(CefConversionError::MissingHeader {
+                header: "CefHeaderName".to_owned()
+            })
+        );
+
+        // severity policies apply
+        value.level = 25;
+        assert!(encoder()
+            .to_cef(&value)
+            .unwrap()
+            .contains("|Login failed|25|"));
+        assert!(encoder()
+            .with_options(CefEncodeOptions::default().with_severity(SeverityPolicy::Clamp))
+            .to_cef(&value)
+            .unwrap()
+            .contains("|Login failed|10|"));
+    }
+
+    #[test]
+    fn test_options_and_errors() {
+        let mut map = HashMap::new();
+        map.insert("src", "10.0.0.1");
+        let encoder = encoder()
+            .with_header(CefHeader::Name, "n")
+            .with_header(CefHeader::Severity, "1")
+            .with_options(
+                CefEncodeOptions::default().with_duplicate_keys(DuplicateKeyPolicy::Error),
+            );
+        assert_eq!(
+            encoder.to_cef(&map).unwrap(),
+            "CEF:0|polyverse|zerotect|V1|Login|n|1|src=10.0.0.1"
+        );
+
+        #[derive(Serialize)]
+        struct Dup {
+            src: &'static str,
+            #[serde(rename = "src")]
+            src2: &'static str,
+        }
+        assert!(matches!(
+            encoder.to_cef(&Dup {
+                src: "a",
+                src2: "b"
+            }),
+            Err(CefConversionError::DuplicateKey { .. })
+        ));
+
+        assert_eq!(encoder.to_cef(&42), Err(top_level_error()));
+
+        #[derive(Serialize)]
+        struct Nested {
+            rows: Vec<Vec<u8>>,
+        }
+        assert!(encoder
+            .to_cef(&Nested {
+                rows: vec![vec![1]]
+            })
+            .is_err());
+    }
+}
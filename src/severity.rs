@@ -0,0 +1,271 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides the CefSeverity type, which holds only the severities
+/// the CEF standard allows, and the policy applied to severity headers
+/// that fall outside of them.
+use crate::{CefConversionError, CefHeaderSeverity, CefResult};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+const SEVERITY_HEADER: &str = "CefHeaderSeverity";
+
+/// The highest numeric severity allowed
+pub const MAX_SEVERITY_LEVEL: u8 = 10;
+
+/// A numeric CEF severity, which is always within 0 to 10
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CefSeverityLevel(u8);
+
+impl CefSeverityLevel {
+    /// The level, or None if it's above 10
+    pub fn new(level: u8) -> Option<Self> {
+        match level <= MAX_SEVERITY_LEVEL {
+            true => Some(CefSeverityLevel(level)),
+            false => None,
+        }
+    }
+
+    /// The level, clamped to 0 to 10
+    pub fn clamped(level: i64) -> Self {
+        CefSeverityLevel(level.clamp(0, MAX_SEVERITY_LEVEL as i64) as u8)
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+/// A CEF severity, either numeric (0 to 10) or one of the named
+/// severities Unknown, Low, Medium, High and Very-High.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CefSeverity {
+    /// A numeric severity
+    Level(CefSeverityLevel),
+    Unknown,
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl CefSeverity {
+    /// A numeric severity, or None if it's above 10
+    pub fn level(level: u8) -> Option<Self> {
+        CefSeverityLevel::new(level).map(CefSeverity::Level)
+    }
+
+    /// A numeric severity, clamped to 0 to 10
+    pub fn clamped(level: i64) -> Self {
+        CefSeverity::Level(CefSeverityLevel::clamped(level))
+    }
+
+    /// The numeric severity. Named severities convert to the highest level
+    /// of their range (i.e. Low is 3), and Unknown has no level.
+    pub fn to_level(&self) -> Option<u8> {
+        match self {
+            CefSeverity::Level(level) => Some(level.get()),
+            CefSeverity::Unknown => None,
+            CefSeverity::Low => Some(3),
+            CefSeverity::Medium => Some(6),
+            CefSeverity::High => Some(8),
+            CefSeverity::VeryHigh => Some(10),
+        }
+    }
+
+    /// The named severity. Levels 0-3 are Low, 4-6 Medium,
+    /// 7-8 High and 9-10 Very-High.
+    pub fn to_named(&self) -> Self {
+        match self {
+            CefSeverity::Level(level) => match level.get() {
+                0..=3 => CefSeverity::Low,
+                4..=6 => CefSeverity::Medium,
+                7..=8 => CefSeverity::High,
+                _ => CefSeverity::VeryHigh,
+            },
+            named => *named,
+        }
+    }
+}
+
+impl Display for CefSeverity {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            CefSeverity::Level(level) => write!(f, "{}", level.get()),
+            CefSeverity::Unknown => write!(f, "Unknown"),
+            CefSeverity::Low => write!(f, "Low"),
+            CefSeverity::Medium => write!(f, "Medium"),
+            CefSeverity::High => write!(f, "High"),
+            CefSeverity::VeryHigh => write!(f, "Very-High"),
+        }
+    }
+}
+
+impl FromStr for CefSeverity {
+    type Err = CefConversionError;
+
+    /// Parses a numeric (0 to 10) or named severity. Names are case-insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| CefConversionError::InvalidHeaderValue {
+            header: SEVERITY_HEADER.to_owned(),
+            value: s.to_owned(),
+            reason: reason.to_owned(),
+        };
+
+        if let Ok(level) = s.parse::<i64>() {
+            return u8::try_from(level)
+                .ok()
+                .and_then(CefSeverity::level)
+                .ok_or_else(|| invalid("severity must be between 0 and 10"));
+        }
+
+        match s.to_ascii_lowercase().as_str() {
+            "unknown" => Ok(CefSeverity::Unknown),
+            "low" => Ok(CefSeverity::Low),
+            "medium" => Ok(CefSeverity::Medium),
+            "high" => Ok(CefSeverity::High),
+            "very-high" => Ok(CefSeverity::VeryHigh),
+            _ => Err(invalid(
+                "severity must be 0 to 10, Unknown, Low, Medium, High or Very-High",
+            )),
+        }
+    }
+}
+
+impl CefHeaderSeverity for CefSeverity {
+    fn cef_header_severity(&self) -> CefResult {
+        Ok(self.to_string())
+    }
+}
+
+/// What to do when a severity header isn't one the spec allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeverityPolicy {
+    /// Emit the severity as it is
+    #[default]
+    PassThrough,
+    /// Fail with `CefConversionError::InvalidHeaderValue`
+    Reject,
+    /// Clamp numbers to 0 or 10, and reject anything else
+    Clamp,
+}
+
+impl SeverityPolicy {
+    /// Checks a severity header, returning the (possibly clamped) value to emit.
+    ///
+    /// Values that are neither numbers nor named severities are rejected
+    /// unless they're passed through.
+    pub fn apply(&self, severity: String) -> CefResult {
+        match (CefSeverity::from_str(&severity), severity.parse::<i64>()) {
+            (Ok(_), _) => Ok(severity),
+            (Err(_), _) if *self == SeverityPolicy::PassThrough => Ok(severity),
+            (Err(_), Ok(level)) if *self == SeverityPolicy::Clamp => {
+                Ok(CefSeverity::clamped(level).to_string())
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_severity_conversions() {
+        let level = |level| CefSeverity::level(level).unwrap();
+
+        assert_eq!(CefSeverityLevel::new(10).map(|l| l.get()), Some(10));
+        assert_eq!(CefSeverityLevel::new(11), None);
+        assert_eq!(CefSeverity::level(11), None);
+        assert_eq!(CefSeverity::clamped(85), level(10));
+        assert_eq!(CefSeverity::clamped(-3), level(0));
+
+        assert_eq!(level(3).to_named(), CefSeverity::Low);
+        assert_eq!(level(4).to_named(), CefSeverity::Medium);
+        assert_eq!(level(8).to_named(), CefSeverity::High);
+        assert_eq!(level(9).to_named(), CefSeverity::VeryHigh);
+        assert_eq!(CefSeverity::High.to_named(), CefSeverity::High);
+
+        assert_eq!(CefSeverity::Medium.to_level(), Some(6));
+        assert_eq!(CefSeverity::Unknown.to_level(), None);
+        assert_eq!(level(2).to_level(), Some(2));
+    }
+
+    #[test]
+    fn test_severity_display_and_parse() {
+        for severity in &[
+            CefSeverity::clamped(0),
+            CefSeverity::clamped(10),
+            CefSeverity::Unknown,
+            CefSeverity::Low,
+            CefSeverity::Medium,
+            CefSeverity::High,
+            CefSeverity::VeryHigh,
+        ] {
+            assert_eq!(severity.to_string().parse::<CefSeverity>(), Ok(*severity));
+        }
+
+        assert_eq!(CefSeverity::VeryHigh.to_string(), "Very-High");
+        assert_eq!(
+            "very-HIGH".parse::<CefSeverity>(),
+            Ok(CefSeverity::VeryHigh)
+        );
+        assert!("11".parse::<CefSeverity>().is_err());
+        assert!("-1".parse::<CefSeverity>().is_err());
+        assert_eq!(
+            "Critical".parse::<CefSeverity>(),
+            Err(CefConversionError::InvalidHeaderValue {
+                header: "CefHeaderSeverity".to_owned(),
+                value: "Critical".to_owned(),
+                reason: "severity must be 0 to 10, Unknown, Low, Medium, High or Very-High"
+                    .to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_severity_policy() {
+        assert_eq!(
+            SeverityPolicy::default().apply("85".to_owned()),
+            Ok("85".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::PassThrough.apply("urgent".to_owned()),
+            Ok("urgent".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::Clamp.apply("7".to_owned()),
+            Ok("7".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::Clamp.apply("85".to_owned()),
+            Ok("10".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::Clamp.apply("-2".to_owned()),
+            Ok("0".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::Clamp.apply("High".to_owned()),
+            Ok("High".to_owned())
+        );
+        assert!(SeverityPolicy::Clamp.apply("Critical".to_owned()).is_err());
+
+        assert_eq!(
+            SeverityPolicy::Reject.apply("10".to_owned()),
+            Ok("10".to_owned())
+        );
+        assert_eq!(
+            SeverityPolicy::Reject.apply("85".to_owned()),
+            Err(CefConversionError::InvalidHeaderValue {
+                header: "CefHeaderSeverity".to_owned(),
+                value: "85".to_owned(),
+                reason: "severity must be between 0 and 10".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,512 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module signs encoded CEF lines so they can be proven unaltered, optionally
+/// chaining every line to the one before it, and verifies files of signed lines.
+///
+/// A signature is appended as the last extension, `signature=<algorithm>:<hex>`,
+/// and covers everything before it. In chained mode a `previousHash` extension
+/// holding the SHA-256 of the previous signed line (or `GENESIS_HASH` for the
+/// first one) is appended before signing, so removing, reordering or altering
+/// any line breaks the chain.
+use crate::parser::split_line;
+use crate::{CefConversionError, CefResult, ToCef};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The extension holding the signature
+pub const SIGNATURE_KEY: &str = "signature";
+
+/// The extension holding the hash of the previous line in chained mode
+pub const PREVIOUS_HASH_KEY: &str = "previousHash";
+
+/// The previous hash of the first line of a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+const HMAC_SHA256: &str = "hmac-sha256";
+#[cfg(feature = "ed25519")]
+const ED25519: &str = "ed25519";
+
+/// The key lines are signed with
+#[derive(Clone)]
+pub enum CefSigningKey {
+    /// HMAC-SHA256 with a shared secret
+    Hmac(Vec<u8>),
+    /// Ed25519, only available with the `ed25519` feature
+    #[cfg(feature = "ed25519")]
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl CefSigningKey {
+    fn sign(&self, signed: &str) -> String {
+        match self {
+            CefSigningKey::Hmac(secret) => {
+                let mac = hmac_sha256(secret, signed).finalize().into_bytes();
+                format!("{}:{}", HMAC_SHA256, to_hex(&mac))
+            }
+            #[cfg(feature = "ed25519")]
+            CefSigningKey::Ed25519(key) => {
+                use ed25519_dalek::Signer;
+                let signature = key.sign(signed.as_bytes());
+                format!("{}:{}", ED25519, to_hex(&signature.to_bytes()))
+            }
+        }
+    }
+}
+
+/// The key signatures are checked with
+#[derive(Clone)]
+pub enum CefVerifyingKey {
+    /// HMAC-SHA256 with a shared secret
+    Hmac(Vec<u8>),
+    /// Ed25519, only available with the `ed25519` feature
+    #[cfg(feature = "ed25519")]
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+impl CefVerifyingKey {
+    fn verify(&self, signed: &str, signature: &str) -> bool {
+        let (algorithm, signature) = match signature.split_once(':') {
+            Some((algorithm, signature)) => (algorithm, from_hex(signature)),
+            None => return false,
+        };
+
+        match (self, algorithm, signature) {
+            (CefVerifyingKey::Hmac(secret), HMAC_SHA256, Some(signature)) => {
+                hmac_sha256(secret, signed).verify_slice(&signature).is_ok()
+            }
+            #[cfg(feature = "ed25519")]
+            (CefVerifyingKey::Ed25519(key), ED25519, Some(signature)) => {
+                match ed25519_dalek::Signature::from_slice(&signature) {
+                    Ok(signature) => key.verify_strict(signed.as_bytes(), &signature).is_ok(),
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Signs encoded CEF lines, one after the other.
+///
+/// ```ignore
+/// let mut signer = CefSigner::new(CefSigningKey::Hmac(secret)).with_chain();
+/// writeln!(file, "{}", signer.sign(&event)?)?;
+/// ```
+pub struct CefSigner {
+    key: CefSigningKey,
+    chained: bool,
+    previous_hash: String,
+}
+
+impl CefSigner {
+    pub fn new(key: CefSigningKey) -> Self {
+        Self {
+            key,
+            chained: false,
+            previous_hash: GENESIS_HASH.to_owned(),
+        }
+    }
+
+    /// Returns this signer chaining every line to the previous one
+    pub fn with_chain(mut self) -> Self {
+        self.chained = true;
+        self
+    }
+
+    /// Returns this signer continuing a chain (i.e. in a new file) whose
+    /// last line hashes to the given hex SHA-256
+    pub fn with_previous_hash(mut self, previous_hash: &str) -> Self {
+        self.previous_hash = previous_hash.to_owned();
+        self
+    }
+
+    /// Encodes the item with `to_cef` and signs it
+    pub fn sign<T: ToCef + ?Sized>(&mut self, item: &T) -> CefResult {
+        self.sign_line(&item.to_cef()?)
+    }
+
+    /// Signs an encoded CEF line
+    pub fn sign_line(&mut self, line: &str) -> CefResult {
+        if line.contains(['\r', '\n']) {
+            return Err(CefConversionError::Unexpected(
+                "a signed CEF line can't span more than one line".to_owned(),
+            ));
+        }
+
+        // values may end in a pipe, so only the headers tell whether there are extensions
+        let (_, extensions) = split_line(line)?;
+        let mut has_extensions = !extensions.is_empty();
+
+        let mut signed = line.to_owned();
+        if self.chained {
+            append_extension(
+                &mut signed,
+                has_extensions,
+                PREVIOUS_HASH_KEY,
+                &self.previous_hash,
+            );
+            has_extensions = true;
+        }
+        let signature = self.key.sign(&signed);
+        append_extension(&mut signed, has_extensions, SIGNATURE_KEY, &signature);
+
+        if self.chained {
+            self.previous_hash = line_hash(&signed);
+        }
+        Ok(signed)
+    }
+}
+
+/// Why a line failed verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrokenLinkReason {
+    /// The line has no `signature` extension
+    MissingSignature,
+    /// The signature doesn't match the line (or was made with another key)
+    BadSignature,
+    /// Chained mode, but the line has no `previousHash` extension
+    MissingPreviousHash,
+    /// The line isn't chained to the one before it
+    PreviousHashMismatch { expected: String, found: String },
+    /// The line couldn't be read
+    Unreadable(String),
+}
+
+/// The first line of a file that failed verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The line's number, starting at 1
+    pub line: usize,
+    pub reason: BrokenLinkReason,
+}
+
+impl Display for BrokenLink {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Line {}: ", self.line)?;
+        match &self.reason {
+            BrokenLinkReason::MissingSignature => write!(f, "the line isn't signed"),
+            BrokenLinkReason::BadSignature => write!(f, "the signature doesn't match the line"),
+            BrokenLinkReason::MissingPreviousHash => {
+                write!(f, "the line has no {} extension", PREVIOUS_HASH_KEY)
+            }
+            BrokenLinkReason::PreviousHashMismatch { expected, found } => write!(
+                f,
+                "the previous line hashes to {}, but the line chains to {}",
+                expected, found
+            ),
+            BrokenLinkReason::Unreadable(e) => write!(f, "the line couldn't be read: {}", e),
+        }
+    }
+}
+
+impl Error for BrokenLink {}
+
+/// Verifies signed CEF lines, one after the other, the way `CefSigner` signed them
+pub struct CefVerifier {
+    key: CefVerifyingKey,
+    chained: bool,
+    previous_hash: String,
+}
+
+impl CefVerifier {
+    pub fn new(key: CefVerifyingKey) -> Self {
+        Self {
+            key,
+            chained: false,
+            previous_hash: GENESIS_HASH.to_owned(),
+        }
+    }
+
+    /// Returns this verifier checking every line is chained to the previous one
+    pub fn with_chain(mut self) -> Self {
+        self.chained = true;
+        self
+    }
+
+    /// Returns this verifier expecting the first line to chain to the given hash
+    pub fn with_previous_hash(mut self, previous_hash: &str) -> Self {
+        self.previous_hash = previous_hash.to_owned();
+        self
+    }
+
+    /// Verifies the next line of the chain
+    pub fn verify_line(&mut self, line: &str) -> Result<(), BrokenLinkReason> {
+        let (signed, signature) =
+            split_extension(line, SIGNATURE_KEY).ok_or(BrokenLinkReason::MissingSignature)?;
+        if !self.key.verify(signed, signature) {
+            return Err(BrokenLinkReason::BadSignature);
+        }
+
+        if self.chained {
+            let (_, previous_hash) = split_extension(signed, PREVIOUS_HASH_KEY)
+                .ok_or(BrokenLinkReason::MissingPreviousHash)?;
+            if previous_hash != self.previous_hash {
+                return Err(BrokenLinkReason::PreviousHashMismatch {
+                    expected: self.previous_hash.clone(),
+                    found: previous_hash.to_owned(),
+                });
+            }
+            self.previous_hash = line_hash(line);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies every line (skipping blank ones), returning the number of lines
+    /// verified, or the first line that failed.
+    pub fn verify_lines<R: BufRead>(&mut self, reader: R) -> Result<usize, BrokenLink> {
+        let mut verified = 0;
+        for (index, line) in reader.lines().enumerate() {
+            let broken = |reason| BrokenLink {
+                line: index + 1,
+                reason,
+            };
+
+            let line = line.map_err(|e| broken(BrokenLinkReason::Unreadable(e.to_string())))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            self.verify_line(&line).map_err(broken)?;
+            verified += 1;
+        }
+        Ok(verified)
+    }
+
+    /// Verifies every line of a file, as `verify_lines` does
+    pub fn verify_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, BrokenLink> {
+        let file = File::open(path).map_err(|e| BrokenLink {
+            line: 0,
+            reason: BrokenLinkReason::Unreadable(e.to_string()),
+        })?;
+        self.verify_lines(BufReader::new(file))
+    }
+}
+
+fn hmac_sha256(secret: &[u8], signed: &str) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(signed.as_bytes());
+    mac
+}
+
+/// The hex SHA-256 of a signed line, as chained by the line after it
+pub fn line_hash(line: &str) -> String {
+    to_hex(&Sha256::digest(line.as_bytes()))
+}
+
+// Extensions follow the last header's pipe, or a space after other extensions
+fn append_extension(line: &mut String, has_extensions: bool, key: &str, value: &str) {
+    if has_extensions {
+        line.push(' ');
+    }
+    line.push_str(key);
+    line.push('=');
+    line.push_str(value);
+}
+
+// Splits off the last extension, when it has the given key. Equal signs in values
+// are escaped, so `key=` can only be an extension key. The first part is the line
+// as it was before the extension was appended.
+fn split_extension<'a>(line: &'a str, key: &str) -> Option<(&'a str, &'a str)> {
+    let start = line.rfind(&format!("{}=", key))?;
+    let value = &line[start + key.len() + 1..];
+    if value.contains(' ') {
+        return None;
+    }
+
+    match line[..start].chars().last() {
+        Some(' ') => Some((&line[..start - 1], value)),
+        Some('|') => Some((&line[..start], value)),
+        _ => None,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+    use std::io::Cursor;
+
+    const LINES: &[&str] = &[
+        "CEF:0|polyverse|zerotect|V1|Login|Login|3|src=10.0.0.1 suser=alice",
+        "CEF:0|polyverse|zerotect|V1|Logout|Logout|3|",
+        "CEF:0|polyverse|zerotect|V1|Login|Login|3|msg=a\\=b signature\\=x src=10.0.0.2",
+        "CEF:0|polyverse|zerotect|V1|Login|Login|3|msg=a|",
+    ];
+
+    fn sign_all(signer: &mut CefSigner) -> Vec<String> {
+        LINES
+            .iter()
+            .map(|line| signer.sign_line(line).unwrap())
+            .collect()
+    }
+
+    fn verify(verifier: &mut CefVerifier, lines: &[String]) -> Result<usize, BrokenLink> {
+        verifier.verify_lines(Cursor::new(lines.join("\n")))
+    }
+
+    #[test]
+    fn test_signed_lines() {
+        let mut signer = CefSigner::new(CefSigningKey::Hmac(b"secret".to_vec()));
+        let lines = sign_all(&mut signer);
+
+        assert!(lines[0].starts_with(&format!("{} signature=hmac-sha256:", LINES[0])));
+        assert!(lines[1].starts_with(&format!("{}signature=hmac-sha256:", LINES[1])));
+        // still CEF, and the signature is just another extension
+        let record = parse_cef(&lines[2]).unwrap();
+        assert_eq!(record.extension("msg"), Some("a=b signature=x"));
+        assert_eq!(record.extension("signature").unwrap().len(), 12 + 64);
+        // a value ending in a pipe isn't mistaken for the end of the headers
+        assert!(lines[3].starts_with(&format!("{} signature=hmac-sha256:", LINES[3])));
+        let record = parse_cef(&lines[3]).unwrap();
+        assert_eq!(record.extension("msg"), Some("a|"));
+
+        let mut verifier = CefVerifier::new(CefVerifyingKey::Hmac(b"secret".to_vec()));
+        assert_eq!(verify(&mut verifier, &lines), Ok(4));
+
+        // another key
+        let mut verifier = CefVerifier::new(CefVerifyingKey::Hmac(b"other".to_vec()));
+        assert_eq!(
+            verify(&mut verifier, &lines),
+            Err(BrokenLink {
+                line: 1,
+                reason: BrokenLinkReason::BadSignature
+            })
+        );
+
+        // an altered line
+        let mut altered = lines.clone();
+        altered[1] = altered[1].replace("Logout|3", "Logout|9");
+        let mut verifier = CefVerifier::new(CefVerifyingKey::Hmac(b"secret".to_vec()));
+        assert_eq!(
+            verify(&mut verifier, &altered),
+            Err(BrokenLink {
+                line: 2,
+                reason: BrokenLinkReason::BadSignature
+            })
+        );
+
+        // an unsigned line
+        let mut unsigned = lines.clone();
+        unsigned.push(String::new());
+        unsigned.push(LINES[0].to_owned());
+        assert_eq!(
+            verify(&mut verifier, &unsigned).unwrap_err().to_string(),
+            "Line 6: the line isn't signed"
+        );
+
+        assert!(signer.sign_line("not a CEF line").is_err());
+    }
+
+    #[test]
+    fn test_chained_lines() {
+        let mut signer = CefSigner::new(CefSigningKey::Hmac(b"secret".to_vec())).with_chain();
+        let lines = sign_all(&mut signer);
+
+        assert!(lines[0].contains(&format!("previousHash={} signature=", GENESIS_HASH)));
+        assert!(lines[1].contains(&format!(
+            "|3|previousHash={} signature=",
+            line_hash(&lines[0])
+        )));
+
+        let verifier = || CefVerifier::new(CefVerifyingKey::Hmac(b"secret".to_vec())).with_chain();
+        assert_eq!(verify(&mut verifier(), &lines), Ok(4));
+
+        // a removed line breaks the chain, even though every signature is good
+        let removed = vec![lines[0].clone(), lines[2].clone()];
+        assert_eq!(
+            verify(&mut verifier(), &removed),
+            Err(BrokenLink {
+                line: 2,
+                reason: BrokenLinkReason::PreviousHashMismatch {
+                    expected: line_hash(&lines[0]),
+                    found: line_hash(&lines[1]),
+                }
+            })
+        );
+
+        // a chain continued in another file
+        assert_eq!(
+            verify(
+                &mut verifier().with_previous_hash(&line_hash(&lines[0])),
+                &lines[1..]
+            ),
+            Ok(3)
+        );
+
+        // lines signed without a chain
+        let mut signer = CefSigner::new(CefSigningKey::Hmac(b"secret".to_vec()));
+        assert_eq!(
+            verify(&mut verifier(), &sign_all(&mut signer)),
+            Err(BrokenLink {
+                line: 1,
+                reason: BrokenLinkReason::MissingPreviousHash
+            })
+        );
+
+        assert!(signer
+            .sign_line("CEF:0|a|b|c|d|e|3|msg=two\nlines")
+            .is_err());
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_ed25519() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let mut signer = CefSigner::new(CefSigningKey::Ed25519(key.clone())).with_chain();
+        let lines = sign_all(&mut signer);
+        assert!(lines[0].contains(" signature=ed25519:"));
+
+        let mut verifier =
+            CefVerifier::new(CefVerifyingKey::Ed25519(key.verifying_key())).with_chain();
+        assert_eq!(verify(&mut verifier, &lines), Ok(4));
+
+        let other = ed25519_dalek::SigningKey::from_bytes(&[8; 32]);
+        let mut verifier = CefVerifier::new(CefVerifyingKey::Ed25519(other.verifying_key()));
+        assert_eq!(
+            verify(&mut verifier, &lines),
+            Err(BrokenLink {
+                line: 1,
+                reason: BrokenLinkReason::BadSignature
+            })
+        );
+
+        // an HMAC signature isn't accepted in place of an Ed25519 one
+        let mut signer = CefSigner::new(CefSigningKey::Hmac(b"secret".to_vec()));
+        let mut verifier = CefVerifier::new(CefVerifyingKey::Ed25519(key.verifying_key()));
+        assert_eq!(
+            verify(&mut verifier, &sign_all(&mut signer)),
+            Err(BrokenLink {
+                line: 1,
+                reason: BrokenLinkReason::BadSignature
+            })
+        );
+    }
+}
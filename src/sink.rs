@@ -0,0 +1,581 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides sinks that send ToCef items, wrapped in syslog
+/// messages, over UDP, TCP and Unix sockets.
+use crate::syslog::{SyslogFormatter, SyslogFraming};
+use crate::{CefConversionError, CefExtensionsResult, ToCef};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The largest datagram RFC 5426 asks receivers to accept
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 2048;
+
+/// The largest payload of a UDP datagram over IPv4
+pub const MAX_UDP_DATAGRAM_SIZE: usize = 65507;
+
+/// A destination for CEF events
+pub trait CefSink {
+    /// Sends a single, already formatted, message
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult;
+
+    /// The formatter used to wrap items in syslog messages
+    fn formatter(&self) -> &SyslogFormatter;
+
+    /// Formats the item as a syslog message and sends it
+    fn send<T: ToCef + ?Sized>(&mut self, item: &T) -> CefExtensionsResult
+    where
+        Self: Sized,
+    {
+        let message = self.formatter().format(item)?;
+        self.send_message(&message)
+    }
+}
+
+/// What a datagram sink does with messages that don't fit in a datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+    /// Fail with CefConversionError::DatagramTooLarge
+    Reject,
+    /// Cut the message short, as RFC 5426 allows
+    #[default]
+    Truncate,
+}
+
+/// Sends one message per UDP datagram (RFC 5426)
+#[derive(Debug)]
+pub struct UdpSink {
+    socket: UdpSocket,
+    formatter: SyslogFormatter,
+    max_datagram_size: usize,
+    oversize: OversizePolicy,
+}
+
+impl UdpSink {
+    /// Binds an ephemeral local port and sends to the given address
+    pub fn connect<A: ToSocketAddrs>(addr: A, formatter: SyslogFormatter) -> io::Result<Self> {
+        let addr = resolve(addr)?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(Self {
+            socket,
+            formatter,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            oversize: OversizePolicy::default(),
+        })
+    }
+
+    /// Sets the largest datagram sent, capped at MAX_UDP_DATAGRAM_SIZE
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size.min(MAX_UDP_DATAGRAM_SIZE);
+        self
+    }
+
+    /// Sets what happens to messages larger than the datagram size (truncated by default)
+    pub fn with_oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize = policy;
+        self
+    }
+}
+
+impl CefSink for UdpSink {
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+        let datagram = fit_datagram(message, self.max_datagram_size, self.oversize)?;
+        self.socket.send(datagram.as_bytes())?;
+        Ok(())
+    }
+
+    fn formatter(&self) -> &SyslogFormatter {
+        &self.formatter
+    }
+}
+
+/// Sends framed messages over TCP (RFC 6587), reconnecting when the
+/// connection is lost.
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: SocketAddr,
+    connection: Reconnecting<TcpStream>,
+    formatter: SyslogFormatter,
+    framing: SyslogFraming,
+    timeout: Option<Duration>,
+}
+
+impl TcpSink {
+    /// Connects to the given address, blocking for as long as the OS allows
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+    ) -> io::Result<Self> {
+        Self::open(addr, formatter, framing, None)
+    }
+
+    /// Connects to the given address, giving up after the timeout. The timeout
+    /// also applies to writes and to reconnecting.
+    pub fn connect_timeout<A: ToSocketAddrs>(
+        addr: A,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        Self::open(addr, formatter, framing, Some(timeout))
+    }
+
+    fn open<A: ToSocketAddrs>(
+        addr: A,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let addr = resolve(addr)?;
+        Ok(Self {
+            addr,
+            connection: Reconnecting {
+                stream: Some(connect_tcp(addr, timeout)?),
+            },
+            formatter,
+            framing,
+            timeout,
+        })
+    }
+}
+
+impl CefSink for TcpSink {
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+        let framed = self.framing.frame(message);
+        let (addr, timeout) = (self.addr, self.timeout);
+        self.connection
+            .write(framed.as_bytes(), || connect_tcp(addr, timeout))?;
+        Ok(())
+    }
+
+    fn formatter(&self) -> &SyslogFormatter {
+        &self.formatter
+    }
+}
+
+/// Sends one message per datagram to a Unix socket, i.e. `/dev/log`.
+/// Local syslog daemons usually expect RFC 3164 messages.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixDatagramSink {
+    socket: UnixDatagram,
+    formatter: SyslogFormatter,
+    max_datagram_size: usize,
+    oversize: OversizePolicy,
+}
+
+#[cfg(unix)]
+impl UnixDatagramSink {
+    /// Sends to the socket at the given path from an unbound socket
+    pub fn connect<P: AsRef<Path>>(path: P, formatter: SyslogFormatter) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self {
+            socket,
+            formatter,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            oversize: OversizePolicy::default(),
+        })
+    }
+
+    /// Sets the largest datagram sent
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// Sets what happens to messages larger than the datagram size (truncated by default)
+    pub fn with_oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize = policy;
+        self
+    }
+}
+
+#[cfg(unix)]
+impl CefSink for UnixDatagramSink {
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+        let datagram = fit_datagram(message, self.max_datagram_size, self.oversize)?;
+        self.socket.send(datagram.as_bytes())?;
+        Ok(())
+    }
+
+    fn formatter(&self) -> &SyslogFormatter {
+        &self.formatter
+    }
+}
+
+/// Sends framed messages over a Unix stream socket, reconnecting when
+/// the connection is lost.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixStreamSink {
+    path: PathBuf,
+    connection: Reconnecting<UnixStream>,
+    formatter: SyslogFormatter,
+    framing: SyslogFraming,
+}
+
+#[cfg(unix)]
+impl UnixStreamSink {
+    /// Connects to the socket at the given path
+    pub fn connect<P: AsRef<Path>>(
+        path: P,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let stream = UnixStream::connect(&path)?;
+        Ok(Self {
+            path,
+            connection: Reconnecting {
+                stream: Some(stream),
+            },
+            formatter,
+            framing,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl CefSink for UnixStreamSink {
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+        let framed = self.framing.frame(message);
+        let path = &self.path;
+        self.connection
+            .write(framed.as_bytes(), || UnixStream::connect(path))?;
+        Ok(())
+    }
+
+    fn formatter(&self) -> &SyslogFormatter {
+        &self.formatter
+    }
+}
+
+pub(crate) fn resolve<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            "address did not resolve to any socket address",
+        )
+    })
+}
+
+pub(crate) fn connect_tcp(addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout)?,
+        None => TcpStream::connect(addr)?,
+    };
+    stream.set_write_timeout(timeout)?;
+    Ok(stream)
+}
+
+fn fit_datagram(
+    message: &str,
+    max_size: usize,
+    policy: OversizePolicy,
+) -> Result<&str, CefConversionError> {
+    if message.len() <= max_size {
+        return Ok(message);
+    }
+
+    match policy {
+        OversizePolicy::Reject => Err(CefConversionError::DatagramTooLarge {
+            length: message.len(),
+            max_size,
+        }),
+        OversizePolicy::Truncate => {
+            // never split a character
+            let mut end = max_size;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            Ok(&message[..end])
+        }
+    }
+}
+
+/// Streams a sink can tell have been closed by the other end
+pub(crate) trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+/// A stream that is reopened when the other end goes away
+#[derive(Debug)]
+pub(crate) struct Reconnecting<S> {
+    pub(crate) stream: Option<S>,
+}
+
+impl<S> Default for Reconnecting<S> {
+    fn default() -> Self {
+        Self { stream: None }
+    }
+}
+
+impl<S: Connection> Reconnecting<S> {
+    /// Writes to the current stream, reconnecting first if it was closed,
+    /// and retrying once on a fresh stream if the write fails.
+    ///
+    /// A frame can't be resumed on another connection, so a message that was
+    /// partly written before the write failed is sent again in full. The
+    /// collector may then have received the start of it on the old connection.
+    pub(crate) fn write<F: Fn() -> io::Result<S>>(
+        &mut self,
+        bytes: &[u8],
+        connect: F,
+    ) -> io::Result<()> {
+        if let Some(stream) = &mut self.stream {
+            if is_closed(stream) {
+                self.stream = None;
+            }
+        }
+
+        if let Some(stream) = &mut self.stream {
+            match stream.write_all(bytes).and_then(|_| stream.flush()) {
+                Ok(()) => return Ok(()),
+                Err(_) => self.stream = None,
+            }
+        }
+
+        let mut stream = connect()?;
+        let result = stream.write_all(bytes).and_then(|_| stream.flush());
+        if result.is_ok() {
+            self.stream = Some(stream);
+        }
+        result
+    }
+}
+
+// Syslog receivers never write back, so a readable stream is one
+// that was closed (or is sending data nobody asked for).
+fn is_closed<S: Connection>(stream: &mut S) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+
+    let mut buf = [0u8; 1];
+    let closed = match stream.read(&mut buf) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => e.kind() != ErrorKind::WouldBlock,
+    };
+
+    stream.set_nonblocking(false).is_err() || closed
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+    use crate::syslog::SyslogFormat;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const CEF: &str = "CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|5|src=10.0.0.1";
+
+    fn formatter() -> SyslogFormatter {
+        SyslogFormatter::new(SyslogFormat::Rfc5424).with_hostname("test")
+    }
+
+    #[test]
+    fn test_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap(), formatter()).unwrap();
+
+        sink.send(&parse_cef(CEF).unwrap()).unwrap();
+
+        let mut buf = [0u8; 4096];
+        let len = receiver.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.starts_with("<12>1 "));
+        assert!(message.ends_with(CEF));
+    }
+
+    #[test]
+    fn test_udp_size_limit() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap(), formatter())
+            .unwrap()
+            .with_max_datagram_size(16);
+
+        sink.send_message("0123456789abcdefghij").unwrap();
+        let mut buf = [0u8; 4096];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 16);
+        assert_eq!(&buf[..16], b"0123456789abcdef");
+
+        // multi-byte characters aren't split
+        sink.send_message("0123456789abcdeé").unwrap();
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 15);
+
+        let mut sink = sink.with_oversize_policy(OversizePolicy::Reject);
+        assert_eq!(
+            sink.send_message("0123456789abcdefghij"),
+            Err(CefConversionError::DatagramTooLarge {
+                length: 20,
+                max_size: 16,
+            })
+        );
+        assert_eq!(
+            sink.send_message("0123456789abcdefghij")
+                .unwrap_err()
+                .to_string(),
+            "Message is 20 bytes long, exceeding the maximum datagram size of 16"
+        );
+
+        let sink = sink.with_max_datagram_size(100000);
+        assert_eq!(sink.max_datagram_size, MAX_UDP_DATAGRAM_SIZE);
+    }
+
+    #[test]
+    fn test_tcp_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut newline =
+            TcpSink::connect(addr, formatter(), SyslogFraming::NonTransparent).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        newline.send(&parse_cef(CEF).unwrap()).unwrap();
+        newline.send_message("second").unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("<12>1 "));
+        assert!(line.ends_with(&format!("{}\n", CEF)));
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "second\n");
+
+        let mut counted =
+            TcpSink::connect(addr, formatter(), SyslogFraming::OctetCounting).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        counted.send_message("first").unwrap();
+        counted.send_message("second").unwrap();
+        drop(counted);
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "5 first6 second");
+    }
+
+    #[test]
+    fn test_tcp_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let read_line = |stream: TcpStream| {
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            line
+        };
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // the connection is dropped once the line is read
+            let line = read_line(stream);
+            (listener, line)
+        });
+
+        let mut sink = TcpSink::connect_timeout(
+            addr,
+            formatter(),
+            SyslogFraming::NonTransparent,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        sink.send_message("before").unwrap();
+        let (listener, line) = server.join().unwrap();
+        assert_eq!(line, "before\n");
+
+        sink.send_message("after").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(read_line(stream), "after\n");
+    }
+
+    #[cfg(unix)]
+    fn socket_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rust-cef-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_datagram() {
+        let path = socket_path("dgram");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        let mut sink =
+            UnixDatagramSink::connect(&path, SyslogFormatter::new(SyslogFormat::Rfc3164)).unwrap();
+
+        sink.send(&parse_cef(CEF).unwrap()).unwrap();
+
+        let mut buf = [0u8; 4096];
+        let len = receiver.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.starts_with("<12>"));
+        assert!(message.ends_with(&format!("localhost CEF: {}", CEF)));
+
+        // messages are fitted to the datagram size as they are over UDP
+        let mut sink = sink.with_max_datagram_size(16);
+        sink.send_message("0123456789abcdefghij").unwrap();
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 16);
+        let mut sink = sink.with_oversize_policy(OversizePolicy::Reject);
+        assert!(sink.send_message("0123456789abcdefghij").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_stream() {
+        use std::os::unix::net::UnixListener;
+
+        let path = socket_path("stream");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut sink =
+            UnixStreamSink::connect(&path, formatter(), SyslogFraming::OctetCounting).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        sink.send_message("first").unwrap();
+        drop(stream);
+
+        // the closed connection is noticed and replaced
+        sink.send_message("second").unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        drop(sink);
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "6 second");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
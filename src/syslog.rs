@@ -0,0 +1,370 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module wraps CEF strings in syslog messages, in either the RFC 3164
+/// (BSD) or RFC 5424 format, with a PRI computed from the CEF severity.
+/// It also provides the RFC 6587 framings used on stream transports.
+use crate::{collect_record, record_to_cef, CefConversionError, CefResult, CefSeverity, ToCef};
+use time::{OffsetDateTime, UtcOffset};
+
+/// Syslog facilities as numbered by RFC 5424
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFacility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Ntp = 12,
+    Audit = 13,
+    Alert = 14,
+    Clock = 15,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// The syslog message format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFormat {
+    /// `<PRI>Mmm dd hh:mm:ss HOSTNAME APP-NAME[PROCID]: MSG`
+    Rfc3164,
+    /// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID - MSG`
+    Rfc5424,
+}
+
+/// How messages are delimited on stream transports (RFC 6587)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFraming {
+    /// Every message is followed by a newline (CEF escapes newlines, so this is safe)
+    NonTransparent,
+    /// Every message is preceded by its length in bytes and a space
+    OctetCounting,
+}
+
+impl SyslogFraming {
+    /// Frames a single message for a stream transport
+    pub fn frame(&self, message: &str) -> String {
+        match self {
+            SyslogFraming::NonTransparent => format!("{}\n", message),
+            SyslogFraming::OctetCounting => format!("{} {}", message.len(), message),
+        }
+    }
+}
+
+/// The syslog severity (0 is Emergency, 7 is Debug) for a CEF severity.
+///
+/// Very-High (9-10) is Critical, High (7-8) is Error, Medium (4-6) is Warning,
+/// Low (0-3) is Informational and Unknown is Notice.
+pub fn syslog_severity(severity: CefSeverity) -> u8 {
+    match severity.to_named() {
+        CefSeverity::VeryHigh => 2,
+        CefSeverity::High => 3,
+        CefSeverity::Medium => 4,
+        CefSeverity::Low => 6,
+        _ => 5,
+    }
+}
+
+/// Wraps items' CEF strings in syslog messages.
+///
+/// Unset header fields are written as the RFC 5424 nil value `-`. RFC 3164
+/// has no nil value, so an unset hostname is written as `localhost` and an
+/// unset app-name as `CEF`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogFormatter {
+    pub format: SyslogFormat,
+    pub facility: SyslogFacility,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub proc_id: Option<String>,
+    pub msg_id: Option<String>,
+}
+
+impl SyslogFormatter {
+    /// A formatter for the given format using the User facility
+    pub fn new(format: SyslogFormat) -> Self {
+        Self {
+            format,
+            facility: SyslogFacility::User,
+            hostname: None,
+            app_name: None,
+            proc_id: None,
+            msg_id: None,
+        }
+    }
+
+    pub fn with_facility(mut self, facility: SyslogFacility) -> Self {
+        self.facility = facility;
+        self
+    }
+
+    pub fn with_hostname(mut self, hostname: &str) -> Self {
+        self.hostname = Some(hostname.to_owned());
+        self
+    }
+
+    pub fn with_app_name(mut self, app_name: &str) -> Self {
+        self.app_name = Some(app_name.to_owned());
+        self
+    }
+
+    pub fn with_proc_id(mut self, proc_id: &str) -> Self {
+        self.proc_id = Some(proc_id.to_owned());
+        self
+    }
+
+    pub fn with_msg_id(mut self, msg_id: &str) -> Self {
+        self.msg_id = Some(msg_id.to_owned());
+        self
+    }
+
+    /// The PRI value for a message with the given CEF severity
+    pub fn priority(&self, severity: CefSeverity) -> u8 {
+        self.facility as u8 * 8 + syslog_severity(severity)
+    }
+
+    /// Wraps the item's CEF string in a syslog message stamped with the current time
+    pub fn format<T: ToCef + ?Sized>(&self, item: &T) -> CefResult {
+        self.format_at(item, OffsetDateTime::now_utc())
+    }
+
+    /// Wraps the item's CEF string in a syslog message with the given timestamp.
+    ///
+    /// RFC 3164 timestamps carry no offset, so they're written in the
+    /// timestamp's own offset.
+    pub fn format_at<T: ToCef + ?Sized>(&self, item: &T, timestamp: OffsetDateTime) -> CefResult {
+        // encoded once, so the PRI comes from the severity the CEF string carries
+        let record = collect_record(item, &item.cef_encode_options())?;
+        let pri = self.priority(record.severity.parse().unwrap_or(CefSeverity::Unknown));
+        let cef = record_to_cef(&record);
+
+        match self.format {
+            SyslogFormat::Rfc3164 => {
+                let hostname = header_field("HOSTNAME", &self.hostname, 255)?;
+                let app_name = header_field("APP-NAME", &self.app_name, 32)?;
+                let proc_id = header_field("PROCID", &self.proc_id, 128)?;
+                let tag = match (app_name, proc_id) {
+                    (Some(app_name), Some(proc_id)) => format!("{}[{}]", app_name, proc_id),
+                    (Some(app_name), None) => app_name.to_owned(),
+                    (None, Some(proc_id)) => format!("CEF[{}]", proc_id),
+                    (None, None) => "CEF".to_owned(),
+                };
+
+                Ok(format!(
+                    "<{}>{} {} {}: {}",
+                    pri,
+                    rfc3164_timestamp(timestamp),
+                    hostname.unwrap_or("localhost"),
+                    tag,
+                    cef
+                ))
+            }
+            SyslogFormat::Rfc5424 => Ok(format!(
+                "<{}>1 {} {} {} {} {} - {}",
+                pri,
+                rfc5424_timestamp(timestamp),
+                header_field("HOSTNAME", &self.hostname, 255)?.unwrap_or("-"),
+                header_field("APP-NAME", &self.app_name, 48)?.unwrap_or("-"),
+                header_field("PROCID", &self.proc_id, 128)?.unwrap_or("-"),
+                header_field("MSGID", &self.msg_id, 32)?.unwrap_or("-"),
+                cef
+            )),
+        }
+    }
+}
+
+// Header fields are printable ASCII without spaces, and of limited length
+fn header_field<'a>(
+    name: &str,
+    value: &'a Option<String>,
+    max_length: usize,
+) -> Result<Option<&'a str>, CefConversionError> {
+    let value = match value {
+        Some(value) => value.as_str(),
+        None => return Ok(None),
+    };
+
+    let reason = if value.is_empty() {
+        Some("must not be empty".to_owned())
+    } else if value.len() > max_length {
+        Some(format!("must be at most {} characters long", max_length))
+    } else if !value.bytes().all(|b| b.is_ascii_graphic()) {
+        Some("must be printable ASCII without spaces".to_owned())
+    } else {
+        None
+    };
+
+    match reason {
+        None => Ok(Some(value)),
+        Some(reason) => Err(CefConversionError::InvalidHeaderValue {
+            header: name.to_owned(),
+            value: value.to_owned(),
+            reason,
+        }),
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Mmm dd hh:mm:ss, with the day padded by a space
+fn rfc3164_timestamp(timestamp: OffsetDateTime) -> String {
+    format!(
+        "{} {:>2} {:02}:{:02}:{:02}",
+        MONTHS[timestamp.month() as usize - 1],
+        timestamp.day(),
+        timestamp.hour(),
+        timestamp.minute(),
+        timestamp.second()
+    )
+}
+
+// RFC 3339 with microseconds, i.e. 2003-10-11T22:14:15.000003Z
+pub(crate) fn rfc5424_timestamp(timestamp: OffsetDateTime) -> String {
+    let offset = timestamp.offset();
+    let offset = match offset == UtcOffset::UTC {
+        true => "Z".to_owned(),
+        false => format!(
+            "{}{:02}:{:02}",
+            if offset.is_negative() { '-' } else { '+' },
+            offset.whole_hours().abs(),
+            offset.minutes_past_hour().abs()
+        ),
+    };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}{}",
+        timestamp.year(),
+        timestamp.month() as u8,
+        timestamp.day(),
+        timestamp.hour(),
+        timestamp.minute(),
+        timestamp.second(),
+        timestamp.microsecond(),
+        offset
+    )
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_cef, CefRecord};
+
+    fn record(severity: &str) -> CefRecord {
+        let mut record =
+            parse_cef("CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|5|src=10.0.0.1").unwrap();
+        record.severity = severity.to_owned();
+        record
+    }
+
+    fn timestamp() -> OffsetDateTime {
+        // 2003-10-11T22:14:15.003Z
+        OffsetDateTime::from_unix_timestamp_nanos(1065910455003000000).unwrap()
+    }
+
+    #[test]
+    fn test_priority() {
+        let formatter = SyslogFormatter::new(SyslogFormat::Rfc5424);
+        assert_eq!(formatter.priority(CefSeverity::clamped(5)), 12);
+        assert_eq!(formatter.priority(CefSeverity::Unknown), 13);
+
+        let formatter = formatter.with_facility(SyslogFacility::Local4);
+        assert_eq!(formatter.priority(CefSeverity::clamped(0)), 166);
+        assert_eq!(formatter.priority(CefSeverity::clamped(8)), 163);
+        assert_eq!(formatter.priority(CefSeverity::VeryHigh), 162);
+    }
+
+    #[test]
+    fn test_rfc5424() {
+        let formatter = SyslogFormatter::new(SyslogFormat::Rfc5424)
+            .with_facility(SyslogFacility::Local4)
+            .with_hostname("mymachine.example.com")
+            .with_app_name("zerotect")
+            .with_proc_id("4242");
+
+        assert_eq!(
+            formatter.format_at(&record("10"), timestamp()).unwrap(),
+            "<162>1 2003-10-11T22:14:15.003000Z mymachine.example.com zerotect 4242 - - CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|10|src=10.0.0.1"
+        );
+
+        let offset = timestamp().to_offset(UtcOffset::from_hms(-7, -30, 0).unwrap());
+        let message = SyslogFormatter::new(SyslogFormat::Rfc5424)
+            .with_msg_id("FAULT")
+            .format_at(&record("Low"), offset)
+            .unwrap();
+        assert!(message.starts_with("<14>1 2003-10-11T14:44:15.003000-07:30 - - - FAULT - CEF:0|"));
+
+        // severities the spec doesn't allow are passed through, with an Unknown PRI
+        let message = formatter.format_at(&record("85"), timestamp()).unwrap();
+        assert!(message.starts_with("<165>1 "));
+        assert!(message.contains("|Kernel Fault|85|"));
+    }
+
+    #[test]
+    fn test_rfc3164() {
+        let formatter = SyslogFormatter::new(SyslogFormat::Rfc3164)
+            .with_facility(SyslogFacility::Auth)
+            .with_hostname("mymachine")
+            .with_app_name("zerotect")
+            .with_proc_id("4242");
+
+        assert_eq!(
+            formatter.format_at(&record("7"), timestamp()).unwrap(),
+            "<35>Oct 11 22:14:15 mymachine zerotect[4242]: CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|7|src=10.0.0.1"
+        );
+
+        let early = OffsetDateTime::from_unix_timestamp(1065225600).unwrap();
+        assert_eq!(
+            SyslogFormatter::new(SyslogFormat::Rfc3164)
+                .format_at(&record("2"), early)
+                .unwrap(),
+            "<14>Oct  4 00:00:00 localhost CEF: CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|2|src=10.0.0.1"
+        );
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let formatter = SyslogFormatter::new(SyslogFormat::Rfc5424).with_hostname("my machine");
+        assert_eq!(
+            formatter.format_at(&record("5"), timestamp()),
+            Err(CefConversionError::InvalidHeaderValue {
+                header: "HOSTNAME".to_owned(),
+                value: "my machine".to_owned(),
+                reason: "must be printable ASCII without spaces".to_owned(),
+            })
+        );
+
+        let formatter = SyslogFormatter::new(SyslogFormat::Rfc5424).with_msg_id(&"m".repeat(33));
+        assert!(formatter.format_at(&record("5"), timestamp()).is_err());
+    }
+
+    #[test]
+    fn test_framing() {
+        assert_eq!(
+            SyslogFraming::NonTransparent.frame("<14>1 - x"),
+            "<14>1 - x\n"
+        );
+        assert_eq!(
+            SyslogFraming::OctetCounting.frame("<14>1 - x"),
+            "9 <14>1 - x"
+        );
+        // octets, not characters
+        assert_eq!(SyslogFraming::OctetCounting.frame("é"), "2 é");
+    }
+}
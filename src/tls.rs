@@ -0,0 +1,403 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a sink that sends CEF-over-syslog through TLS (RFC 5425).
+/// It's only available with the `tls` feature.
+use crate::sink::{connect_tcp, resolve, CefSink, Connection, Reconnecting};
+use crate::syslog::{SyslogFormatter, SyslogFraming};
+use crate::CefExtensionsResult;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::convert::TryFrom;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// How a TlsSink authenticates the collector, and itself.
+///
+/// The collector's certificate must chain up to one of the CAs added with
+/// `with_ca_pem`, and nothing else: system roots are never trusted, which
+/// pins the collector to the given CAs. Its certificate must also be valid
+/// for the server name, which is sent as SNI.
+#[derive(Debug)]
+pub struct TlsConfig {
+    server_name: ServerName<'static>,
+    roots: RootCertStore,
+    client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+}
+
+impl Clone for TlsConfig {
+    fn clone(&self) -> Self {
+        Self {
+            server_name: self.server_name.clone(),
+            roots: self.roots.clone(),
+            client_auth: self
+                .client_auth
+                .as_ref()
+                .map(|(chain, key)| (chain.clone(), key.clone_key())),
+        }
+    }
+}
+
+impl TlsConfig {
+    /// A configuration expecting the collector to present a certificate for server_name
+    pub fn new(server_name: &str) -> io::Result<Self> {
+        let server_name = ServerName::try_from(server_name)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?
+            .to_owned();
+
+        Ok(Self {
+            server_name,
+            roots: RootCertStore::empty(),
+            client_auth: None,
+        })
+    }
+
+    /// Trusts the CA certificates in the PEM
+    pub fn with_ca_pem(mut self, pem: &[u8]) -> io::Result<Self> {
+        for cert in CertificateDer::pem_slice_iter(pem) {
+            self.roots
+                .add(cert.map_err(invalid_data)?)
+                .map_err(invalid_data)?;
+        }
+        Ok(self)
+    }
+
+    /// Presents the client certificate chain in cert_pem, for the private key in key_pem
+    pub fn with_client_cert_pem(mut self, cert_pem: &[u8], key_pem: &[u8]) -> io::Result<Self> {
+        let chain = CertificateDer::pem_slice_iter(cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_data)?;
+        let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(invalid_data)?;
+        self.client_auth = Some((chain, key));
+        Ok(self)
+    }
+
+    fn client_config(&self) -> io::Result<Arc<ClientConfig>> {
+        if self.roots.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "at least one CA certificate must be trusted",
+            ));
+        }
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_root_certificates(self.roots.clone());
+
+        let config = match &self.client_auth {
+            Some((chain, key)) => builder
+                .with_client_auth_cert(chain.clone(), key.clone_key())
+                .map_err(invalid_data)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// Sends framed messages over TLS, reconnecting when the connection is lost.
+/// RFC 5425 calls for octet-counting framing.
+#[derive(Debug)]
+pub struct TlsSink {
+    addr: SocketAddr,
+    server_name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+    connection: Reconnecting<TlsStream>,
+    formatter: SyslogFormatter,
+    framing: SyslogFraming,
+    timeout: Option<Duration>,
+}
+
+impl TlsSink {
+    /// Connects to the given address and completes the TLS handshake, blocking
+    /// for as long as the OS and the collector allow
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        tls: &TlsConfig,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+    ) -> io::Result<Self> {
+        Self::open(addr, tls, formatter, framing, None)
+    }
+
+    /// Connects to the given address and completes the TLS handshake, giving up
+    /// when either takes longer than the timeout. The timeout also applies to
+    /// writes and to reconnecting.
+    pub fn connect_timeout<A: ToSocketAddrs>(
+        addr: A,
+        tls: &TlsConfig,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        Self::open(addr, tls, formatter, framing, Some(timeout))
+    }
+
+    fn open<A: ToSocketAddrs>(
+        addr: A,
+        tls: &TlsConfig,
+        formatter: SyslogFormatter,
+        framing: SyslogFraming,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let addr = resolve(addr)?;
+        let config = tls.client_config()?;
+        let stream = connect_tls(addr, timeout, &config, &tls.server_name)?;
+        Ok(Self {
+            addr,
+            server_name: tls.server_name.clone(),
+            config,
+            connection: Reconnecting {
+                stream: Some(stream),
+            },
+            formatter,
+            framing,
+            timeout,
+        })
+    }
+}
+
+impl CefSink for TlsSink {
+    fn send_message(&mut self, message: &str) -> CefExtensionsResult {
+        let framed = self.framing.frame(message);
+        let (addr, timeout, config, server_name) =
+            (self.addr, self.timeout, &self.config, &self.server_name);
+        self.connection.write(framed.as_bytes(), || {
+            connect_tls(addr, timeout, config, server_name)
+        })?;
+        Ok(())
+    }
+
+    fn formatter(&self) -> &SyslogFormatter {
+        &self.formatter
+    }
+}
+
+impl Connection for TlsStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.sock.set_nonblocking(nonblocking)
+    }
+}
+
+// Handshakes eagerly, so certificate problems surface on connect
+fn connect_tls(
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+    config: &Arc<ClientConfig>,
+    server_name: &ServerName<'static>,
+) -> io::Result<TlsStream> {
+    let mut sock = connect_tcp(addr, timeout)?;
+    // the handshake waits on the collector's replies
+    sock.set_read_timeout(timeout)?;
+    let mut conn =
+        ClientConnection::new(config.clone(), server_name.clone()).map_err(invalid_data)?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut sock)?;
+    }
+    Ok(StreamOwned::new(conn, sock))
+}
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cef;
+    use crate::syslog::SyslogFormat;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{ServerConfig, ServerConnection};
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    struct Pki {
+        ca: String,
+        server_cert: String,
+        server_key: String,
+        client_cert: String,
+        client_key: String,
+    }
+
+    fn pki() -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["collector.test".to_owned()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let client = CertificateParams::new(vec!["sender.test".to_owned()])
+            .unwrap()
+            .signed_by(&client_key, &ca, &ca_key)
+            .unwrap();
+
+        Pki {
+            ca: ca.pem(),
+            server_cert: server.pem(),
+            server_key: server_key.serialize_pem(),
+            client_cert: client.pem(),
+            client_key: client_key.serialize_pem(),
+        }
+    }
+
+    // A collector requiring client certificates from the same CA,
+    // returning whatever it received on its first connection.
+    fn collector(pki: &Pki) -> (SocketAddr, JoinHandle<io::Result<String>>) {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(pki.ca.as_bytes()).unwrap())
+            .unwrap();
+        let provider = Arc::new(default_provider());
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .unwrap();
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(
+                CertificateDer::pem_slice_iter(pki.server_cert.as_bytes())
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap(),
+                PrivateKeyDer::from_pem_slice(pki.server_key.as_bytes()).unwrap(),
+            )
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (sock, _) = listener.accept()?;
+            let conn = ServerConnection::new(Arc::new(config)).map_err(invalid_data)?;
+            let mut stream = StreamOwned::new(conn, sock);
+            let mut received = String::new();
+            match stream.read_to_string(&mut received) {
+                // the sender may drop the connection without close_notify
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(received),
+                result => result.map(|_| received),
+            }
+        });
+
+        (addr, server)
+    }
+
+    fn formatter() -> SyslogFormatter {
+        SyslogFormatter::new(SyslogFormat::Rfc5424).with_hostname("sender.test")
+    }
+
+    #[test]
+    fn test_tls_with_client_cert() {
+        let pki = pki();
+        let (addr, server) = collector(&pki);
+
+        let tls = TlsConfig::new("collector.test")
+            .unwrap()
+            .with_ca_pem(pki.ca.as_bytes())
+            .unwrap()
+            .with_client_cert_pem(pki.client_cert.as_bytes(), pki.client_key.as_bytes())
+            .unwrap();
+        let mut sink =
+            TlsSink::connect(addr, &tls, formatter(), SyslogFraming::OctetCounting).unwrap();
+
+        let record =
+            parse_cef("CEF:0|polyverse|zerotect|V1|Fault|Kernel Fault|5|src=10.0.0.1").unwrap();
+        sink.send(&record).unwrap();
+        sink.send_message("second").unwrap();
+        drop(sink);
+
+        let received = server.join().unwrap().unwrap();
+        let (length, rest) = received.split_once(' ').unwrap();
+        let (first, second) = rest.split_at(length.parse().unwrap());
+        assert!(first.starts_with("<12>1 "));
+        assert!(first.ends_with("|Kernel Fault|5|src=10.0.0.1"));
+        assert_eq!(second, "6 second");
+    }
+
+    #[test]
+    fn test_tls_rejects_wrong_server_name() {
+        let pki = pki();
+        let (addr, server) = collector(&pki);
+
+        let tls = TlsConfig::new("elsewhere.test")
+            .unwrap()
+            .with_ca_pem(pki.ca.as_bytes())
+            .unwrap()
+            .with_client_cert_pem(pki.client_cert.as_bytes(), pki.client_key.as_bytes())
+            .unwrap();
+        let result = TlsSink::connect(addr, &tls, formatter(), SyslogFraming::OctetCounting);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_tls_rejects_unpinned_ca() {
+        let pki = pki();
+        let other = self::pki();
+        let (addr, server) = collector(&pki);
+
+        let tls = TlsConfig::new("collector.test")
+            .unwrap()
+            .with_ca_pem(other.ca.as_bytes())
+            .unwrap();
+        let result = TlsSink::connect(addr, &tls, formatter(), SyslogFraming::OctetCounting);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_tls_handshake_timeout() {
+        let pki = pki();
+        // accepts connections but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let tls = TlsConfig::new("collector.test")
+            .unwrap()
+            .with_ca_pem(pki.ca.as_bytes())
+            .unwrap();
+        let result = TlsSink::connect_timeout(
+            addr,
+            &tls,
+            formatter(),
+            SyslogFraming::OctetCounting,
+            Duration::from_millis(100),
+        );
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        ));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_tls_config_errors() {
+        assert!(TlsConfig::new("not a name").is_err());
+
+        let tls = TlsConfig::new("collector.test").unwrap();
+        assert!(TlsSink::connect(
+            "127.0.0.1:1",
+            &tls,
+            formatter(),
+            SyslogFraming::OctetCounting
+        )
+        .is_err());
+        assert!(tls.clone().with_client_cert_pem(b"", b"").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+/// Copyright 2020 Polyverse Corporation
+///
+/// This module provides a `tracing_subscriber::Layer` that emits every event as a CEF event.
+/// It's only available with the `tracing` feature.
+use crate::is_valid_extension_key;
+use crate::log_event::{CefLogEvent, CefLogLevel};
+use crate::sink::CefSink;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+impl From<&Level> for CefLogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => CefLogLevel::Error,
+            Level::WARN => CefLogLevel::Warn,
+            Level::INFO => CefLogLevel::Info,
+            Level::DEBUG => CefLogLevel::Debug,
+            _ => CefLogLevel::Trace,
+        }
+    }
+}
+
+/// The device headers stamped on every event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CefLayerConfig {
+    pub device_vendor: String,
+    pub device_product: String,
+    pub device_version: String,
+}
+
+impl CefLayerConfig {
+    pub fn new(device_vendor: &str, device_product: &str, device_version: &str) -> Self {
+        Self {
+            device_vendor: device_vendor.to_owned(),
+            device_product: device_product.to_owned(),
+            device_version: device_version.to_owned(),
+        }
+    }
+}
+
+/// Field values of a span, kept in the span's extensions
+struct SpanFields(Vec<(&'static str, String)>);
+
+struct FieldVisitor<'f>(&'f mut Vec<(&'static str, String)>);
+
+impl FieldVisitor<'_> {
+    fn push(&mut self, field: &Field, value: String) {
+        let key = match field.name() {
+            "message" => "msg",
+            name => name,
+        };
+
+        if is_valid_extension_key(key) {
+            self.0.push((key, value));
+        }
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.push(field, format!("{:?}", value));
+    }
+}
+
+/// A `Layer` that sends every event to a `CefSink`.
+///
+/// The target is the DeviceEventClassID, the event's message is the Name
+/// (the target when it has none) and the level is the Severity. Fields are extensions under their own names,
+/// except `message` which becomes `msg`. Fields whose names can't be extension
+/// keys are skipped.
+///
+/// Events inherit the fields of the spans they're in, outermost span first, the
+/// way `#[cef_ext_gobble]` inherits the extensions of nested items: when a key
+/// shows up more than once, the innermost value (or the event's own) wins.
+///
+/// Layers can't report errors, so events that fail to convert or send are dropped.
+pub struct CefLayer<K> {
+    config: CefLayerConfig,
+    sink: Mutex<K>,
+}
+
+impl<K: CefSink + Send + 'static> CefLayer<K> {
+    pub fn new(config: CefLayerConfig, sink: K) -> Self {
+        Self {
+            config,
+            sink: Mutex::new(sink),
+        }
+    }
+}
+
+impl<S, K> Layer<S> for CefLayer<K>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    K: CefSink + Send + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = vec![];
+        attrs.record(&mut FieldVisitor(&mut fields));
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(&mut FieldVisitor(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = vec![];
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                    fields.extend(span_fields.0.iter().cloned());
+                }
+            }
+        }
+        event.record(&mut FieldVisitor(&mut fields));
+
+        let metadata = event.metadata();
+        // the event's own message, recorded after the spans' fields
+        let name = match fields.iter().rev().find(|(key, _)| *key == "msg") {
+            Some((_, message)) => Cow::Owned(message.clone()),
+            None => Cow::Borrowed(metadata.target()),
+        };
+        let event = CefLogEvent {
+            device_vendor: &self.config.device_vendor,
+            device_product: &self.config.device_product,
+            device_version: &self.config.device_version,
+            device_event_class_id: metadata.target(),
+            name,
+            level: metadata.level().into(),
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (Cow::Borrowed(key), value))
+                .collect(),
+        };
+
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = sink.send(&event);
+        }
+    }
+}
+
+/********************************************************************************************** */
+/* Tests! Tests! Tests! */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log_event::test::MemorySink;
+    use crate::syslog::SyslogFormat;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    // the CEF part of every message sent
+    fn capture<F: FnOnce()>(f: F) -> Vec<String> {
+        let (sink, messages) = MemorySink::new(SyslogFormat::Rfc5424);
+        let layer = CefLayer::new(CefLayerConfig::new("polyverse", "zerotect", "V1"), sink);
+
+        tracing::subscriber::with_default(Registry::default().with(layer), f);
+
+        let messages = messages.lock().unwrap();
+        messages
+            .iter()
+            .map(|message| message[message.find("CEF:").unwrap()..].to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let messages = capture(|| {
+            tracing::warn!(target: "auth", src = "10.0.0.1", suser = "alice", cnt = 3, "login failed");
+            tracing::error!(target: "kernel", { "bad key" = 1, dst = "10.0.0.2" }, "a=b");
+            tracing::info!(target: "kernel", cnt = 1);
+        });
+
+        // the message is the Name, or the target without one
+        assert_eq!(
+            messages,
+            vec![
+                "CEF:0|polyverse|zerotect|V1|auth|login failed|5|cnt=3 msg=login failed src=10.0.0.1 suser=alice",
+                "CEF:0|polyverse|zerotect|V1|kernel|a=b|8|dst=10.0.0.2 msg=a\\=b",
+                "CEF:0|polyverse|zerotect|V1|kernel|kernel|3|cnt=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span_fields_are_inherited() {
+        let messages = capture(|| {
+            let outer = tracing::info_span!("request", suser = "alice", dhost = "outer");
+            let _outer = outer.enter();
+            let inner =
+                tracing::info_span!("handler", dhost = "inner", cnt = tracing::field::Empty);
+            let _inner = inner.enter();
+            inner.record("cnt", 2);
+
+            tracing::info!(target: "app", "handled");
+            tracing::info!(target: "app", suser = "bob", "impersonated");
+        });
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("CEF:0|polyverse|zerotect|V1|app|"));
+        assert!(messages[0].ends_with("|3|cnt=2 dhost=inner msg=handled suser=alice"));
+        assert!(messages[1].ends_with("|3|cnt=2 dhost=inner msg=impersonated suser=bob"));
+    }
+}